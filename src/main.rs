@@ -1,17 +1,46 @@
+// `std::simd`, used by the word-wise operations of `utils::bitmap`.
 #![feature(portable_simd)]
 
 pub mod storage {
+    pub mod chunk;
     pub mod column;
+    pub mod config;
+    pub mod csv;
+    pub mod export;
+    pub mod format;
+    pub mod ipc;
+    pub mod json;
+    pub mod parquet;
+    pub mod stats;
     pub mod table;
+    pub mod timestamp;
+    pub mod upgrade;
+    pub mod vector;
+    pub mod verify;
+    pub mod wal;
+    pub mod writer;
+    pub mod zonemap;
 }
 pub mod utils {
+    pub mod bitmap;
+    pub mod crc32c;
     pub mod simd;
 }
+pub mod query {
+    pub mod cost;
+    pub mod exec;
+    pub mod expr;
+    pub mod lexer;
+    pub mod plan;
+    pub mod predicate;
+    pub mod sql;
+}
 
 use std::{collections::HashMap, fs, path::Path};
 
-use storage::{column:: ColumnStore, table::TableSchema};
-use clap::{command, Parser, Subcommand};
+use storage::{column:: ColumnStore, config::{DatabaseConfig, Durability}, csv::CsvOptions, export::ExportFormat, format::FORMAT_VERSION, table::TableSchema, upgrade::Upgrade, wal};
+use clap::{Parser, Subcommand};
+use query::{expr::Expr, plan::plan_query, predicate::Predicate};
 use utils::simd::{LogicalOp, SimdOp};
 
 
@@ -35,6 +64,24 @@ enum Commands {
         values: Vec<String>,
     },
 
+    /// Inserts several rows at once, each given as comma separated values.
+    InsertRows {
+        table_name: String,
+        rows: Vec<String>,
+    },
+
+    /// Loads a CSV file, matching header names to columns unless --no-header is given.
+    ImportCsv {
+        table_name: String,
+        file: String,
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+        #[arg(long, default_value_t = '"')]
+        quote: char,
+        #[arg(long)]
+        no_header: bool,
+    },
+
     Scan {
         table_name: String,
         column_name: String
@@ -87,6 +134,101 @@ enum Commands {
         logic: LogicalOp,
     },
 
+    FilterSimdBetween {
+        table_name: String,
+        column_name: String,
+        low: i32,
+        high: i32,
+    },
+
+    FilterSimdIn {
+        table_name: String,
+        column_name: String,
+        values: Vec<i32>,
+    },
+
+    FilterSimdColumns {
+        table_name: String,
+        left_column: String,
+        op: SimdOp,
+        right_column: String,
+    },
+
+    FilterSimdExpr {
+        table_name: String,
+        predicate: Vec<String>,
+    },
+
+    Select {
+        table_name: String,
+        projection: String,
+        #[arg(long = "where")]
+        predicate: Option<String>,
+    },
+
+    ImportJson {
+        table_name: String,
+        file: String,
+        /// Records read to infer the schema when the table does not exist yet.
+        #[arg(long, default_value_t = 1000)]
+        sample: usize,
+    },
+
+    ImportArrow {
+        table_name: String,
+        file: String,
+    },
+
+    Export {
+        table_name: String,
+        file: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        #[arg(long = "where")]
+        predicate: Option<String>,
+    },
+
+    Analyze {
+        table_name: String,
+    },
+
+    /// Checks that the files of a table, or of every table, agree with each other.
+    Verify {
+        table_name: Option<String>,
+        /// Rebuild the index files found not to match their data.
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Rewrites the files of a table, or of every table, written before the current file format.
+    Upgrade {
+        table_name: Option<String>,
+    },
+
+    /// Rebuilds the index files of a table, or of one column, from the data files.
+    Reindex {
+        table_name: String,
+        column_name: Option<String>,
+    },
+
+    /// Shows the settings of the database, or changes the ones given.
+    Config {
+        /// When writes are fsynced: trades ingest speed against what a crash of the machine can lose.
+        #[arg(long, value_enum)]
+        durability: Option<Durability>,
+    },
+
+    Query {
+        sql: Vec<String>,
+    },
+
+    Explain {
+        sql: Vec<String>,
+        /// Run the query and report what each operator actually did.
+        #[arg(long)]
+        analyze: bool,
+    },
+
     FilterSimdEqAvx {
         table_name: String,
         column_name: String,
@@ -126,6 +268,15 @@ enum Commands {
     ListTables,
 }
 
+impl Commands {
+    /// Whether the command reads or writes the files of existing tables, and so must leave out
+    /// those in a format this build cannot read. `verify` and `upgrade` are how such a table gets
+    /// fixed, so they see every table.
+    fn uses_table_files(&self) -> bool {
+        !matches!(self, Commands::CreateTable { .. } | Commands::Verify { .. } | Commands::Upgrade { .. } | Commands::Config { .. } | Commands::ListTables)
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
     let base_path = "data";
@@ -145,27 +296,106 @@ fn main() {
         println!("AVX2 is NOT supported.");
     }
     
-    let tables: HashMap<String, TableSchema> = TableSchema::load_metadata(base_path);
+    match wal::recover(base_path) {
+        Ok(tables) => {
+            for table_name in tables {
+                println!("Rolled back an unfinished write to '{}'", table_name);
+            }
+        }
+        Err(e) => eprintln!("Failed to recover unfinished writes: {}", e),
+    }
+
+    let mut tables: HashMap<String, TableSchema> = TableSchema::load_metadata(base_path);
+    let store = ColumnStore::new(base_path);
+    if cli.command.uses_table_files() {
+        tables.retain(|table_name, schema| match store.check_format(schema) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Skipping table '{}': {}", table_name, e);
+                false
+            }
+        });
+    }
 
     match &cli.command {
         Commands::CreateTable { table_name, values } => {
             let schema = TableSchema::new( table_name.clone(), values.clone());
-            schema.save(base_path);
-            println!("Table '{}' created!", table_name);
+            match schema.save(base_path) {
+                Ok(()) => println!("Table '{}' created!", table_name),
+                Err(e) => println!("Failed to create table '{}': {}", table_name, e),
+            }
         }
         Commands::Insert { table_name, values } => {
             if let Some(schema) = tables.get(table_name) {
                 let store: ColumnStore = ColumnStore::new(base_path);
-                store.insert_row(schema, values.iter().map(String::as_str).collect());
-                println!("Inserted into '{}': {:?}", table_name, values);
+                match store.insert_row(schema, values.iter().map(String::as_str).collect()) {
+                    Ok(()) => println!("Inserted into '{}': {:?}", table_name, values),
+                    Err(e) => println!("Insert failed: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::InsertRows { table_name, rows } => {
+            if let Some(schema) = tables.get(table_name) {
+                let store = ColumnStore::new(base_path);
+                let rows: Vec<Vec<&str>> = rows.iter().map(|row| row.split(',').collect()).collect();
+                match store.insert_rows(schema, &rows) {
+                    Ok(count) => println!("Inserted {} rows into '{}'", count, table_name),
+                    Err(e) => println!("Insert failed:\n{}", e),
+                }
             } else {
                 println!("Table '{}' not found.", table_name);
             }
         }
+        Commands::ImportCsv { table_name, file, delimiter, quote, no_header } => {
+            if let Some(schema) = tables.get(table_name) {
+                let store = ColumnStore::new(base_path);
+                let options = CsvOptions { delimiter: *delimiter, quote: *quote, header: !no_header };
+                match store.import_csv(schema, file, options) {
+                    Ok(count) => println!("Imported {} rows into '{}'", count, table_name),
+                    Err(e) => println!("Import failed:\n{}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::ImportJson { table_name, file, sample } => {
+            let store = ColumnStore::new(base_path);
+            let imported = if let Some(schema) = tables.get(table_name) {
+                store.import_json(schema, file, false)
+            } else {
+                store.infer_json_schema(table_name, file, *sample).and_then(|schema| {
+                    let count = store.import_json(&schema, file, true)?;
+                    println!("Created table '{}' [{}]", table_name, schema.describe_columns());
+                    Ok(count)
+                })
+            };
+            match imported {
+                Ok(count) => println!("Imported {} rows into '{}'", count, table_name),
+                Err(e) => println!("Import failed:\n{}", e),
+            }
+        }
+        Commands::ImportArrow { table_name, file } => {
+            if tables.contains_key(table_name) {
+                println!("Table '{}' already exists.", table_name);
+            } else {
+                let store = ColumnStore::new(base_path);
+                match store.import_arrow(table_name, file) {
+                    Ok((schema, count)) => {
+                        println!("Created table '{}' [{}]", table_name, schema.describe_columns());
+                        println!("Imported {} rows into '{}'", count, table_name);
+                    }
+                    Err(e) => println!("Import failed:\n{}", e),
+                }
+            }
+        }
         Commands::Scan { table_name, column_name } => {
             if let Some(schema) = tables.get(table_name) {
                 let store = ColumnStore::new(base_path);
-                store.scan_column(schema, column_name);
+                if let Err(e) = store.scan_column(schema, column_name) {
+                    println!("Scan failed: {}", e);
+                }
             } else {
                 println!("Table '{}' not found.", table_name);
             }
@@ -219,22 +449,226 @@ fn main() {
             }
         }
         Commands::FilterSimdLogical { table_name, column1, op1, value1, column2, op2, value2, logic } => {
+            if let Some(schema) = tables.get(table_name) {
+                let predicate = Predicate::from_pair(column1, *op1, *value1, column2, *op2, *value2, *logic);
+                match predicate.validate(schema) {
+                    Ok(_) => ColumnStore::new(base_path).filter_predicate_simd(schema, &predicate),
+                    Err(e) => println!("Invalid predicate: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::FilterSimdBetween { table_name, column_name, low, high } => {
+            if let Some(schema) = tables.get(table_name) {
+                let predicate = Predicate::Between { column: column_name.clone(), low: *low, high: *high };
+                match predicate.validate(schema) {
+                    Ok(_) => ColumnStore::new(base_path).filter_predicate_simd(schema, &predicate),
+                    Err(e) => println!("Invalid predicate: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::FilterSimdIn { table_name, column_name, values } => {
+            if let Some(schema) = tables.get(table_name) {
+                let predicate = Predicate::In { column: column_name.clone(), values: values.clone() };
+                match predicate.validate(schema) {
+                    Ok(_) => ColumnStore::new(base_path).filter_predicate_simd(schema, &predicate),
+                    Err(e) => println!("Invalid predicate: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::FilterSimdColumns { table_name, left_column, op, right_column } => {
+            if let Some(schema) = tables.get(table_name) {
+                let predicate = Predicate::CompareColumns { left: left_column.clone(), op: *op, right: right_column.clone() };
+                match predicate.validate(schema) {
+                    Ok(_) => ColumnStore::new(base_path).filter_predicate_simd(schema, &predicate),
+                    Err(e) => println!("Invalid predicate: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::FilterSimdExpr { table_name, predicate } => {
+            if let Some(schema) = tables.get(table_name) {
+                match Predicate::parse(&predicate.join(" "), schema) {
+                    Ok(predicate) => {
+                        let store = ColumnStore::new(base_path);
+                        store.filter_predicate_simd(schema, &predicate);
+                    }
+                    Err(e) => println!("Invalid predicate: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::Select { table_name, projection, predicate } => {
+            if let Some(schema) = tables.get(table_name) {
+                let projection = if projection.trim() == "*" {
+                    Ok(schema.columns.iter().map(|c| (Expr::Column(c.name.clone()), c.name.clone())).collect())
+                } else {
+                    Expr::parse_projection(projection)
+                };
+                let predicate = predicate.as_deref().map(|p| Predicate::parse(p, schema)).transpose();
+
+                match projection.and_then(|projection| predicate.map(|predicate| (projection, predicate))) {
+                    Ok((projection, predicate)) => {
+                        let store = ColumnStore::new(base_path);
+                        if let Err(e) = store.select(schema, &projection, predicate.as_ref()) {
+                            println!("Select failed: {}", e);
+                        }
+                    }
+                    Err(e) => println!("Invalid query: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::Export { table_name, file, format, predicate } => {
+            if let Some(schema) = tables.get(table_name) {
+                match predicate.as_deref().map(|p| Predicate::parse(p, schema)).transpose() {
+                    Ok(predicate) => {
+                        let store = ColumnStore::new(base_path);
+                        match store.export(schema, file, *format, predicate.as_ref()) {
+                            Ok(count) => println!("Exported {} rows from '{}' to '{}'", count, table_name, file),
+                            Err(e) => println!("Export failed: {}", e),
+                        }
+                    }
+                    Err(e) => println!("Invalid query: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::Analyze { table_name } => {
             if let Some(schema) = tables.get(table_name) {
                 let store = ColumnStore::new(base_path);
-                store.filter_columns_logical_simd(
-                    schema,
-                    &column1,
-                    *op1,
-                    *value1,
-                    &column2,
-                    *op2,
-                    *value2,
-                    *logic,
-                );
+                if let Err(e) = store.analyze(schema) {
+                    println!("Analyze failed: {}", e);
+                }
             } else {
                 println!("Table '{}' not found.", table_name);
             }
         }
+        Commands::Verify { table_name, repair } => {
+            let store = ColumnStore::new(base_path);
+            let mut names: Vec<&String> = match table_name {
+                Some(name) => vec![name],
+                None => tables.keys().collect(),
+            };
+            names.sort();
+            for name in names {
+                let Some(schema) = tables.get(name) else {
+                    println!("Table '{}' not found.", name);
+                    continue;
+                };
+                let report = store.verify(schema);
+                println!("{}", report);
+                if !*repair || report.problems.is_empty() {
+                    continue;
+                }
+                if let Err(e) = store.check_format(schema) {
+                    println!("Not repairing '{}': {}", name, e);
+                    continue;
+                }
+                for column in report.damaged_indexes() {
+                    match store.reindex(schema, Some(column)) {
+                        Ok(_) => println!("Rebuilt the index of '{}.{}'", name, column),
+                        Err(e) => println!("Reindex failed: {}", e),
+                    }
+                }
+                println!("{}", store.verify(schema));
+            }
+        }
+        Commands::Upgrade { table_name } => {
+            let store = ColumnStore::new(base_path);
+            let mut names: Vec<&String> = match table_name {
+                Some(name) => vec![name],
+                None => tables.keys().collect(),
+            };
+            names.sort();
+            for name in names {
+                let Some(schema) = tables.get(name) else {
+                    println!("Table '{}' not found.", name);
+                    continue;
+                };
+                match store.upgrade(schema) {
+                    Ok(Upgrade::UpToDate) => println!("Table '{}': already up to date", name),
+                    Ok(Upgrade::Upgraded { files, row_count }) => {
+                        println!("Table '{}': upgraded {} files to format version {}, {} rows verified", name, files, FORMAT_VERSION, row_count)
+                    }
+                    Err(e) => println!("Table '{}': upgrade failed: {}", name, e),
+                }
+            }
+        }
+        Commands::Reindex { table_name, column_name } => {
+            if let Some(schema) = tables.get(table_name) {
+                let store = ColumnStore::new(base_path);
+                match store.reindex(schema, column_name.as_deref()) {
+                    Ok(columns) => {
+                        for (column, entries) in columns {
+                            println!("Rebuilt the index of '{}.{}': {} entries", table_name, column, entries);
+                        }
+                    }
+                    Err(e) => println!("Reindex failed: {}", e),
+                }
+            } else {
+                println!("Table '{}' not found.", table_name);
+            }
+        }
+        Commands::Config { durability } => {
+            let mut config = match DatabaseConfig::load(base_path) {
+                Ok(config) => config,
+                Err(e) => {
+                    println!("{}; saving replaces it with the defaults", e);
+                    DatabaseConfig::default()
+                }
+            };
+            match durability {
+                Some(durability) => {
+                    config.durability = *durability;
+                    match config.save(base_path) {
+                        Ok(()) => println!("durability set to {}", durability),
+                        Err(e) => println!("Failed to save the config: {}", e),
+                    }
+                }
+                None => println!("durability: {}", config.durability),
+            }
+        }
+        Commands::Query { sql } => {
+            let store = ColumnStore::new(base_path);
+            match plan_query(&sql.join(" "), &tables, &store) {
+                Ok((_, physical)) => match physical.execute(&store) {
+                    Ok(relation) => relation.print(),
+                    Err(e) => println!("Query failed: {}", e),
+                },
+                Err(e) => println!("Invalid query: {}", e),
+            }
+        }
+        Commands::Explain { sql, analyze } => {
+            let store = ColumnStore::new(base_path);
+            match plan_query(&sql.join(" "), &tables, &store) {
+                Ok((_, physical)) if *analyze => match physical.analyze(&store) {
+                    Ok((_, metrics)) => {
+                        println!("Physical plan (analyzed):\n{}", metrics.report(&physical));
+                        let (data_bytes, index_bytes) = metrics.total_bytes();
+                        let table_bytes: u64 = physical.tables().iter().map(|t| store.data_bytes(t)).sum();
+                        let share = if table_bytes == 0 { 0.0 } else { data_bytes as f64 * 100.0 / table_bytes as f64 };
+                        println!("Execution time: {:.3}ms", metrics.elapsed.as_secs_f64() * 1000.0);
+                        println!("Read {} of {} .data bytes ({:.1}%) and {} .idx bytes", data_bytes, table_bytes, share, index_bytes);
+                    }
+                    Err(e) => println!("Query failed: {}", e),
+                },
+                Ok((logical, physical)) => {
+                    println!("Logical plan:\n{}", logical);
+                    println!("Physical plan:\n{}", physical);
+                }
+                Err(e) => println!("Invalid query: {}", e),
+            }
+        }
         Commands::FilterSimdEqAvx { table_name, column_name, threshold_value } => {
             if let Some(schema) = tables.get(table_name) {
                 let store = ColumnStore::new(base_path);
@@ -0,0 +1,193 @@
+use super::predicate::Predicate;
+use crate::storage::{chunk::{fixed_width, CHUNK_ROWS}, stats::TableStats, table::TableSchema};
+use crate::utils::simd::{SimdBackend, IN_LIST_BROADCAST_LIMIT};
+
+/// Fixed work per chunk a kernel touches, in the same units as reading one byte.
+/// The 256 bit kernels pay more to get going, so tiny chunks are cheaper on SSE.
+const SSE_CHUNK_SETUP: f64 = 8.0;
+const AVX2_CHUNK_SETUP: f64 = 32.0;
+
+/// Per-row cost of a computed expression leaf on top of reading its columns.
+const EXPR_ROW_COST: f64 = 8.0;
+
+/// Assumed width of a string value, which has no fixed size on disk.
+const STRING_BYTES: f64 = 16.0;
+
+/// Parsing a JSON `.idx` line costs this many times reading the same bytes of `.data`.
+const INDEX_PARSE_FACTOR: f64 = 2.0;
+
+/// Share of the rows a filter rejects that are expected to sit in chunks the zone map can skip.
+/// Data rarely arrives sorted, so only part of the selectivity turns into whole pruned chunks.
+const ZONE_MAP_CLUSTERING: f64 = 0.5;
+
+/// Estimates what filters cost and keep, from the table's statistics when it was analyzed.
+pub struct CostModel<'a> {
+    table: &'a TableSchema,
+    stats: Option<&'a TableStats>,
+    available: SimdBackend,
+}
+
+impl<'a> CostModel<'a> {
+    pub fn new(table: &'a TableSchema, stats: Option<&'a TableStats>) -> Self {
+        Self { table, stats, available: SimdBackend::detect() }
+    }
+
+    pub fn selectivity(&self, predicate: &Predicate) -> f64 {
+        predicate.estimated_selectivity(self.stats)
+    }
+
+    /// Estimated work per candidate row to evaluate `predicate`, counting the bytes it reads
+    /// plus its compute; children of `And`/`Or` are charged as if every row reached them.
+    pub fn row_cost(&self, predicate: &Predicate) -> f64 {
+        let simd_row = 1.0 / self.lanes() as f64;
+        match predicate {
+            Predicate::Compare { .. } | Predicate::Between { .. } => 4.0 + simd_row,
+            Predicate::CompareColumns { .. } => 8.0 + simd_row,
+            Predicate::In { values, .. } if values.len() > IN_LIST_BROADCAST_LIMIT => 4.0 + (values.len() as f64).log2(),
+            Predicate::In { values, .. } => 4.0 + simd_row * values.len() as f64,
+            Predicate::Expr(expr) => expr.columns().iter().map(|c| self.column_bytes(c)).sum::<f64>() + EXPR_ROW_COST,
+            Predicate::And(children) | Predicate::Or(children) => children.iter().map(|c| self.row_cost(c)).sum(),
+            Predicate::Not(child) => self.row_cost(child),
+        }
+    }
+
+    /// Reorders `predicate` so the cheapest way to shrink the candidate set runs first.
+    ///
+    /// Conjuncts are sorted by ascending `(selectivity - 1) / cost`, the classic rank that
+    /// puts cheap, selective filters first; disjuncts by descending `selectivity / cost`,
+    /// so the rows they match drop out of the set later disjuncts evaluate.
+    ///
+    /// A child that can fail, such as `a / b > 1`, stays behind every child written before it,
+    /// which may be what guards it (`b != 0 and a / b > 1`); only the children between two
+    /// such barriers are sorted.
+    pub fn order(&self, predicate: Predicate) -> Predicate {
+        let rank = |p: &Predicate, conjunct: bool| {
+            let (selectivity, cost) = (self.selectivity(p), self.row_cost(p).max(f64::EPSILON));
+            if conjunct { (selectivity - 1.0) / cost } else { -selectivity / cost }
+        };
+        let sort = |children: Vec<Predicate>, conjunct: bool| {
+            let by_rank = |a: &Predicate, b: &Predicate| rank(a, conjunct).total_cmp(&rank(b, conjunct));
+            let mut sorted: Vec<Predicate> = Vec::with_capacity(children.len());
+            let mut segment_start = 0;
+            for child in children.into_iter().map(|c| self.order(c)) {
+                if child.may_fail() {
+                    sorted[segment_start..].sort_by(by_rank);
+                    sorted.push(child);
+                    segment_start = sorted.len();
+                } else {
+                    sorted.push(child);
+                }
+            }
+            sorted[segment_start..].sort_by(by_rank);
+            sorted
+        };
+        match predicate {
+            Predicate::And(children) => Predicate::And(sort(children, true)),
+            Predicate::Or(children) => Predicate::Or(sort(children, false)),
+            Predicate::Not(child) => Predicate::Not(Box::new(self.order(*child))),
+            leaf => leaf,
+        }
+    }
+
+    /// SSE or AVX2 for the kernels over a column of `row_count` rows: whichever is cheaper
+    /// per chunk, which is AVX2 unless chunks are tiny or the CPU lacks it.
+    pub fn choose_backend(&self, row_count: usize) -> SimdBackend {
+        let chunk_rows = row_count.min(CHUNK_ROWS) as f64;
+        let sse = SSE_CHUNK_SETUP + chunk_rows / 4.0;
+        let avx2 = AVX2_CHUNK_SETUP + chunk_rows / 8.0;
+        if self.available == SimdBackend::Avx2 && avx2 < sse { SimdBackend::Avx2 } else { SimdBackend::Sse }
+    }
+
+    /// Whether reading a column's `.idx` to prune chunks is expected to save more than it costs.
+    ///
+    /// `scan_bytes` is what the scan reads per row without pruning and `selectivity` the share
+    /// of rows the filters on this column keep.
+    pub fn use_zone_map(&self, index_bytes: u64, row_count: usize, scan_bytes: f64, selectivity: f64) -> bool {
+        if index_bytes == 0 || row_count == 0 {
+            return false;
+        }
+        let saved = row_count as f64 * scan_bytes * (1.0 - selectivity) * ZONE_MAP_CLUSTERING;
+        saved > index_bytes as f64 * INDEX_PARSE_FACTOR
+    }
+
+    /// Bytes per row read for `column`.
+    pub fn column_bytes(&self, column: &str) -> f64 {
+        match self.table.columns.iter().find(|c| c.name == column) {
+            Some(c) => fixed_width(&c.data_type).map_or(STRING_BYTES, |width| width as f64),
+            None => STRING_BYTES,
+        }
+    }
+
+    fn lanes(&self) -> usize {
+        match self.available {
+            SimdBackend::Sse => 4,
+            SimdBackend::Avx2 => 8,
+        }
+    }
+}
+
+/// Whether a hash join should build its table from the left input: always the smaller side,
+/// since the build side is held in memory and the probe side only streams past it.
+pub fn build_left(left_rows: f64, right_rows: f64) -> bool {
+    left_rows < right_rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::stats::ColumnStats;
+
+    fn table() -> TableSchema {
+        TableSchema::new("t".to_string(), ["id:int", "age:int", "name:string"].map(String::from).to_vec())
+    }
+
+    fn ordered(model: &CostModel, input: &str) -> Predicate {
+        model.order(Predicate::parse(input, &table()).unwrap())
+    }
+
+    #[test]
+    fn conjuncts_put_cheap_selective_filters_first() {
+        let table = table();
+        let model = CostModel::new(&table, None);
+        assert_eq!(ordered(&model, "id != 3 and age = 5"), Predicate::parse("age = 5 and id != 3", &table).unwrap());
+        // A computed leaf reads its columns row by row, so an equally selective kernel runs first.
+        assert_eq!(ordered(&model, "age + 1 > 3 and id > 3"), Predicate::parse("id > 3 and age + 1 > 3", &table).unwrap());
+        // Disjuncts run the one that matches the most rows first.
+        assert_eq!(ordered(&model, "age = 5 or id != 3"), Predicate::parse("id != 3 or age = 5", &table).unwrap());
+    }
+
+    #[test]
+    fn statistics_override_the_fixed_guesses() {
+        let table = table();
+        let stats = TableStats {
+            table_name: "t".to_string(),
+            row_count: 1000,
+            columns: vec![ColumnStats::from_ints("id", (0..1000).collect()), ColumnStats::from_ints("age", vec![5; 1000])],
+        };
+        // Every row has `age = 5`, while `id < 10` keeps one percent of them.
+        let model = CostModel::new(&table, Some(&stats));
+        assert_eq!(ordered(&model, "age = 5 and id < 10"), Predicate::parse("id < 10 and age = 5", &table).unwrap());
+    }
+
+    #[test]
+    fn children_that_can_fail_stay_behind_their_guards() {
+        let table = table();
+        let model = CostModel::new(&table, None);
+        let predicate = "id != 0 and age / id > 2 and name = 'x' and age = 1";
+        assert_eq!(
+            ordered(&model, predicate),
+            Predicate::parse("id != 0 and age / id > 2 and age = 1 and name = 'x'", &table).unwrap()
+        );
+    }
+
+    #[test]
+    fn zone_maps_pay_off_only_for_selective_scans_of_large_columns() {
+        let table = table();
+        let model = CostModel::new(&table, None);
+        assert!(model.use_zone_map(1000, 1_000_000, 4.0, 0.01));
+        assert!(!model.use_zone_map(1000, 1_000_000, 4.0, 1.0));
+        assert!(!model.use_zone_map(0, 1_000_000, 4.0, 0.01));
+        assert!(build_left(10.0, 100.0));
+        assert!(!build_left(100.0, 10.0));
+    }
+}
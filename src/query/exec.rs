@@ -0,0 +1,461 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use super::{
+    expr::Expr,
+    plan::{Aggregate, PhysicalPlan},
+    sql::AggregateFunc,
+};
+use crate::storage::{chunk::CHUNK_ROWS, column::ColumnStore, vector::{Batch, Vector}};
+use crate::utils::bitmap::Bitmap;
+
+/// Rows flowing between operators: one vector per named column, all of length `len`.
+///
+/// `nulls` holds, per column, the rows that are null, `None` when there are none; those rows
+/// hold their type's zero value in the vector.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    pub names: Vec<String>,
+    pub vectors: Vec<Vector>,
+    pub nulls: Vec<Option<Bitmap>>,
+    pub len: usize,
+}
+
+impl Relation {
+    fn batch(&self) -> Batch {
+        let columns = self.names.iter().cloned().zip(self.vectors.iter().cloned()).collect();
+        let nulls = self.names.iter().zip(&self.nulls).filter_map(|(name, nulls)| Some((name.clone(), nulls.clone()?))).collect();
+        Batch { columns, nulls, len: self.len }
+    }
+
+    fn take(&self, rows: &[usize]) -> Relation {
+        Relation {
+            names: self.names.clone(),
+            vectors: self.vectors.iter().map(|v| v.take(rows)).collect(),
+            nulls: self.nulls.iter().map(|n| n.as_ref().map(|n| n.take(rows)).filter(Bitmap::any)).collect(),
+            len: rows.len(),
+        }
+    }
+
+    fn is_null(&self, column: usize, row: usize) -> bool {
+        self.nulls[column].as_ref().is_some_and(|nulls| nulls.get(row))
+    }
+
+    /// Prints every row as `Row <n>: name = value, ...`.
+    pub fn print(&self) {
+        for row in 0..self.len {
+            let values: Vec<String> = self
+                .names
+                .iter()
+                .zip(&self.vectors)
+                .enumerate()
+                .map(|(column, (name, vector))| {
+                    let value = if self.is_null(column, row) { "null".to_string() } else { vector.display(row) };
+                    format!("{} = {}", name, value)
+                })
+                .collect();
+            println!("Row {}: {}", row, values.join(", "));
+        }
+    }
+}
+
+/// What one operator did while running, for `explain --analyze`. `elapsed` includes the
+/// time spent in the operators below it.
+#[derive(Debug, Clone, Default)]
+pub struct OperatorMetrics {
+    pub rows_in: usize,
+    pub rows_out: usize,
+    pub data_bytes: u64,
+    pub index_bytes: u64,
+    pub chunks_pruned: usize,
+    pub chunk_count: usize,
+    pub elapsed: Duration,
+    pub children: Vec<OperatorMetrics>,
+}
+
+impl OperatorMetrics {
+    fn new(children: Vec<OperatorMetrics>, rows_out: usize) -> Self {
+        let rows_in = children.iter().map(|c| c.rows_out).sum();
+        Self { rows_in, rows_out, children, ..Self::default() }
+    }
+
+    /// Bytes read by this operator and every operator below it, from `.data` and `.idx` files.
+    pub fn total_bytes(&self) -> (u64, u64) {
+        self.children.iter().map(OperatorMetrics::total_bytes).fold((self.data_bytes, self.index_bytes), |(d, i), (cd, ci)| (d + cd, i + ci))
+    }
+
+    /// The plan tree annotated with what each operator actually did.
+    pub fn report(&self, plan: &PhysicalPlan) -> String {
+        let mut out = String::new();
+        self.report_into(plan, 0, &mut out);
+        out
+    }
+
+    fn report_into(&self, plan: &PhysicalPlan, depth: usize, out: &mut String) {
+        let mut actual = format!("rows in={} out={}", self.rows_in, self.rows_out);
+        if let PhysicalPlan::ColumnScan { .. } = plan {
+            actual += &format!(" .data bytes={} .idx bytes={}", self.data_bytes, self.index_bytes);
+            actual += &format!(" chunks pruned={} of {}", self.chunks_pruned, self.chunk_count);
+        }
+        actual += &format!(" time={:.3}ms", self.elapsed.as_secs_f64() * 1000.0);
+
+        out.push_str(&format!("{}{}\n{}  actual: {}\n", "  ".repeat(depth), plan.describe(), "  ".repeat(depth), actual));
+        for (child, metrics) in plan.children().into_iter().zip(&self.children) {
+            metrics.report_into(child, depth + 1, out);
+        }
+    }
+}
+
+impl PhysicalPlan {
+    pub fn execute(&self, store: &ColumnStore) -> Result<Relation, String> {
+        self.analyze(store).map(|(relation, _)| relation)
+    }
+
+    /// Runs the plan and records rows, bytes read, pruning and wall time per operator.
+    pub fn analyze(&self, store: &ColumnStore) -> Result<(Relation, OperatorMetrics), String> {
+        let started = Instant::now();
+        let (relation, mut metrics) = self.run(store)?;
+        metrics.elapsed = started.elapsed();
+        Ok((relation, metrics))
+    }
+
+    fn run(&self, store: &ColumnStore) -> Result<(Relation, OperatorMetrics), String> {
+        match self {
+            PhysicalPlan::ColumnScan { table, columns, qualify, filter, backends, zone_maps, index_bytes, .. } => {
+                let mut names: Vec<&str> = columns.iter().map(String::as_str).collect();
+                for name in filter.iter().flat_map(|f| f.columns()) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                let mut chunks = store.open_chunks(table, &names)?;
+                let row_count = chunks.row_count();
+
+                let mut chunks_pruned = 0;
+                let selected = match filter {
+                    Some(filter) => {
+                        // Chunks the zone maps rule out never become candidates, so they are never read.
+                        let mut surviving = Bitmap::new(row_count);
+                        for chunk in 0..chunks.chunk_count() {
+                            if filter.chunk_may_match(zone_maps, chunk) {
+                                surviving.or_words_at(chunk * CHUNK_ROWS, &vec![u64::MAX; chunks.chunk_len(chunk).div_ceil(64)]);
+                            } else {
+                                chunks_pruned += 1;
+                            }
+                        }
+                        let candidates = Bitmap::full(row_count).and(&surviving);
+                        filter.evaluate_with(&mut chunks, &candidates, backends)?
+                    }
+                    None => Bitmap::full(row_count),
+                };
+
+                let output: Vec<&str> = columns.iter().map(String::as_str).collect();
+                let mut vectors: Vec<Vector> = output
+                    .iter()
+                    .map(|name| Vector::empty(&table.columns.iter().find(|c| c.name == *name).unwrap().data_type))
+                    .collect();
+                let mut nulls = vec![Bitmap::new(selected.count_ones()); output.len()];
+                let mut produced = 0;
+                for chunk in 0..chunks.chunk_count() {
+                    let start = chunk * CHUNK_ROWS;
+                    if !selected.any_in(start, start + CHUNK_ROWS) {
+                        continue;
+                    }
+
+                    let rows: Vec<usize> = (0..chunks.chunk_len(chunk)).filter(|row| selected.get(start + row)).collect();
+                    let mut batch = chunks.batch(&output, chunk)?.take(&rows);
+                    for ((vector, column_nulls), name) in vectors.iter_mut().zip(&mut nulls).zip(&output) {
+                        vector.append(batch.columns.remove(*name).unwrap());
+                        for row in batch.nulls.get(*name).into_iter().flat_map(Bitmap::iter_ones) {
+                            column_nulls.set(produced + row);
+                        }
+                    }
+                    produced += rows.len();
+                }
+                let nulls = nulls.into_iter().map(|n| n.any().then_some(n)).collect();
+
+                let names = columns
+                    .iter()
+                    .map(|name| if *qualify { format!("{}.{}", table.table_name, name) } else { name.clone() })
+                    .collect();
+                let metrics = OperatorMetrics {
+                    rows_in: row_count,
+                    rows_out: selected.count_ones(),
+                    data_bytes: chunks.bytes_read(),
+                    index_bytes: *index_bytes,
+                    chunks_pruned,
+                    chunk_count: chunks.chunk_count(),
+                    ..OperatorMetrics::default()
+                };
+                Ok((Relation { names, vectors, nulls, len: metrics.rows_out }, metrics))
+            }
+            PhysicalPlan::Filter { input, predicate, .. } => {
+                let (input, child) = input.analyze(store)?;
+                // A row where the predicate is null is not kept, whatever the predicate says.
+                match predicate.evaluate_nullable(&input.batch())? {
+                    (Vector::Bool(hits), nulls) => {
+                        let is_null = |row: usize| nulls.as_ref().is_some_and(|n| n.get(row));
+                        let rows: Vec<usize> = (0..input.len).filter(|&row| hits[row] && !is_null(row)).collect();
+                        Ok((input.take(&rows), OperatorMetrics::new(vec![child], rows.len())))
+                    }
+                    (other, _) => Err(format!("Filter '{}' produced {} values, expected bool", predicate, other.data_type())),
+                }
+            }
+            PhysicalPlan::HashJoin { left, right, left_key, right_key, build_left, .. } => {
+                let ((left, left_metrics), (right, right_metrics)) = (left.analyze(store)?, right.analyze(store)?);
+                // A null key equals nothing, not even another null, so it is neither built nor probed.
+                let key_of = |relation: &Relation, key: &str| -> Result<Vec<Option<String>>, String> {
+                    let (values, nulls) = Expr::Column(key.to_string()).evaluate_nullable(&relation.batch())?;
+                    Ok((0..relation.len).map(|row| (!nulls.as_ref().is_some_and(|n| n.get(row))).then(|| values.display(row))).collect())
+                };
+                let (left_keys, right_keys) = (key_of(&left, left_key)?, key_of(&right, right_key)?);
+
+                let (build_keys, probe_keys) = if *build_left { (&left_keys, &right_keys) } else { (&right_keys, &left_keys) };
+                let mut table: HashMap<&str, Vec<usize>> = HashMap::new();
+                for (row, key) in build_keys.iter().enumerate() {
+                    if let Some(key) = key {
+                        table.entry(key.as_str()).or_default().push(row);
+                    }
+                }
+
+                let (mut left_rows, mut right_rows) = (Vec::new(), Vec::new());
+                for (probe, key) in probe_keys.iter().enumerate() {
+                    for &build in key.as_deref().and_then(|key| table.get(key)).into_iter().flatten() {
+                        let (l, r) = if *build_left { (build, probe) } else { (probe, build) };
+                        left_rows.push(l);
+                        right_rows.push(r);
+                    }
+                }
+
+                let (left, right) = (left.take(&left_rows), right.take(&right_rows));
+                let relation = Relation {
+                    names: left.names.into_iter().chain(right.names).collect(),
+                    vectors: left.vectors.into_iter().chain(right.vectors).collect(),
+                    nulls: left.nulls.into_iter().chain(right.nulls).collect(),
+                    len: left_rows.len(),
+                };
+                Ok((relation, OperatorMetrics::new(vec![left_metrics, right_metrics], left_rows.len())))
+            }
+            PhysicalPlan::Project { input, exprs } => {
+                let (input, child) = input.analyze(store)?;
+                let batch = input.batch();
+                let (vectors, nulls) = exprs.iter().map(|(expr, _)| expr.evaluate_nullable(&batch)).collect::<Result<Vec<_>, _>>()?.into_iter().unzip();
+                let relation = Relation { names: exprs.iter().map(|(_, name)| name.clone()).collect(), vectors, nulls, len: input.len };
+                Ok((relation, OperatorMetrics::new(vec![child], input.len)))
+            }
+            PhysicalPlan::HashAggregate { input, group_by, aggregates } => {
+                let (input, child) = input.analyze(store)?;
+                let relation = aggregate(&input, group_by, aggregates)?;
+                let metrics = OperatorMetrics::new(vec![child], relation.len);
+                Ok((relation, metrics))
+            }
+            PhysicalPlan::Sort { input, keys } => {
+                let (input, child) = input.analyze(store)?;
+                let batch = input.batch();
+                let values = keys.iter().map(|(key, _)| key.evaluate_nullable(&batch)).collect::<Result<Vec<_>, _>>()?;
+
+                // Nulls sort after every value, so they come last ascending and first descending.
+                let mut rows: Vec<usize> = (0..input.len).collect();
+                rows.sort_by(|&a, &b| {
+                    keys.iter()
+                        .zip(&values)
+                        .map(|((_, ascending), (vector, nulls))| {
+                            let is_null = |row: usize| nulls.as_ref().is_some_and(|n| n.get(row));
+                            let order = match (is_null(a), is_null(b)) {
+                                (false, false) => vector.compare_rows(a, b),
+                                (a_null, b_null) => a_null.cmp(&b_null),
+                            };
+                            if *ascending { order } else { order.reverse() }
+                        })
+                        .find(|order| order.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                Ok((input.take(&rows), OperatorMetrics::new(vec![child], input.len)))
+            }
+            PhysicalPlan::Limit { input, limit } => {
+                let (mut relation, child) = input.analyze(store)?;
+                relation.len = relation.len.min(*limit);
+                for vector in &mut relation.vectors {
+                    vector.truncate(relation.len);
+                }
+                let kept: Vec<usize> = (0..relation.len).collect();
+                for nulls in &mut relation.nulls {
+                    *nulls = nulls.take().map(|n| n.take(&kept)).filter(Bitmap::any);
+                }
+                let metrics = OperatorMetrics::new(vec![child], relation.len);
+                Ok((relation, metrics))
+            }
+        }
+    }
+}
+
+/// Groups `input` by the values of `group_by` and computes `aggregates` per group, with
+/// groups in order of first appearance; rows whose keys are null group together.
+///
+/// Aggregates skip nulls: `count(x)` counts the other rows, and `sum`, `avg`, `min` and
+/// `max` are null for a group without any. Without a `group by` there is exactly one group,
+/// even over no rows.
+fn aggregate(input: &Relation, group_by: &[(Expr, String)], aggregates: &[Aggregate]) -> Result<Relation, String> {
+    let is_null = |nulls: &Option<Bitmap>, row: usize| nulls.as_ref().is_some_and(|n| n.get(row));
+    let batch = input.batch();
+    let keys = group_by.iter().map(|(expr, _)| expr.evaluate_nullable(&batch)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut groups: HashMap<Vec<Option<String>>, usize> = HashMap::new();
+    let mut first_rows = Vec::new();
+    let mut group_of = Vec::with_capacity(input.len);
+    for row in 0..input.len {
+        let key: Vec<Option<String>> = keys.iter().map(|(k, nulls)| (!is_null(nulls, row)).then(|| k.display(row))).collect();
+        let group = *groups.entry(key).or_insert_with(|| {
+            first_rows.push(row);
+            first_rows.len() - 1
+        });
+        group_of.push(group);
+    }
+
+    let group_count = if group_by.is_empty() { 1 } else { first_rows.len() };
+    let mut names: Vec<String> = group_by.iter().map(|(_, name)| name.clone()).collect();
+    let mut vectors: Vec<Vector> = keys.iter().map(|(k, _)| k.take(&first_rows)).collect();
+    let mut nulls: Vec<Option<Bitmap>> = keys.iter().map(|(_, n)| n.as_ref().map(|n| n.take(&first_rows)).filter(Bitmap::any)).collect();
+
+    for aggregate in aggregates {
+        let (values, value_nulls) = match aggregate.arg.as_ref().map(|arg| arg.evaluate_nullable(&batch)).transpose()? {
+            Some((values, nulls)) => (Some(values), nulls),
+            None => (None, None),
+        };
+        // The rows each group aggregates, as (row, group): every row for `count(*)`.
+        let present: Vec<(usize, usize)> = group_of.iter().copied().enumerate().filter(|&(row, _)| !is_null(&value_nulls, row)).collect();
+        let mut counts = vec![0usize; group_count];
+        present.iter().for_each(|&(_, g)| counts[g] += 1);
+        let mut empty = Bitmap::new(group_count);
+        for (g, _) in counts.iter().enumerate().filter(|(_, count)| **count == 0) {
+            empty.set(g);
+        }
+
+        names.push(aggregate.name.clone());
+        nulls.push((aggregate.func != AggregateFunc::Count && empty.any()).then_some(empty));
+        vectors.push(match (aggregate.func, values) {
+            (AggregateFunc::Count, _) => Vector::Int(counts.iter().map(|&count| count as i32).collect()),
+            (AggregateFunc::Sum, Some(Vector::Int(values))) => {
+                let mut sums = vec![0i64; group_count];
+                present.iter().for_each(|&(row, g)| sums[g] += values[row] as i64);
+                let sums = sums
+                    .into_iter()
+                    .map(|sum| i32::try_from(sum).map_err(|_| format!("{} overflows int", aggregate.name)))
+                    .collect::<Result<_, _>>()?;
+                Vector::Int(sums)
+            }
+            (AggregateFunc::Sum, Some(Vector::Float(values))) => {
+                let mut sums = vec![0.0; group_count];
+                present.iter().for_each(|&(row, g)| sums[g] += values[row]);
+                Vector::Float(sums)
+            }
+            (AggregateFunc::Avg, Some(values)) => {
+                let mut sums = vec![0.0; group_count];
+                for &(row, g) in &present {
+                    sums[g] += match &values {
+                        Vector::Int(v) => v[row] as f64,
+                        Vector::Float(v) => v[row],
+                        other => return Err(format!("avg needs a numeric argument, found {}", other.data_type())),
+                    };
+                }
+                Vector::Float(sums.iter().zip(&counts).map(|(sum, &count)| if count == 0 { 0.0 } else { sum / count as f64 }).collect())
+            }
+            (AggregateFunc::Min | AggregateFunc::Max, Some(values)) => {
+                let wanted = if aggregate.func == AggregateFunc::Min { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater };
+                let mut best: Vec<Option<usize>> = vec![None; group_count];
+                for &(row, g) in &present {
+                    match best[g] {
+                        Some(current) if values.compare_rows(row, current) != wanted => {}
+                        _ => best[g] = Some(row),
+                    }
+                }
+                let (groups, rows): (Vec<usize>, Vec<usize>) = best.iter().enumerate().filter_map(|(g, row)| Some((g, (*row)?))).unzip();
+                values.take(&rows).spread(&groups, group_count)
+            }
+            (func, values) => {
+                let found = values.map_or("nothing", |v| v.data_type());
+                return Err(format!("{} cannot aggregate {} values", func, found));
+            }
+        });
+    }
+
+    Ok(Relation { names, vectors, nulls, len: group_count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::plan::plan_query;
+    use crate::storage::table::TableSchema;
+
+    #[test]
+    fn analyze_counts_rows_bytes_and_pruned_chunks() {
+        let dir = std::env::temp_dir().join(format!("cdbe-exec-analyze-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let mut table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        table.format_version = 0;
+
+        // Three chunks of ascending ids in files without headers, with one index entry per chunk.
+        let ids: Vec<u8> = (0..3000i32).flat_map(|id| id.to_le_bytes()).collect();
+        std::fs::write(dir.join("t_id.data"), ids).unwrap();
+        let index: String = [(0, 1023), (1024, 2047), (2048, 2999)]
+            .iter()
+            .map(|(min, max)| format!("{{\"chunk_offset\":{},\"min_value\":\"{}\",\"max_value\":\"{}\"}}\n", min * 4, min, max))
+            .collect();
+        std::fs::write(dir.join("t_id.idx"), &index).unwrap();
+
+        let tables = HashMap::from([("t".to_string(), table)]);
+        let (_, physical) = plan_query("select id + 1 from t where id >= 2500 limit 100", &tables, &store).unwrap();
+        let (relation, metrics) = physical.analyze(&store).unwrap();
+        assert_eq!(relation.len, 100);
+        assert_eq!((metrics.rows_in, metrics.rows_out), (500, 100));
+
+        let (mut scan, mut depth) = (&metrics, 0);
+        while let Some(child) = scan.children.first() {
+            (scan, depth) = (child, depth + 1);
+        }
+        assert_eq!(depth, 2);
+        assert_eq!((scan.rows_in, scan.rows_out), (3000, 500));
+        assert_eq!((scan.chunks_pruned, scan.chunk_count), (2, 3));
+        assert_eq!(scan.data_bytes, (3000 - 2048) * 4);
+        assert_eq!(scan.index_bytes, index.len() as u64);
+        assert_eq!(metrics.total_bytes(), (scan.data_bytes, scan.index_bytes));
+        assert!(metrics.report(&physical).contains("chunks pruned=2 of 3"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nulls_never_match_and_aggregates_skip_them() {
+        let dir = std::env::temp_dir().join(format!("cdbe-exec-nulls-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let users = TableSchema::new("users".to_string(), ["id:int", "team:int?", "score:int?"].map(String::from).to_vec());
+        let teams = TableSchema::new("teams".to_string(), ["key:int?", "name:string"].map(String::from).to_vec());
+        store.insert_rows(&users, &[vec!["1", "1", "10"], vec!["2", "", ""], vec!["3", "1", ""], vec!["4", "", "5"]]).unwrap();
+        store.insert_rows(&teams, &[vec!["1", "red"], vec!["", "none"]]).unwrap();
+        let tables = HashMap::from([("users".to_string(), users), ("teams".to_string(), teams)]);
+
+        let rows = |sql: &str| -> Vec<String> {
+            let (_, physical) = plan_query(sql, &tables, &store).unwrap();
+            let relation = physical.execute(&store).unwrap();
+            (0..relation.len)
+                .map(|row| {
+                    let values: Vec<String> = (0..relation.names.len())
+                        .map(|column| if relation.is_null(column, row) { "null".to_string() } else { relation.vectors[column].display(row) })
+                        .collect();
+                    values.join(",")
+                })
+                .collect()
+        };
+
+        // Neither side of a comparison with a null holds.
+        assert_eq!(rows("select id from users where score < 100"), ["1", "4"]);
+        assert_eq!(rows("select id from users where not score < 100"), Vec::<String>::new());
+        assert_eq!(rows("select id, score + 1 from users where id <= 2"), ["1,11", "2,null"]);
+        assert_eq!(rows("select count(*), count(score), sum(score), min(score) from users"), ["4,2,15,5"]);
+        assert_eq!(rows("select team, count(*) from users group by team"), ["1,2", "null,2"]);
+        // A null key joins nothing, not even the other side's null.
+        assert_eq!(rows("select id, name from users join teams on team = key"), ["1,red", "3,red"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1055 @@
+use std::{cmp::Ordering, fmt};
+
+use super::lexer::{is_keyword, tokenize, Token};
+use crate::storage::{table::TableSchema, vector::{Batch, Vector}};
+use crate::utils::{bitmap::Bitmap, simd::{compare_scalar, SimdOp}};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar {
+    Int(i32),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Upper,
+    Lower,
+    Length,
+    Substring,
+}
+
+/// A computed value over the columns of one table, evaluated a whole batch at a time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Scalar),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Arith { op: ArithOp, left: Box<Expr>, right: Box<Expr> },
+    Compare { op: SimdOp, left: Box<Expr>, right: Box<Expr> },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Between { expr: Box<Expr>, low: Box<Expr>, high: Box<Expr>, negated: bool },
+    In { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    Cast { expr: Box<Expr>, data_type: String },
+    Case { branches: Vec<(Expr, Expr)>, otherwise: Box<Expr> },
+    Call { function: Function, args: Vec<Expr> },
+}
+
+impl Expr {
+    /// Parses a single expression, e.g. `price * qty`, `upper(substring(name, 1, 3))`
+    /// or `case when age < 30 then 'young' else 'old' end`.
+    pub fn parse(input: &str) -> Result<Expr, String> {
+        let mut parser = ExprParser::new(input)?;
+        let expr = parser.parse_expr()?;
+        parser.expect_end()?;
+        Ok(expr)
+    }
+
+    /// Parses a comma separated projection list where each entry may be named with `as`.
+    /// Unnamed entries are named after their own text.
+    pub fn parse_projection(input: &str) -> Result<Vec<(Expr, String)>, String> {
+        let mut parser = ExprParser::new(input)?;
+        let projection = parser.parse_projection()?;
+        parser.expect_end()?;
+        Ok(projection)
+    }
+
+    /// Returns the referenced column names in order of first appearance.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Expr::Column(name) => {
+                if !columns.contains(&name.as_str()) {
+                    columns.push(name);
+                }
+            }
+            Expr::Literal(_) => {}
+            Expr::Neg(expr) | Expr::Not(expr) | Expr::Cast { expr, .. } => expr.collect_columns(columns),
+            Expr::Arith { left, right, .. } | Expr::Compare { left, right, .. } | Expr::And(left, right) | Expr::Or(left, right) => {
+                left.collect_columns(columns);
+                right.collect_columns(columns);
+            }
+            Expr::Between { expr, low, high, .. } => {
+                expr.collect_columns(columns);
+                low.collect_columns(columns);
+                high.collect_columns(columns);
+            }
+            Expr::In { expr, list, .. } => {
+                expr.collect_columns(columns);
+                for item in list {
+                    item.collect_columns(columns);
+                }
+            }
+            Expr::Case { branches, otherwise } => {
+                for (condition, value) in branches {
+                    condition.collect_columns(columns);
+                    value.collect_columns(columns);
+                }
+                otherwise.collect_columns(columns);
+            }
+            Expr::Call { args, .. } => {
+                for arg in args {
+                    arg.collect_columns(columns);
+                }
+            }
+        }
+    }
+
+    /// Whether evaluating the expression can fail on some rows: integer division or modulo by
+    /// zero, or a cast of text that does not parse.
+    pub fn may_fail(&self) -> bool {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) => false,
+            Expr::Arith { op: ArithOp::Div | ArithOp::Mod, .. } | Expr::Cast { .. } => true,
+            Expr::Neg(expr) | Expr::Not(expr) => expr.may_fail(),
+            Expr::Arith { left, right, .. } | Expr::Compare { left, right, .. } | Expr::And(left, right) | Expr::Or(left, right) => {
+                left.may_fail() || right.may_fail()
+            }
+            Expr::Between { expr, low, high, .. } => expr.may_fail() || low.may_fail() || high.may_fail(),
+            Expr::In { expr, list, .. } => expr.may_fail() || list.iter().any(Expr::may_fail),
+            Expr::Case { branches, otherwise } => {
+                branches.iter().any(|(condition, value)| condition.may_fail() || value.may_fail()) || otherwise.may_fail()
+            }
+            Expr::Call { args, .. } => args.iter().any(Expr::may_fail),
+        }
+    }
+
+    /// Rebuilds the expression with every column reference passed through `rename`.
+    pub fn map_columns(&self, rename: &mut impl FnMut(&str) -> Result<String, String>) -> Result<Expr, String> {
+        let mut boxed = |expr: &Expr| expr.map_columns(rename).map(Box::new);
+        Ok(match self {
+            Expr::Column(name) => Expr::Column(rename(name)?),
+            Expr::Literal(scalar) => Expr::Literal(scalar.clone()),
+            Expr::Neg(expr) => Expr::Neg(boxed(expr)?),
+            Expr::Not(expr) => Expr::Not(boxed(expr)?),
+            Expr::Arith { op, left, right } => Expr::Arith { op: *op, left: boxed(left)?, right: boxed(right)? },
+            Expr::Compare { op, left, right } => Expr::Compare { op: *op, left: boxed(left)?, right: boxed(right)? },
+            Expr::And(left, right) => Expr::And(boxed(left)?, boxed(right)?),
+            Expr::Or(left, right) => Expr::Or(boxed(left)?, boxed(right)?),
+            Expr::Between { expr, low, high, negated } => {
+                Expr::Between { expr: boxed(expr)?, low: boxed(low)?, high: boxed(high)?, negated: *negated }
+            }
+            Expr::In { expr, list, negated } => Expr::In {
+                expr: boxed(expr)?,
+                list: list.iter().map(|item| item.map_columns(rename)).collect::<Result<_, _>>()?,
+                negated: *negated,
+            },
+            Expr::Cast { expr, data_type } => Expr::Cast { expr: boxed(expr)?, data_type: data_type.clone() },
+            Expr::Case { branches, otherwise } => Expr::Case {
+                branches: branches
+                    .iter()
+                    .map(|(condition, value)| Ok((condition.map_columns(rename)?, value.map_columns(rename)?)))
+                    .collect::<Result<_, String>>()?,
+                otherwise: otherwise.map_columns(rename).map(Box::new)?,
+            },
+            Expr::Call { function, args } => Expr::Call {
+                function: *function,
+                args: args.iter().map(|arg| arg.map_columns(rename)).collect::<Result<_, _>>()?,
+            },
+        })
+    }
+
+    /// Splits a chain of `and`s into its conjuncts.
+    pub fn conjuncts(self) -> Vec<Expr> {
+        match self {
+            Expr::And(left, right) => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            expr => vec![expr],
+        }
+    }
+
+    /// Type checks the expression against `table` and returns the type it produces.
+    pub fn result_type(&self, table: &TableSchema) -> Result<&'static str, String> {
+        match self {
+            Expr::Column(name) => match table.columns.iter().find(|c| &c.name == name) {
+                Some(column) => type_name(&column.data_type),
+                None => Err(format!("Column '{}' not found in table '{}'", name, table.table_name)),
+            },
+            Expr::Literal(scalar) => Ok(scalar.data_type()),
+            Expr::Neg(expr) => {
+                let ty = expr.result_type(table)?;
+                if is_numeric(ty) {
+                    Ok(ty)
+                } else {
+                    Err(format!("Cannot negate a {} value", ty))
+                }
+            }
+            Expr::Not(expr) => expect_bool(expr.result_type(table)?, "NOT"),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                expect_bool(left.result_type(table)?, "AND/OR")?;
+                expect_bool(right.result_type(table)?, "AND/OR")
+            }
+            Expr::Arith { left, right, .. } => {
+                let (l, r) = (left.result_type(table)?, right.result_type(table)?);
+                if !is_numeric(l) || !is_numeric(r) {
+                    return Err(format!("Arithmetic needs numeric operands, found {} and {}", l, r));
+                }
+                Ok(if l == "float" || r == "float" { "float" } else { "int" })
+            }
+            Expr::Compare { left, right, .. } => {
+                comparable(left.result_type(table)?, right.result_type(table)?)?;
+                Ok("bool")
+            }
+            Expr::Between { expr, low, high, .. } => {
+                let ty = expr.result_type(table)?;
+                comparable(ty, low.result_type(table)?)?;
+                comparable(ty, high.result_type(table)?)?;
+                Ok("bool")
+            }
+            Expr::In { expr, list, .. } => {
+                let ty = expr.result_type(table)?;
+                for item in list {
+                    comparable(ty, item.result_type(table)?)?;
+                }
+                Ok("bool")
+            }
+            Expr::Cast { expr, data_type } => {
+                expr.result_type(table)?;
+                type_name(data_type)
+            }
+            Expr::Case { branches, otherwise } => {
+                let mut ty = otherwise.result_type(table)?;
+                for (condition, value) in branches {
+                    expect_bool(condition.result_type(table)?, "CASE WHEN")?;
+                    ty = unify(ty, value.result_type(table)?)?;
+                }
+                Ok(ty)
+            }
+            Expr::Call { function, args } => {
+                let types = args.iter().map(|arg| arg.result_type(table)).collect::<Result<Vec<_>, _>>()?;
+                let expected: &[&str] = match function {
+                    Function::Upper | Function::Lower | Function::Length => &["string"],
+                    Function::Substring if types.len() == 2 => &["string", "int"],
+                    Function::Substring => &["string", "int", "int"],
+                };
+                if types != expected {
+                    return Err(format!("{} expects ({}), found ({})", function.name(), expected.join(", "), types.join(", ")));
+                }
+                Ok(match function {
+                    Function::Length => "int",
+                    _ => "string",
+                })
+            }
+        }
+    }
+
+    /// Evaluates the expression over every row of `batch`, along with the rows where it is
+    /// null: those where a column it reads is null. Only the other rows are evaluated, so the
+    /// placeholder stored for a null never reaches an operator.
+    pub fn evaluate_nullable(&self, batch: &Batch) -> Result<(Vector, Option<Bitmap>), String> {
+        match batch.null_rows(&self.columns()) {
+            Some(nulls) => {
+                let rows: Vec<usize> = (0..batch.len).filter(|&row| !nulls.get(row)).collect();
+                Ok((self.evaluate(&batch.take(&rows))?.spread(&rows, batch.len), Some(nulls)))
+            }
+            None => Ok((self.evaluate(batch)?, None)),
+        }
+    }
+
+    /// Evaluates the expression over every row of `batch`, which must hold no nulls in the
+    /// columns it reads; see [`Expr::evaluate_nullable`].
+    pub fn evaluate(&self, batch: &Batch) -> Result<Vector, String> {
+        match self {
+            Expr::Column(name) => batch
+                .columns
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Column '{}' missing from batch", name)),
+            Expr::Literal(scalar) => Ok(scalar.broadcast(batch.len)),
+            Expr::Neg(expr) => match expr.evaluate(batch)? {
+                Vector::Int(v) => Ok(Vector::Int(v.iter().map(|x| x.wrapping_neg()).collect())),
+                Vector::Float(v) => Ok(Vector::Float(v.iter().map(|x| -x).collect())),
+                other => Err(format!("Cannot negate a {} value", other.data_type())),
+            },
+            Expr::Not(expr) => {
+                let values = as_bools(expr.evaluate(batch)?)?;
+                Ok(Vector::Bool(values.iter().map(|b| !b).collect()))
+            }
+            // The right side only sees the rows the left one leaves undecided.
+            Expr::And(left, right) => {
+                let mut values = as_bools(left.evaluate(batch)?)?;
+                let rows: Vec<usize> = (0..batch.len).filter(|&row| values[row]).collect();
+                for (row, hit) in rows.iter().zip(as_bools(right.evaluate(&batch.take(&rows))?)?) {
+                    values[*row] = hit;
+                }
+                Ok(Vector::Bool(values))
+            }
+            Expr::Or(left, right) => {
+                let mut values = as_bools(left.evaluate(batch)?)?;
+                let rows: Vec<usize> = (0..batch.len).filter(|&row| !values[row]).collect();
+                for (row, hit) in rows.iter().zip(as_bools(right.evaluate(&batch.take(&rows))?)?) {
+                    values[*row] = hit;
+                }
+                Ok(Vector::Bool(values))
+            }
+            Expr::Arith { op, left, right } => arith(*op, left.evaluate(batch)?, right.evaluate(batch)?),
+            Expr::Compare { op, left, right } => compare(*op, &left.evaluate(batch)?, &right.evaluate(batch)?),
+            Expr::Between { expr, low, high, negated } => {
+                let values = expr.evaluate(batch)?;
+                let lo = as_bools(compare(SimdOp::Ge, &values, &low.evaluate(batch)?)?)?;
+                let hi = as_bools(compare(SimdOp::Le, &values, &high.evaluate(batch)?)?)?;
+                Ok(Vector::Bool(lo.iter().zip(&hi).map(|(a, b)| (*a && *b) != *negated).collect()))
+            }
+            Expr::In { expr, list, negated } => {
+                let values = expr.evaluate(batch)?;
+                let mut matched = vec![false; batch.len];
+                for item in list {
+                    let eq = as_bools(compare(SimdOp::Eq, &values, &item.evaluate(batch)?)?)?;
+                    for (m, e) in matched.iter_mut().zip(eq) {
+                        *m |= e;
+                    }
+                }
+                Ok(Vector::Bool(matched.into_iter().map(|m| m != *negated).collect()))
+            }
+            Expr::Cast { expr, data_type } => cast(expr.evaluate(batch)?, type_name(data_type)?),
+            // Each condition sees the rows no earlier branch took and each value only the rows
+            // that take it, so `case when b != 0 then a / b else 0 end` never divides by zero.
+            Expr::Case { branches, otherwise } => {
+                let mut chosen = vec![branches.len(); batch.len];
+                let mut open: Vec<usize> = (0..batch.len).collect();
+                for (branch, (condition, _)) in branches.iter().enumerate() {
+                    let hits = as_bools(condition.evaluate(&batch.take(&open))?)?;
+                    let mut still_open = Vec::with_capacity(open.len());
+                    for (row, hit) in open.into_iter().zip(hits) {
+                        if hit {
+                            chosen[row] = branch;
+                        } else {
+                            still_open.push(row);
+                        }
+                    }
+                    open = still_open;
+                }
+                let values = branches
+                    .iter()
+                    .map(|(_, value)| value)
+                    .chain([otherwise.as_ref()])
+                    .enumerate()
+                    .map(|(branch, value)| {
+                        let rows: Vec<usize> = (0..batch.len).filter(|&row| chosen[row] == branch).collect();
+                        value.evaluate(&batch.take(&rows))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                pick(&chosen, values)
+            }
+            Expr::Call { function, args } => {
+                let args = args.iter().map(|arg| arg.evaluate(batch)).collect::<Result<Vec<_>, _>>()?;
+                call(*function, args)
+            }
+        }
+    }
+}
+
+impl Scalar {
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            Scalar::Int(_) => "int",
+            Scalar::Float(_) => "float",
+            Scalar::Str(_) => "string",
+            Scalar::Bool(_) => "bool",
+        }
+    }
+
+    fn broadcast(&self, len: usize) -> Vector {
+        match self {
+            Scalar::Int(v) => Vector::Int(vec![*v; len]),
+            Scalar::Float(v) => Vector::Float(vec![*v; len]),
+            Scalar::Str(v) => Vector::Str(vec![v.clone(); len]),
+            Scalar::Bool(v) => Vector::Bool(vec![*v; len]),
+        }
+    }
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name.to_lowercase().as_str() {
+            "upper" => Some(Function::Upper),
+            "lower" => Some(Function::Lower),
+            "length" | "len" => Some(Function::Length),
+            "substring" | "substr" => Some(Function::Substring),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Upper => "upper",
+            Function::Lower => "lower",
+            Function::Length => "length",
+            Function::Substring => "substring",
+        }
+    }
+}
+
+/// Normalizes a type name written in a schema or a `CAST`.
+fn type_name(name: &str) -> Result<&'static str, String> {
+    match name.to_lowercase().as_str() {
+        "int" | "integer" => Ok("int"),
+        "float" | "double" | "real" => Ok("float"),
+        "string" | "text" | "varchar" => Ok("string"),
+        "bool" | "boolean" => Ok("bool"),
+        // Timestamps are read as text that sorts chronologically, see `timestamp::format`.
+        "timestamp" => Ok("string"),
+        other => Err(format!("Unsupported data type '{}'", other)),
+    }
+}
+
+fn is_numeric(ty: &str) -> bool {
+    ty == "int" || ty == "float"
+}
+
+fn expect_bool(ty: &'static str, context: &str) -> Result<&'static str, String> {
+    if ty == "bool" {
+        Ok(ty)
+    } else {
+        Err(format!("{} needs a boolean operand, found {}", context, ty))
+    }
+}
+
+fn comparable(left: &str, right: &str) -> Result<(), String> {
+    if left == right || (is_numeric(left) && is_numeric(right)) {
+        Ok(())
+    } else {
+        Err(format!("Cannot compare {} with {}", left, right))
+    }
+}
+
+fn unify(left: &'static str, right: &'static str) -> Result<&'static str, String> {
+    if left == right {
+        Ok(left)
+    } else if is_numeric(left) && is_numeric(right) {
+        Ok("float")
+    } else {
+        Err(format!("CASE branches mix {} and {}", left, right))
+    }
+}
+
+fn as_bools(vector: Vector) -> Result<Vec<bool>, String> {
+    match vector {
+        Vector::Bool(v) => Ok(v),
+        other => Err(format!("Expected a boolean, found {}", other.data_type())),
+    }
+}
+
+fn as_floats(vector: &Vector) -> Option<Vec<f64>> {
+    match vector {
+        Vector::Int(v) => Some(v.iter().map(|&x| x as f64).collect()),
+        Vector::Float(v) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+fn arith(op: ArithOp, left: Vector, right: Vector) -> Result<Vector, String> {
+    if let (Vector::Int(l), Vector::Int(r)) = (&left, &right) {
+        let mut out = Vec::with_capacity(l.len());
+        for (&a, &b) in l.iter().zip(r) {
+            out.push(match op {
+                ArithOp::Add => a.wrapping_add(b),
+                ArithOp::Sub => a.wrapping_sub(b),
+                ArithOp::Mul => a.wrapping_mul(b),
+                ArithOp::Div | ArithOp::Mod if b == 0 => return Err("Division by zero".to_string()),
+                ArithOp::Div => a.wrapping_div(b),
+                ArithOp::Mod => a.wrapping_rem(b),
+            });
+        }
+        return Ok(Vector::Int(out));
+    }
+
+    match (as_floats(&left), as_floats(&right)) {
+        (Some(l), Some(r)) => Ok(Vector::Float(
+            l.iter()
+                .zip(&r)
+                .map(|(a, b)| match op {
+                    ArithOp::Add => a + b,
+                    ArithOp::Sub => a - b,
+                    ArithOp::Mul => a * b,
+                    ArithOp::Div => a / b,
+                    ArithOp::Mod => a % b,
+                })
+                .collect(),
+        )),
+        _ => Err(format!("Arithmetic needs numeric operands, found {} and {}", left.data_type(), right.data_type())),
+    }
+}
+
+fn ordering_matches(ordering: Ordering, op: SimdOp) -> bool {
+    match op {
+        SimdOp::Eq => ordering == Ordering::Equal,
+        SimdOp::Ne => ordering != Ordering::Equal,
+        SimdOp::Lt => ordering == Ordering::Less,
+        SimdOp::Gt => ordering == Ordering::Greater,
+        SimdOp::Le => ordering != Ordering::Greater,
+        SimdOp::Ge => ordering != Ordering::Less,
+    }
+}
+
+fn compare(op: SimdOp, left: &Vector, right: &Vector) -> Result<Vector, String> {
+    let matched: Vec<bool> = match (left, right) {
+        (Vector::Int(l), Vector::Int(r)) => l.iter().zip(r).map(|(&a, &b)| compare_scalar(a, b, op)).collect(),
+        (Vector::Str(l), Vector::Str(r)) => l.iter().zip(r).map(|(a, b)| ordering_matches(a.cmp(b), op)).collect(),
+        (Vector::Bool(l), Vector::Bool(r)) => l.iter().zip(r).map(|(a, b)| ordering_matches(a.cmp(b), op)).collect(),
+        _ => match (as_floats(left), as_floats(right)) {
+            (Some(l), Some(r)) => l
+                .iter()
+                .zip(&r)
+                .map(|(a, b)| a.partial_cmp(b).is_some_and(|ordering| ordering_matches(ordering, op)))
+                .collect(),
+            _ => return Err(format!("Cannot compare {} with {}", left.data_type(), right.data_type())),
+        },
+    };
+    Ok(Vector::Bool(matched))
+}
+
+fn cast(vector: Vector, to: &str) -> Result<Vector, String> {
+    Ok(match (vector, to) {
+        (v, to) if v.data_type() == to => v,
+        (Vector::Int(v), "float") => Vector::Float(v.iter().map(|&x| x as f64).collect()),
+        (Vector::Int(v), "bool") => Vector::Bool(v.iter().map(|&x| x != 0).collect()),
+        (Vector::Float(v), "int") => Vector::Int(v.iter().map(|&x| x as i32).collect()),
+        (Vector::Bool(v), "int") => Vector::Int(v.iter().map(|&x| x as i32).collect()),
+        (Vector::Bool(v), "float") => Vector::Float(v.iter().map(|&x| x as i32 as f64).collect()),
+        (Vector::Str(v), "int") => Vector::Int(
+            v.iter()
+                .map(|s| s.trim().parse().map_err(|_| format!("Cannot cast '{}' to int", s)))
+                .collect::<Result<_, _>>()?,
+        ),
+        (Vector::Str(v), "float") => Vector::Float(
+            v.iter()
+                .map(|s| s.trim().parse().map_err(|_| format!("Cannot cast '{}' to float", s)))
+                .collect::<Result<_, _>>()?,
+        ),
+        (Vector::Str(v), "bool") => Vector::Bool(
+            v.iter()
+                .map(|s| match s.trim().to_lowercase().as_str() {
+                    "true" | "t" | "1" => Ok(true),
+                    "false" | "f" | "0" => Ok(false),
+                    _ => Err(format!("Cannot cast '{}' to bool", s)),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        (v, "string") => Vector::Str((0..v.len()).map(|row| v.display(row)).collect()),
+        (v, to) => return Err(format!("Cannot cast {} to {}", v.data_type(), to)),
+    })
+}
+
+/// Builds the output of a `CASE`: `values[i]` holds the values of the rows with `chosen` set to
+/// `i`, in row order, and each row takes the next one of its branch.
+fn pick(chosen: &[usize], values: Vec<Vector>) -> Result<Vector, String> {
+    let target = values.iter().skip(1).try_fold(values[0].data_type(), |ty, v| unify(ty, v.data_type()))?;
+    let values = values.into_iter().map(|v| cast(v, target)).collect::<Result<Vec<_>, _>>()?;
+    let mut next = vec![0; values.len()];
+    let positions: Vec<(usize, usize)> = chosen
+        .iter()
+        .map(|&i| {
+            next[i] += 1;
+            (i, next[i] - 1)
+        })
+        .collect();
+
+    Ok(match target {
+        "int" => Vector::Int(positions.iter().map(|&(i, pos)| match &values[i] {
+            Vector::Int(v) => v[pos],
+            _ => unreachable!(),
+        }).collect()),
+        "float" => Vector::Float(positions.iter().map(|&(i, pos)| match &values[i] {
+            Vector::Float(v) => v[pos],
+            _ => unreachable!(),
+        }).collect()),
+        "bool" => Vector::Bool(positions.iter().map(|&(i, pos)| match &values[i] {
+            Vector::Bool(v) => v[pos],
+            _ => unreachable!(),
+        }).collect()),
+        _ => Vector::Str(positions.iter().map(|&(i, pos)| values[i].display(pos)).collect()),
+    })
+}
+
+fn call(function: Function, args: Vec<Vector>) -> Result<Vector, String> {
+    let mut args = args.into_iter();
+    let strings = match args.next() {
+        Some(Vector::Str(v)) => v,
+        _ => return Err(format!("{} expects a string argument", function.name())),
+    };
+
+    Ok(match function {
+        Function::Upper => Vector::Str(strings.iter().map(|s| s.to_uppercase()).collect()),
+        Function::Lower => Vector::Str(strings.iter().map(|s| s.to_lowercase()).collect()),
+        Function::Length => Vector::Int(strings.iter().map(|s| s.chars().count() as i32).collect()),
+        Function::Substring => {
+            let starts = match args.next() {
+                Some(Vector::Int(v)) => v,
+                _ => return Err("substring expects an int start position".to_string()),
+            };
+            let lengths = match args.next() {
+                Some(Vector::Int(v)) => Some(v),
+                None => None,
+                _ => return Err("substring expects an int length".to_string()),
+            };
+
+            // SQL semantics: positions are 1-based and a start before 1 eats into the length.
+            Vector::Str(
+                strings
+                    .iter()
+                    .enumerate()
+                    .map(|(row, s)| {
+                        let start = starts[row] as i64;
+                        let skip = (start - 1).max(0) as usize;
+                        let take = match &lengths {
+                            Some(lengths) => (lengths[row] as i64 + (start - 1).min(0)).max(0) as usize,
+                            None => usize::MAX,
+                        };
+                        s.chars().skip(skip).take(take).collect()
+                    })
+                    .collect(),
+            )
+        }
+    })
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scalar::Int(v) => write!(f, "{}", v),
+            Scalar::Float(v) => write!(f, "{:?}", v),
+            Scalar::Str(v) => write!(f, "'{}'", v.replace('\'', "''")),
+            Scalar::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+pub fn op_symbol(op: SimdOp) -> &'static str {
+    match op {
+        SimdOp::Eq => "=",
+        SimdOp::Ne => "!=",
+        SimdOp::Lt => "<",
+        SimdOp::Gt => ">",
+        SimdOp::Le => "<=",
+        SimdOp::Ge => ">=",
+    }
+}
+
+/// Writes `expr`, parenthesized when it is itself a binary operation.
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Expr::Arith { .. } | Expr::Compare { .. } | Expr::And(..) | Expr::Or(..) | Expr::Between { .. } | Expr::In { .. } => {
+                write!(f, "({})", self.0)
+            }
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(scalar) => write!(f, "{}", scalar),
+            Expr::Neg(expr) => write!(f, "-{}", Operand(expr)),
+            Expr::Not(expr) => write!(f, "not {}", Operand(expr)),
+            Expr::Arith { op, left, right } => {
+                let symbol = match op {
+                    ArithOp::Add => "+",
+                    ArithOp::Sub => "-",
+                    ArithOp::Mul => "*",
+                    ArithOp::Div => "/",
+                    ArithOp::Mod => "%",
+                };
+                write!(f, "{} {} {}", Operand(left), symbol, Operand(right))
+            }
+            Expr::Compare { op, left, right } => write!(f, "{} {} {}", Operand(left), op_symbol(*op), Operand(right)),
+            Expr::And(left, right) => write!(f, "{} and {}", Operand(left), Operand(right)),
+            Expr::Or(left, right) => write!(f, "{} or {}", Operand(left), Operand(right)),
+            Expr::Between { expr, low, high, negated } => {
+                let not = if *negated { "not " } else { "" };
+                write!(f, "{} {}between {} and {}", Operand(expr), not, Operand(low), Operand(high))
+            }
+            Expr::In { expr, list, negated } => {
+                let not = if *negated { "not " } else { "" };
+                let items: Vec<String> = list.iter().map(|item| item.to_string()).collect();
+                write!(f, "{} {}in ({})", Operand(expr), not, items.join(", "))
+            }
+            Expr::Cast { expr, data_type } => write!(f, "cast({} as {})", expr, data_type),
+            Expr::Case { branches, otherwise } => {
+                write!(f, "case")?;
+                for (condition, value) in branches {
+                    write!(f, " when {} then {}", condition, value)?;
+                }
+                write!(f, " else {} end", otherwise)
+            }
+            Expr::Call { function, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", function.name(), args.join(", "))
+            }
+        }
+    }
+}
+
+/// Recursive descent parser over [`Token`]s, shared by expressions, predicates and projections.
+///
+/// Precedence from loosest to tightest: `or`, `and`, `not`, comparisons / `between` / `in`,
+/// `+ -`, `* / %`, unary minus.
+pub struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    pub fn new(input: &str) -> Result<Self, String> {
+        Ok(Self { tokens: tokenize(input)?, pos: 0 })
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    pub fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    pub fn is_keyword_at(&self, offset: usize, keyword: &str) -> bool {
+        matches!(self.peek_at(offset), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Consumes the next token if it is `keyword`.
+    pub fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword_at(0, keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}', found {:?}", keyword, self.peek()))
+        }
+    }
+
+    pub fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(format!("Expected {:?}, found {:?}", expected, other)),
+        }
+    }
+
+    pub fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("Unexpected token {:?}", token)),
+        }
+    }
+
+    pub fn expect_ident(&mut self) -> Result<String, String> {
+        match self.advance() {
+            Some(Token::Word(word)) if !is_keyword(&word) => Ok(word),
+            other => Err(format!("Expected a name, found {:?}", other)),
+        }
+    }
+
+    pub fn parse_projection(&mut self) -> Result<Vec<(Expr, String)>, String> {
+        let mut projection = Vec::new();
+        loop {
+            let expr = self.parse_expr()?;
+            let name = if self.eat_keyword("as") { self.expect_ident()? } else { expr.to_string() };
+            projection.push((expr, name));
+
+            if self.peek() == Some(&Token::Comma) {
+                self.advance();
+            } else {
+                return Ok(projection);
+            }
+        }
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.eat_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_not()?;
+        while self.eat_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_additive()?;
+
+        if let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            self.advance();
+            let right = self.parse_additive()?;
+            return Ok(Expr::Compare { op, left: Box::new(left), right: Box::new(right) });
+        }
+
+        let negated = self.is_keyword_at(0, "not") && (self.is_keyword_at(1, "between") || self.is_keyword_at(1, "in"));
+        if negated {
+            self.advance();
+        }
+
+        if self.eat_keyword("between") {
+            let low = self.parse_additive()?;
+            self.expect_keyword("and")?;
+            let high = self.parse_additive()?;
+            return Ok(Expr::Between { expr: Box::new(left), low: Box::new(low), high: Box::new(high), negated });
+        }
+
+        if self.eat_keyword("in") {
+            self.expect(Token::LParen)?;
+            let mut list = vec![self.parse_additive()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.advance();
+                list.push(self.parse_additive()?);
+            }
+            self.expect(Token::RParen)?;
+            return Ok(Expr::In { expr: Box::new(left), list, negated });
+        }
+
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => ArithOp::Add,
+                Some(Token::Minus) => ArithOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            expr = Expr::Arith { op, left: Box::new(expr), right: Box::new(right) };
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => ArithOp::Mul,
+                Some(Token::Slash) => ArithOp::Div,
+                Some(Token::Percent) => ArithOp::Mod,
+                _ => return Ok(expr),
+            };
+            self.advance();
+            let right = self.parse_unary()?;
+            expr = Expr::Arith { op, left: Box::new(expr), right: Box::new(right) };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.peek() != Some(&Token::Minus) {
+            return self.parse_primary();
+        }
+        self.advance();
+
+        // Fold negative literals so `-5` stays a literal the SIMD kernels can use.
+        match self.peek() {
+            Some(Token::Int(value)) => {
+                let value = -*value;
+                self.advance();
+                Ok(Expr::Literal(Scalar::Int(int_literal(value)?)))
+            }
+            Some(Token::Float(value)) => {
+                let value = -*value;
+                self.advance();
+                Ok(Expr::Literal(Scalar::Float(value)))
+            }
+            _ => Ok(Expr::Neg(Box::new(self.parse_unary()?))),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Int(value)) => Ok(Expr::Literal(Scalar::Int(int_literal(value)?))),
+            Some(Token::Float(value)) => Ok(Expr::Literal(Scalar::Float(value))),
+            Some(Token::Str(value)) => Ok(Expr::Literal(Scalar::Str(value))),
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Word(word)) => match word.to_lowercase().as_str() {
+                "true" => Ok(Expr::Literal(Scalar::Bool(true))),
+                "false" => Ok(Expr::Literal(Scalar::Bool(false))),
+                "case" => self.parse_case(),
+                "cast" => {
+                    self.expect(Token::LParen)?;
+                    let expr = self.parse_expr()?;
+                    self.expect_keyword("as")?;
+                    let data_type = type_name(&self.expect_ident()?)?.to_string();
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::Cast { expr: Box::new(expr), data_type })
+                }
+                _ if is_keyword(&word) => Err(format!("Unexpected keyword '{}'", word)),
+                _ => match Function::from_name(&word) {
+                    Some(function) if self.peek() == Some(&Token::LParen) => {
+                        self.advance();
+                        let mut args = vec![self.parse_expr()?];
+                        while self.peek() == Some(&Token::Comma) {
+                            self.advance();
+                            args.push(self.parse_expr()?);
+                        }
+                        self.expect(Token::RParen)?;
+                        Ok(Expr::Call { function, args })
+                    }
+                    _ => Ok(Expr::Column(word)),
+                },
+            },
+            Some(token) => Err(format!("Unexpected token {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_case(&mut self) -> Result<Expr, String> {
+        let mut branches = Vec::new();
+        while self.eat_keyword("when") {
+            let condition = self.parse_expr()?;
+            self.expect_keyword("then")?;
+            branches.push((condition, self.parse_expr()?));
+        }
+        if branches.is_empty() {
+            return Err("CASE needs at least one WHEN branch".to_string());
+        }
+
+        // An expression is only null where a column it reads is, so a row that takes no
+        // branch needs an ELSE to fall back on.
+        if !self.eat_keyword("else") {
+            return Err("CASE needs an ELSE branch".to_string());
+        }
+        let otherwise = self.parse_expr()?;
+        self.expect_keyword("end")?;
+
+        Ok(Expr::Case { branches, otherwise: Box::new(otherwise) })
+    }
+}
+
+fn int_literal(value: i64) -> Result<i32, String> {
+    i32::try_from(value).map_err(|_| format!("Integer literal {} does not fit in an int", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn table() -> TableSchema {
+        TableSchema::new("t".to_string(), ["a:int", "b:int", "price:float", "name:string"].map(String::from).to_vec())
+    }
+
+    fn batch() -> Batch {
+        let columns = HashMap::from([
+            ("a".to_string(), Vector::Int(vec![7, -3, 10, 0])),
+            ("b".to_string(), Vector::Int(vec![2, 0, 5, 4])),
+            ("price".to_string(), Vector::Float(vec![1.5, 2.0, -0.5, 4.25])),
+            ("name".to_string(), Vector::Str(vec!["alice".to_string(), "Bob".to_string(), "".to_string(), "dave".to_string()])),
+        ]);
+        Batch { columns, nulls: HashMap::new(), len: 4 }
+    }
+
+    fn eval(input: &str) -> Result<Vector, String> {
+        let expr = Expr::parse(input)?;
+        expr.result_type(&table())?;
+        expr.evaluate(&batch())
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence_and_promotes_to_float() {
+        assert_eq!(eval("a + b * 2 - -1").unwrap(), Vector::Int(vec![12, -2, 21, 9]));
+        assert_eq!(eval("(a + b) % 3").unwrap(), Vector::Int(vec![0, -0, 0, 1]));
+        assert_eq!(eval("a * price").unwrap(), Vector::Float(vec![10.5, -6.0, -5.0, 0.0]));
+        assert_eq!(Expr::parse("a / price").unwrap().result_type(&table()), Ok("float"));
+    }
+
+    #[test]
+    fn integer_division_by_zero_fails() {
+        assert_eq!(eval("a / b").unwrap_err(), "Division by zero");
+        assert_eq!(eval("a % b").unwrap_err(), "Division by zero");
+        assert!(Expr::parse("a / b").unwrap().may_fail());
+        assert!(!Expr::parse("a * b + 1 > 2").unwrap().may_fail());
+    }
+
+    #[test]
+    fn and_or_case_only_evaluate_the_rows_they_need() {
+        // Row 1 has `b = 0`: each guard keeps the division away from it.
+        assert_eq!(eval("b != 0 and a / b > 1").unwrap(), Vector::Bool(vec![true, false, true, false]));
+        assert_eq!(eval("b = 0 or a / b > 1").unwrap(), Vector::Bool(vec![true, true, true, false]));
+        assert_eq!(eval("case when b = 0 then 0 else a / b end").unwrap(), Vector::Int(vec![3, 0, 2, 0]));
+    }
+
+    #[test]
+    fn case_picks_the_first_matching_branch_and_unifies_types() {
+        let input = "case when a < 0 then 'negative' when a < 8 then 'small' else name end";
+        assert_eq!(Expr::parse(input).unwrap().result_type(&table()), Ok("string"));
+        assert_eq!(eval(input).unwrap(), Vector::Str(["small", "negative", "", "small"].map(String::from).to_vec()));
+        assert_eq!(eval("case when a > 5 then price else 0 end").unwrap(), Vector::Float(vec![1.5, 0.0, -0.5, 0.0]));
+    }
+
+    #[test]
+    fn between_in_and_string_functions() {
+        assert_eq!(eval("a between 0 and 7").unwrap(), Vector::Bool(vec![true, false, false, true]));
+        assert_eq!(eval("a not in (7, b, 1)").unwrap(), Vector::Bool(vec![false, true, true, true]));
+        assert_eq!(eval("upper(substring(name, 2, 3))").unwrap(), Vector::Str(["LIC", "OB", "", "AVE"].map(String::from).to_vec()));
+        assert_eq!(eval("length(name) + 1").unwrap(), Vector::Int(vec![6, 4, 1, 5]));
+        assert_eq!(eval("cast(a as string) = '10'").unwrap(), Vector::Bool(vec![false, false, true, false]));
+    }
+
+    #[test]
+    fn result_type_rejects_mismatched_operands() {
+        let table = table();
+        for input in ["name + 1", "a = 'x'", "not a", "a and b > 1", "upper(a)", "missing > 1", "cast(a as blob)"] {
+            assert!(Expr::parse(input).and_then(|expr| expr.result_type(&table)).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn projection_names_unnamed_entries_after_their_text() {
+        let projection = Expr::parse_projection("a + 1 as next, upper(name)").unwrap();
+        let names: Vec<&str> = projection.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(names, ["next", "upper(name)"]);
+        assert!(Expr::parse_projection("a as").is_err());
+    }
+}
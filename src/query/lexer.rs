@@ -0,0 +1,148 @@
+use crate::utils::simd::SimdOp;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Op(SimdOp),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Comma,
+    LParen,
+    RParen,
+}
+
+/// Words that cannot be used as bare column names.
+const KEYWORDS: &[&str] = &[
+    "and", "or", "not", "between", "in", "case", "when", "then", "else", "end", "cast", "as", "true", "false",
+    "select", "from", "where", "join", "on", "group", "order", "by", "asc", "desc", "limit",
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word.to_lowercase().as_str())
+}
+
+/// Splits query text into tokens.
+///
+/// Comparison operators are accepted both as symbols and as the `SimdOp` names
+/// (`eq`, `ne`, `lt`, `gt`, `le`, `ge`); string literals use single quotes with `''` as escape.
+/// Names may be qualified with their table, e.g. `users.age`.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let single = match c {
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            ',' => Some(Token::Comma),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            '%' => Some(Token::Percent),
+            _ => None,
+        };
+        if let Some(token) = single {
+            tokens.push(token);
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '=' | '!' | '<' | '>' => {
+                let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                let (op, width) = match two.as_str() {
+                    "==" => (SimdOp::Eq, 2),
+                    "!=" | "<>" => (SimdOp::Ne, 2),
+                    "<=" => (SimdOp::Le, 2),
+                    ">=" => (SimdOp::Ge, 2),
+                    _ => match c {
+                        '=' => (SimdOp::Eq, 1),
+                        '<' => (SimdOp::Lt, 1),
+                        '>' => (SimdOp::Gt, 1),
+                        _ => return Err(format!("Unexpected character '{}'", c)),
+                    },
+                };
+                tokens.push(Token::Op(op));
+                i += width;
+            }
+            '\'' => {
+                let mut literal = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            literal.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            literal.push(ch);
+                            i += 1;
+                        }
+                        None => return Err("Unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(literal));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let is_float = chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                if is_float {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let literal: String = chars[start..i].iter().collect();
+                if is_float {
+                    tokens.push(Token::Float(literal.parse().map_err(|_| format!("Invalid number '{}'", literal))?));
+                } else {
+                    tokens.push(Token::Int(literal.parse().map_err(|_| format!("Invalid integer '{}'", literal))?));
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                    if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_alphabetic() || *c == '_') {
+                        i += 1;
+                    }
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.to_lowercase().as_str() {
+                    "eq" => Token::Op(SimdOp::Eq),
+                    "ne" => Token::Op(SimdOp::Ne),
+                    "lt" => Token::Op(SimdOp::Lt),
+                    "gt" => Token::Op(SimdOp::Gt),
+                    "le" => Token::Op(SimdOp::Le),
+                    "ge" => Token::Op(SimdOp::Ge),
+                    _ => Token::Word(word),
+                };
+                tokens.push(token);
+            }
+            _ => return Err(format!("Unexpected character '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
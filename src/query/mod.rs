@@ -0,0 +1,7 @@
+pub mod cost;
+pub mod exec;
+pub mod expr;
+pub mod lexer;
+pub mod plan;
+pub mod predicate;
+pub mod sql;
@@ -0,0 +1,689 @@
+use std::{collections::HashMap, fmt};
+
+use super::{
+    cost::{build_left, CostModel},
+    expr::Expr,
+    predicate::Predicate,
+    sql::{AggregateFunc, Query, SelectItem},
+};
+use crate::storage::{
+    chunk::CHUNK_ROWS,
+    column::{Column, ColumnStore},
+    format::FORMAT_VERSION,
+    stats::TableStats,
+    table::TableSchema,
+    zonemap::ZoneMap,
+};
+use crate::utils::simd::SimdBackend;
+
+/// One aggregate computed per group by `Aggregate` / `HashAggregate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggregateFunc,
+    pub arg: Option<Expr>,
+    pub name: String,
+}
+
+/// What a query computes, independent of how the storage is read.
+///
+/// Column names are plain (`age`) for single-table queries and qualified (`users.age`)
+/// once a join brings a second table into scope.
+#[derive(Debug, Clone)]
+pub enum LogicalPlan {
+    Scan { table: TableSchema, columns: Vec<String>, qualify: bool },
+    Filter { input: Box<LogicalPlan>, predicate: Expr },
+    Join { left: Box<LogicalPlan>, right: Box<LogicalPlan>, left_key: String, right_key: String },
+    Project { input: Box<LogicalPlan>, exprs: Vec<(Expr, String)> },
+    Aggregate { input: Box<LogicalPlan>, group_by: Vec<(Expr, String)>, aggregates: Vec<Aggregate> },
+    Sort { input: Box<LogicalPlan>, keys: Vec<(Expr, bool)> },
+    Limit { input: Box<LogicalPlan>, limit: usize },
+}
+
+/// How a query is executed: filters fused into column scans with a chosen SIMD backend
+/// and zone maps, hash based joins and aggregation, and the row estimates behind those choices.
+#[derive(Debug, Clone)]
+pub enum PhysicalPlan {
+    ColumnScan {
+        table: TableSchema,
+        columns: Vec<String>,
+        qualify: bool,
+        filter: Option<Predicate>,
+        /// Kernel family per filtered int column.
+        backends: HashMap<String, SimdBackend>,
+        zone_maps: HashMap<String, ZoneMap>,
+        /// Bytes of `.idx` files read while planning to build `zone_maps`.
+        index_bytes: u64,
+        row_count: usize,
+        chunk_count: usize,
+        pruned_chunks: usize,
+        estimated_rows: f64,
+    },
+    Filter { input: Box<PhysicalPlan>, predicate: Expr, estimated_rows: f64 },
+    HashJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        left_key: String,
+        right_key: String,
+        build_left: bool,
+        estimated_rows: f64,
+    },
+    Project { input: Box<PhysicalPlan>, exprs: Vec<(Expr, String)> },
+    HashAggregate { input: Box<PhysicalPlan>, group_by: Vec<(Expr, String)>, aggregates: Vec<Aggregate> },
+    Sort { input: Box<PhysicalPlan>, keys: Vec<(Expr, bool)> },
+    Limit { input: Box<PhysicalPlan>, limit: usize },
+}
+
+/// The tables a query can see and how its column references resolve against them.
+struct Scope<'a> {
+    tables: Vec<&'a TableSchema>,
+    qualify: bool,
+}
+
+impl Scope<'_> {
+    fn resolve(&self, name: &str) -> Result<String, String> {
+        let (table, column) = match name.split_once('.') {
+            Some((table, column)) => {
+                let table = self
+                    .tables
+                    .iter()
+                    .find(|t| t.table_name == table)
+                    .ok_or_else(|| format!("Table '{}' is not part of the query", table))?;
+                if !table.columns.iter().any(|c| c.name == column) {
+                    return Err(format!("Column '{}' not found in table '{}'", column, table.table_name));
+                }
+                (*table, column)
+            }
+            None => {
+                let owners: Vec<&&TableSchema> = self.tables.iter().filter(|t| t.columns.iter().any(|c| c.name == name)).collect();
+                match owners.as_slice() {
+                    [table] => (**table, name),
+                    [] => return Err(format!("Column '{}' not found", name)),
+                    _ => return Err(format!("Column '{}' is ambiguous, qualify it with its table", name)),
+                }
+            }
+        };
+
+        Ok(if self.qualify { format!("{}.{}", table.table_name, column) } else { column.to_string() })
+    }
+
+    fn bind(&self, expr: &Expr) -> Result<Expr, String> {
+        expr.map_columns(&mut |name| self.resolve(name))
+    }
+
+    /// The table a resolved column name belongs to.
+    fn owner(&self, resolved: &str) -> &TableSchema {
+        match resolved.split_once('.') {
+            Some((table, _)) if self.qualify => self.tables.iter().find(|t| t.table_name == table).unwrap(),
+            _ => self.tables[0],
+        }
+    }
+
+    /// A schema over every resolved column name, used to type check bound expressions.
+    fn relation_schema(&self) -> TableSchema {
+        let mut columns = Vec::new();
+        for table in &self.tables {
+            for column in &table.columns {
+                let name = if self.qualify { format!("{}.{}", table.table_name, column.name) } else { column.name.clone() };
+                columns.push(Column { name, data_type: column.data_type.clone(), nullable: column.nullable });
+            }
+        }
+        let table_name = self.tables.iter().map(|t| t.table_name.as_str()).collect::<Vec<_>>().join(" join ");
+        TableSchema { table_name, columns, format_version: FORMAT_VERSION }
+    }
+}
+
+impl LogicalPlan {
+    /// Binds `query` against the known tables and builds its logical plan.
+    ///
+    /// Conjuncts of the `where` clause that only touch one table are pushed down to that
+    /// table's scan; the rest are applied after the join.
+    pub fn from_query(query: &Query, tables: &HashMap<String, TableSchema>) -> Result<LogicalPlan, String> {
+        let lookup = |name: &str| tables.get(name).ok_or_else(|| format!("Table '{}' not found.", name));
+        let from = lookup(&query.from)?;
+        let mut scope = Scope { tables: vec![from], qualify: false };
+        if let Some(join) = &query.join {
+            if join.table == query.from {
+                return Err(format!("Joining '{}' with itself is not supported", join.table));
+            }
+            scope.tables.push(lookup(&join.table)?);
+            scope.qualify = true;
+        }
+        let relation = scope.relation_schema();
+
+        // Select list, with `*` expanded to every column in scope.
+        let mut items = Vec::new();
+        for item in &query.items {
+            match item {
+                SelectItem::Star => {
+                    for column in &relation.columns {
+                        items.push(SelectItem::Expr { expr: Expr::Column(column.name.clone()), name: column.name.clone() });
+                    }
+                }
+                SelectItem::Expr { expr, name } => items.push(SelectItem::Expr { expr: scope.bind(expr)?, name: name.clone() }),
+                SelectItem::Aggregate { func, arg, name } => {
+                    let arg = arg.as_ref().map(|arg| scope.bind(arg)).transpose()?;
+                    items.push(SelectItem::Aggregate { func: *func, arg, name: name.clone() });
+                }
+            }
+        }
+
+        // Where clause, split into per-table and cross-table conjuncts.
+        let mut pushed: Vec<Vec<Expr>> = vec![Vec::new(); scope.tables.len()];
+        let mut residual = Vec::new();
+        for conjunct in query.filter.clone().map(Expr::conjuncts).unwrap_or_default() {
+            let bound = scope.bind(&conjunct)?;
+            match bound.result_type(&relation)? {
+                "bool" => {}
+                other => return Err(format!("Filter '{}' produces {}, expected bool", bound, other)),
+            }
+
+            let owners: Vec<&str> = bound.columns().iter().map(|c| scope.owner(c).table_name.as_str()).collect();
+            let single = owners.first().filter(|first| owners.iter().all(|o| o == *first));
+            match single {
+                Some(owner) if scope.qualify => {
+                    let index = scope.tables.iter().position(|t| &t.table_name == owner).unwrap();
+                    pushed[index].push(bound.map_columns(&mut |name| Ok(name.split_once('.').unwrap().1.to_string()))?);
+                }
+                _ if !scope.qualify => pushed[0].push(bound),
+                _ => residual.push(bound),
+            }
+        }
+
+        let join_keys = match &query.join {
+            Some(join) => {
+                let (left, right) = (scope.resolve(&join.left)?, scope.resolve(&join.right)?);
+                let (left_owner, right_owner) = (scope.owner(&left).table_name.clone(), scope.owner(&right).table_name.clone());
+                if left_owner == right_owner {
+                    return Err(format!("Join keys '{}' and '{}' must come from different tables", left, right));
+                }
+                let (left, right) = if left_owner == query.from { (left, right) } else { (right, left) };
+                let (left_type, right_type) = (Expr::Column(left.clone()).result_type(&relation)?, Expr::Column(right.clone()).result_type(&relation)?);
+                if left_type != right_type {
+                    return Err(format!("Cannot join {} column '{}' with {} column '{}'", left_type, left, right_type, right));
+                }
+                Some((left, right))
+            }
+            None => None,
+        };
+
+        // Everything above the scans, bound and type checked.
+        let aggregated = query.has_aggregates();
+        let mut group_by = Vec::new();
+        let mut aggregates = Vec::new();
+        let mut projection = Vec::new();
+        if aggregated {
+            for expr in &query.group_by {
+                let bound = scope.bind(expr)?;
+                bound.result_type(&relation)?;
+                group_by.push((bound.clone(), bound.to_string()));
+            }
+            for item in &items {
+                match item {
+                    SelectItem::Expr { expr, name } => {
+                        let Some((_, group_name)) = group_by.iter().find(|(group, _)| group == expr) else {
+                            return Err(format!("'{}' must appear in GROUP BY or be used in an aggregate", expr));
+                        };
+                        projection.push((Expr::Column(group_name.clone()), name.clone()));
+                    }
+                    SelectItem::Aggregate { func, arg, name } => {
+                        if let Some(arg) = arg {
+                            let ty = arg.result_type(&relation)?;
+                            if matches!(func, AggregateFunc::Sum | AggregateFunc::Avg) && ty != "int" && ty != "float" {
+                                return Err(format!("{} needs a numeric argument, found {}", func, ty));
+                            }
+                        }
+                        aggregates.push(Aggregate { func: *func, arg: arg.clone(), name: name.clone() });
+                        projection.push((Expr::Column(name.clone()), name.clone()));
+                    }
+                    SelectItem::Star => unreachable!(),
+                }
+            }
+        } else {
+            for item in &items {
+                if let SelectItem::Expr { expr, name } = item {
+                    expr.result_type(&relation)?;
+                    projection.push((expr.clone(), name.clone()));
+                }
+            }
+        }
+
+        // Order by output names when possible, otherwise (without aggregation) by input columns.
+        let output_names: Vec<&str> = projection.iter().map(|(_, name)| name.as_str()).collect();
+        let sort_above = query
+            .order_by
+            .iter()
+            .all(|(key, _)| key.columns().iter().all(|c| output_names.contains(c)));
+        let sort_keys: Vec<(Expr, bool)> = if sort_above {
+            query.order_by.clone()
+        } else if aggregated {
+            return Err("ORDER BY of an aggregate query must use names from the select list".to_string());
+        } else {
+            query
+                .order_by
+                .iter()
+                .map(|(key, ascending)| Ok((scope.bind(key)?, *ascending)))
+                .collect::<Result<_, String>>()?
+        };
+        if !sort_above {
+            for (key, _) in &sort_keys {
+                key.result_type(&relation)?;
+            }
+        }
+
+        // Columns each scan has to produce for the operators above it.
+        let mut needed: Vec<String> = Vec::new();
+        let mut need = |expr: &Expr| {
+            for column in expr.columns() {
+                if !needed.iter().any(|n| n == column) {
+                    needed.push(column.to_string());
+                }
+            }
+        };
+        if aggregated {
+            group_by.iter().for_each(|(expr, _)| need(expr));
+            aggregates.iter().filter_map(|a| a.arg.as_ref()).for_each(&mut need);
+        } else {
+            projection.iter().for_each(|(expr, _)| need(expr));
+        }
+        if !sort_above {
+            sort_keys.iter().for_each(|(key, _)| need(key));
+        }
+        residual.iter().for_each(&mut need);
+        let join_key_names: Vec<String> = join_keys.iter().flat_map(|(l, r)| [l.clone(), r.clone()]).collect();
+
+        let mut inputs = Vec::new();
+        for (index, table) in scope.tables.iter().enumerate() {
+            let columns: Vec<String> = relation
+                .columns
+                .iter()
+                .map(|c| c.name.clone())
+                .filter(|name| scope.owner(name).table_name == table.table_name)
+                .filter(|name| needed.contains(name) || join_key_names.contains(name))
+                .map(|name| name.split_once('.').filter(|_| scope.qualify).map_or(name.clone(), |(_, c)| c.to_string()))
+                .collect();
+            let columns = if columns.is_empty() {
+                // Nothing to read but the row count, which any column can tell; prefer one the filter reads anyway.
+                let filtered = pushed[index].iter().flat_map(Expr::columns).next();
+                let name = filtered.or(table.columns.first().map(|c| c.name.as_str()));
+                name.map(str::to_string).into_iter().collect()
+            } else {
+                columns
+            };
+
+            let mut plan = LogicalPlan::Scan { table: (*table).clone(), columns, qualify: scope.qualify };
+            if let Some(predicate) = and_all(std::mem::take(&mut pushed[index])) {
+                plan = LogicalPlan::Filter { input: Box::new(plan), predicate };
+            }
+            inputs.push(plan);
+        }
+
+        let mut plan = inputs.remove(0);
+        if let Some((left_key, right_key)) = join_keys {
+            plan = LogicalPlan::Join { left: Box::new(plan), right: Box::new(inputs.remove(0)), left_key, right_key };
+        }
+        if let Some(predicate) = and_all(residual) {
+            plan = LogicalPlan::Filter { input: Box::new(plan), predicate };
+        }
+        if aggregated {
+            plan = LogicalPlan::Aggregate { input: Box::new(plan), group_by, aggregates };
+        }
+        if !sort_above && !sort_keys.is_empty() {
+            plan = LogicalPlan::Sort { input: Box::new(plan), keys: sort_keys.clone() };
+        }
+        plan = LogicalPlan::Project { input: Box::new(plan), exprs: projection };
+        if sort_above && !sort_keys.is_empty() {
+            plan = LogicalPlan::Sort { input: Box::new(plan), keys: sort_keys };
+        }
+        if let Some(limit) = query.limit {
+            plan = LogicalPlan::Limit { input: Box::new(plan), limit };
+        }
+
+        Ok(plan)
+    }
+}
+
+/// Parses `sql` and plans it against the known tables.
+pub fn plan_query(sql: &str, tables: &HashMap<String, TableSchema>, store: &ColumnStore) -> Result<(LogicalPlan, PhysicalPlan), String> {
+    let logical = LogicalPlan::from_query(&Query::parse(sql)?, tables)?;
+    let physical = PhysicalPlan::from_logical(&logical, store)?;
+    Ok((logical, physical))
+}
+
+/// Distinct values of a qualified join key (`table.column`) from the table's statistics.
+fn key_distinct_count(key: &str, store: &ColumnStore) -> Option<u64> {
+    let (table, column) = key.split_once('.')?;
+    Some(TableStats::load(&store.base_path, table)?.column(column)?.distinct_count)
+}
+
+fn and_all(exprs: Vec<Expr>) -> Option<Expr> {
+    exprs.into_iter().reduce(|left, right| Expr::And(Box::new(left), Box::new(right)))
+}
+
+impl PhysicalPlan {
+    /// Chooses how to run `logical`: filters over a scan become SIMD predicates evaluated
+    /// inside the scan, with the zone maps of the filtered int columns loaded up front.
+    pub fn from_logical(logical: &LogicalPlan, store: &ColumnStore) -> Result<PhysicalPlan, String> {
+        Ok(match logical {
+            LogicalPlan::Scan { .. } => Self::scan(logical, None, store)?,
+            LogicalPlan::Filter { input, predicate } => match input.as_ref() {
+                LogicalPlan::Scan { table, .. } => {
+                    let predicate = Predicate::from_expr(predicate.clone(), table);
+                    predicate.validate(table)?;
+                    Self::scan(input, Some(predicate), store)?
+                }
+                _ => {
+                    let input = Self::from_logical(input, store)?;
+                    let estimated_rows = input.estimated_rows() / 3.0;
+                    PhysicalPlan::Filter { input: Box::new(input), predicate: predicate.clone(), estimated_rows }
+                }
+            },
+            LogicalPlan::Join { left, right, left_key, right_key } => {
+                let (left, right) = (Self::from_logical(left, store)?, Self::from_logical(right, store)?);
+                let (left_rows, right_rows) = (left.estimated_rows(), right.estimated_rows());
+
+                // With distinct counts every key value on the side with fewer of them is assumed
+                // to find its matches on the other; without them, each row matches about once.
+                let distinct = [left_key, right_key].map(|key| key_distinct_count(key, store)).into_iter().flatten().max();
+                let estimated_rows = match distinct {
+                    Some(distinct) => left_rows * right_rows / distinct.max(1) as f64,
+                    None => left_rows.max(right_rows),
+                };
+                PhysicalPlan::HashJoin {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    left_key: left_key.clone(),
+                    right_key: right_key.clone(),
+                    build_left: build_left(left_rows, right_rows),
+                    estimated_rows,
+                }
+            }
+            LogicalPlan::Project { input, exprs } => PhysicalPlan::Project { input: Box::new(Self::from_logical(input, store)?), exprs: exprs.clone() },
+            LogicalPlan::Aggregate { input, group_by, aggregates } => PhysicalPlan::HashAggregate {
+                input: Box::new(Self::from_logical(input, store)?),
+                group_by: group_by.clone(),
+                aggregates: aggregates.clone(),
+            },
+            LogicalPlan::Sort { input, keys } => PhysicalPlan::Sort { input: Box::new(Self::from_logical(input, store)?), keys: keys.clone() },
+            LogicalPlan::Limit { input, limit } => PhysicalPlan::Limit { input: Box::new(Self::from_logical(input, store)?), limit: *limit },
+        })
+    }
+
+    /// A column scan with `filter` fused in. The cost model orders the filter's conjuncts,
+    /// picks the SIMD backend per filtered int column and decides which columns' zone maps
+    /// are worth loading.
+    fn scan(logical: &LogicalPlan, filter: Option<Predicate>, store: &ColumnStore) -> Result<PhysicalPlan, String> {
+        let LogicalPlan::Scan { table, columns, qualify } = logical else {
+            unreachable!()
+        };
+
+        let stats = TableStats::load(&store.base_path, &table.table_name);
+        let model = CostModel::new(table, stats.as_ref());
+        let row_count = store.row_count(table);
+        let chunk_count = row_count.div_ceil(CHUNK_ROWS);
+        let mut backends = HashMap::new();
+        let mut zone_maps = HashMap::new();
+        let mut index_bytes = 0;
+        let mut pruned_chunks = 0;
+        let mut selectivity = 1.0;
+
+        let filter = filter.map(|filter| model.order(filter));
+        if let Some(filter) = &filter {
+            let mut read: Vec<&str> = columns.iter().map(String::as_str).collect();
+            for column in filter.columns() {
+                if !read.contains(&column) {
+                    read.push(column);
+                }
+            }
+            let scan_bytes: f64 = read.iter().map(|c| model.column_bytes(c)).sum();
+
+            for column in filter.columns() {
+                if table.columns.iter().all(|c| c.name != column || c.data_type != "int") {
+                    continue;
+                }
+                backends.insert(column.to_string(), model.choose_backend(row_count));
+
+                let column_index_bytes = store.index_bytes(table, column);
+                let column_selectivity = model.selectivity(&filter.restricted_to(column));
+                if model.use_zone_map(column_index_bytes, row_count, scan_bytes, column_selectivity) {
+                    if let Some(zone_map) = store.load_zone_map(table, column) {
+                        zone_maps.insert(column.to_string(), zone_map);
+                        index_bytes += column_index_bytes;
+                    }
+                }
+            }
+            pruned_chunks = (0..chunk_count).filter(|&chunk| !filter.chunk_may_match(&zone_maps, chunk)).count();
+            selectivity = model.selectivity(filter);
+        }
+
+        let surviving = if chunk_count == 0 { 0.0 } else { (chunk_count - pruned_chunks) as f64 / chunk_count as f64 };
+        Ok(PhysicalPlan::ColumnScan {
+            table: table.clone(),
+            columns: columns.clone(),
+            qualify: *qualify,
+            filter,
+            backends,
+            zone_maps,
+            index_bytes,
+            row_count,
+            chunk_count,
+            pruned_chunks,
+            estimated_rows: row_count as f64 * selectivity.min(surviving),
+        })
+    }
+
+    pub fn estimated_rows(&self) -> f64 {
+        match self {
+            PhysicalPlan::ColumnScan { estimated_rows, .. }
+            | PhysicalPlan::Filter { estimated_rows, .. }
+            | PhysicalPlan::HashJoin { estimated_rows, .. } => *estimated_rows,
+            PhysicalPlan::Project { input, .. } | PhysicalPlan::Sort { input, .. } => input.estimated_rows(),
+            PhysicalPlan::HashAggregate { input, group_by, .. } => {
+                if group_by.is_empty() { 1.0 } else { input.estimated_rows().sqrt().ceil() }
+            }
+            PhysicalPlan::Limit { input, limit } => input.estimated_rows().min(*limit as f64),
+        }
+    }
+
+    /// Operators directly below this one.
+    pub fn children(&self) -> Vec<&PhysicalPlan> {
+        match self {
+            PhysicalPlan::ColumnScan { .. } => Vec::new(),
+            PhysicalPlan::HashJoin { left, right, .. } => vec![left, right],
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::Project { input, .. }
+            | PhysicalPlan::HashAggregate { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::Limit { input, .. } => vec![input],
+        }
+    }
+
+    /// Tables read by the scans of this plan.
+    pub fn tables(&self) -> Vec<&TableSchema> {
+        match self {
+            PhysicalPlan::ColumnScan { table, .. } => vec![table],
+            _ => self.children().into_iter().flat_map(PhysicalPlan::tables).collect(),
+        }
+    }
+
+    /// One line describing this operator alone, as shown by `explain`.
+    pub fn describe(&self) -> String {
+        let list = |exprs: &[(Expr, String)]| {
+            exprs
+                .iter()
+                .map(|(expr, name)| if expr.to_string() == *name { name.clone() } else { format!("{} as {}", expr, name) })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            PhysicalPlan::ColumnScan { table, columns, filter, backends, zone_maps, row_count, chunk_count, pruned_chunks, estimated_rows, .. } => {
+                let mut line = format!("ColumnScan {} [{}] rows={}", table.table_name, columns.join(", "), row_count);
+                if let Some(filter) = filter {
+                    let access: Vec<String> = filter
+                        .columns()
+                        .into_iter()
+                        .filter_map(|c| backends.get(c).map(|b| (c, b)))
+                        .map(|(c, b)| if zone_maps.contains_key(c) { format!("{}: {} + zone map", c, b) } else { format!("{}: {}", c, b) })
+                        .collect();
+                    line += &format!(" filter=({}) access=[{}]", filter, access.join(", "));
+                    if !zone_maps.is_empty() {
+                        line += &format!(" zone-map pruned {} of {} chunks", pruned_chunks, chunk_count);
+                    }
+                }
+                line + &format!(" est. rows={:.0}", estimated_rows)
+            }
+            PhysicalPlan::Filter { predicate, estimated_rows, .. } => format!("Filter ({}) est. rows={:.0}", predicate, estimated_rows),
+            PhysicalPlan::HashJoin { left_key, right_key, build_left, estimated_rows, .. } => {
+                let build = if *build_left { "left" } else { "right" };
+                format!("HashJoin on {} = {} build={} est. rows={:.0}", left_key, right_key, build, estimated_rows)
+            }
+            PhysicalPlan::Project { exprs, .. } => format!("Project [{}]", list(exprs)),
+            PhysicalPlan::HashAggregate { group_by, aggregates, .. } => {
+                let aggregates: Vec<String> = aggregates.iter().map(|a| a.name.clone()).collect();
+                format!("HashAggregate group by [{}] compute [{}]", list(group_by), aggregates.join(", "))
+            }
+            PhysicalPlan::Sort { keys, .. } => {
+                let keys: Vec<String> = keys.iter().map(|(key, asc)| format!("{} {}", key, if *asc { "asc" } else { "desc" })).collect();
+                format!("Sort [{}]", keys.join(", "))
+            }
+            PhysicalPlan::Limit { limit, .. } => format!("Limit {}", limit),
+        }
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(f, "{}{}", "  ".repeat(depth), self.describe())?;
+        for child in self.children() {
+            child.fmt_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PhysicalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+impl LogicalPlan {
+    fn fmt_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            LogicalPlan::Scan { table, columns, .. } => writeln!(f, "{}Scan {} [{}]", indent, table.table_name, columns.join(", ")),
+            LogicalPlan::Filter { input, predicate } => {
+                writeln!(f, "{}Filter ({})", indent, predicate)?;
+                input.fmt_tree(f, depth + 1)
+            }
+            LogicalPlan::Join { left, right, left_key, right_key } => {
+                writeln!(f, "{}Join on {} = {}", indent, left_key, right_key)?;
+                left.fmt_tree(f, depth + 1)?;
+                right.fmt_tree(f, depth + 1)
+            }
+            LogicalPlan::Project { input, exprs } => {
+                let names: Vec<&str> = exprs.iter().map(|(_, name)| name.as_str()).collect();
+                writeln!(f, "{}Project [{}]", indent, names.join(", "))?;
+                input.fmt_tree(f, depth + 1)
+            }
+            LogicalPlan::Aggregate { input, group_by, aggregates } => {
+                let groups: Vec<&str> = group_by.iter().map(|(_, name)| name.as_str()).collect();
+                let aggregates: Vec<&str> = aggregates.iter().map(|a| a.name.as_str()).collect();
+                writeln!(f, "{}Aggregate group by [{}] compute [{}]", indent, groups.join(", "), aggregates.join(", "))?;
+                input.fmt_tree(f, depth + 1)
+            }
+            LogicalPlan::Sort { input, keys } => {
+                let keys: Vec<String> = keys.iter().map(|(key, asc)| format!("{} {}", key, if *asc { "asc" } else { "desc" })).collect();
+                writeln!(f, "{}Sort [{}]", indent, keys.join(", "))?;
+                input.fmt_tree(f, depth + 1)
+            }
+            LogicalPlan::Limit { input, limit } => {
+                writeln!(f, "{}Limit {}", indent, limit)?;
+                input.fmt_tree(f, depth + 1)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LogicalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store in a directory of its own holding `users` and `orders`.
+    fn store(name: &str) -> (ColumnStore, HashMap<String, TableSchema>) {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+
+        let users = TableSchema::new("users".to_string(), ["id:int", "age:int", "name:string"].map(String::from).to_vec());
+        let orders = TableSchema::new("orders".to_string(), ["user_id:int", "amount:int"].map(String::from).to_vec());
+        for row in [["1", "34", "ann"], ["2", "27", "bob"], ["3", "41", "cid"], ["4", "27", "dee"]] {
+            store.insert_row(&users, row.to_vec()).unwrap();
+        }
+        for row in [["1", "10"], ["3", "5"], ["1", "7"], ["2", "30"], ["9", "1"]] {
+            store.insert_row(&orders, row.to_vec()).unwrap();
+        }
+
+        let tables = HashMap::from([("users".to_string(), users), ("orders".to_string(), orders)]);
+        (store, tables)
+    }
+
+    fn run(sql: &str, store: &ColumnStore, tables: &HashMap<String, TableSchema>) -> Vec<String> {
+        let (_, physical) = plan_query(sql, tables, store).unwrap();
+        let relation = physical.execute(store).unwrap();
+        (0..relation.len)
+            .map(|row| relation.vectors.iter().map(|v| v.display(row)).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn single_table_conjuncts_are_pushed_below_the_join() {
+        let (store, tables) = store("plan-pushdown");
+        let sql = "select name, amount from users join orders on user_id = id where age > 30 and amount > 5 and age > amount";
+        let (logical, physical) = plan_query(sql, &tables, &store).unwrap();
+
+        let LogicalPlan::Project { input, .. } = logical else { panic!("{}", logical) };
+        let LogicalPlan::Filter { input, predicate } = *input else { panic!() };
+        assert_eq!(predicate.to_string(), "users.age > orders.amount");
+        let LogicalPlan::Join { left, right, left_key, right_key } = *input else { panic!() };
+        assert_eq!((left_key.as_str(), right_key.as_str()), ("users.id", "orders.user_id"));
+        assert!(matches!(*left, LogicalPlan::Filter { ref predicate, .. } if predicate.to_string() == "age > 30"));
+        assert!(matches!(*right, LogicalPlan::Filter { ref predicate, .. } if predicate.to_string() == "amount > 5"));
+
+        // The pushed filters run inside the scans as SIMD predicates.
+        let scans: Vec<&PhysicalPlan> = physical.children()[0].children()[0].children();
+        assert!(scans.iter().all(|scan| matches!(scan, PhysicalPlan::ColumnScan { filter: Some(_), .. })));
+        assert_eq!(run(sql, &store, &tables), ["ann 10", "ann 7"]);
+        std::fs::remove_dir_all(&store.base_path).unwrap();
+    }
+
+    #[test]
+    fn aggregates_group_sort_and_limit() {
+        let (store, tables) = store("plan-aggregate");
+        assert_eq!(
+            run("select age, count(*), max(name) from users group by age order by age desc", &store, &tables),
+            ["41 1 cid", "34 1 ann", "27 2 dee"],
+        );
+        assert_eq!(run("select sum(amount), avg(amount) from orders where user_id != 9", &store, &tables), ["52 13"]);
+        assert_eq!(run("select name from users order by age, name desc limit 3", &store, &tables), ["dee", "bob", "ann"]);
+        std::fs::remove_dir_all(&store.base_path).unwrap();
+    }
+
+    #[test]
+    fn binding_errors_name_the_problem() {
+        let (store, tables) = store("plan-errors");
+        let error = |sql: &str| plan_query(sql, &tables, &store).unwrap_err();
+        assert!(error("select * from missing").contains("not found"));
+        assert!(error("select nope from users").contains("not found"));
+        assert!(error("select * from users join orders on id = age").contains("different tables"));
+        assert!(error("select * from users join orders on name = user_id").contains("Cannot join"));
+        assert!(error("select * from users join users on id = id").contains("itself"));
+        assert!(error("select name, count(*) from users group by age").contains("GROUP BY"));
+        assert!(error("select age from users where name").contains("expected bool"));
+        assert!(error("select sum(name) from users").contains("numeric"));
+        std::fs::remove_dir_all(&store.base_path).unwrap();
+    }
+}
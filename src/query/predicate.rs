@@ -0,0 +1,579 @@
+use std::{collections::HashMap, fmt};
+
+use super::expr::{op_symbol, Expr, Scalar};
+use crate::storage::{chunk::{ColumnChunks, CHUNK_ROWS}, stats::TableStats, table::TableSchema, vector::Vector, zonemap::ZoneMap};
+use crate::utils::bitmap::Bitmap;
+use crate::utils::simd::{
+    combine_bitmaps, filter_between_simd_32_avx_bitmap, filter_between_simd_32_bitmap, filter_columns_simd_32_avx_bitmap, filter_columns_simd_32_bitmap,
+    filter_in_simd_32_avx_bitmap, filter_in_simd_32_bitmap, filter_simd_32_avx_bitmap, filter_simd_32_bitmap, LogicalOp, SimdBackend, SimdOp,
+};
+
+/// A boolean filter over the columns of a table.
+///
+/// Leaves over int columns compare a column against a scalar (or another column of the same table)
+/// with one of the SIMD kernels, anything else is kept as a computed [`Expr`] evaluated per batch.
+/// Inner nodes combine the selection bitmaps of their children.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Compare { column: String, op: SimdOp, value: i32 },
+    CompareColumns { left: String, op: SimdOp, right: String },
+    Between { column: String, low: i32, high: i32 },
+    In { column: String, values: Vec<i32> },
+    Expr(Expr),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Parses and validates a predicate such as `age gt 25 and (id < 5 or not age = 54)`.
+    ///
+    /// Comparisons accept both the `SimdOp` names (`eq`, `ne`, `lt`, `gt`, `le`, `ge`)
+    /// and their symbols; `not` binds tighter than `and`, which binds tighter than `or`.
+    /// A column name on the right-hand side compares two columns, e.g. `start_ts < end_ts`.
+    /// Ranges and lists are written `age between 20 and 30` and `age in (20, 54, 65)`,
+    /// either of which may be negated as `age not between ...` / `age not in (...)`.
+    /// Any other boolean expression, e.g. `price * qty > 100` or `upper(name) = 'ALICE'`, is accepted too.
+    pub fn parse(input: &str, table: &TableSchema) -> Result<Predicate, String> {
+        let predicate = Predicate::from_expr(Expr::parse(input)?, table);
+        predicate.validate(table)?;
+        Ok(predicate)
+    }
+
+    /// Splits a boolean expression into SIMD leaves where the shape allows it.
+    pub fn from_expr(expr: Expr, table: &TableSchema) -> Predicate {
+        let is_int_column = |expr: &Expr| match expr {
+            Expr::Column(name) => table.columns.iter().any(|c| &c.name == name && c.data_type == "int"),
+            _ => false,
+        };
+        let int_literal = |expr: &Expr| match expr {
+            Expr::Literal(Scalar::Int(value)) => Some(*value),
+            _ => None,
+        };
+        let column_name = |expr: &Expr| match expr {
+            Expr::Column(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        match expr {
+            Expr::And(left, right) => flatten(vec![Self::from_expr(*left, table), Self::from_expr(*right, table)], LogicalOp::And),
+            Expr::Or(left, right) => flatten(vec![Self::from_expr(*left, table), Self::from_expr(*right, table)], LogicalOp::Or),
+            Expr::Not(inner) => Predicate::Not(Box::new(Self::from_expr(*inner, table))),
+            Expr::Compare { op, left, right } if is_int_column(&left) && int_literal(&right).is_some() => {
+                Predicate::Compare { column: column_name(&left), op, value: int_literal(&right).unwrap() }
+            }
+            Expr::Compare { op, left, right } if int_literal(&left).is_some() && is_int_column(&right) => {
+                Predicate::Compare { column: column_name(&right), op: flip(op), value: int_literal(&left).unwrap() }
+            }
+            Expr::Compare { op, left, right } if is_int_column(&left) && is_int_column(&right) => {
+                Predicate::CompareColumns { left: column_name(&left), op, right: column_name(&right) }
+            }
+            Expr::Between { expr, low, high, negated } if is_int_column(&expr) && int_literal(&low).is_some() && int_literal(&high).is_some() => {
+                let between = Predicate::Between { column: column_name(&expr), low: int_literal(&low).unwrap(), high: int_literal(&high).unwrap() };
+                if negated { Predicate::Not(Box::new(between)) } else { between }
+            }
+            Expr::In { expr, list, negated } if is_int_column(&expr) && list.iter().all(|item| int_literal(item).is_some()) => {
+                let values = list.iter().filter_map(int_literal).collect();
+                let in_list = Predicate::In { column: column_name(&expr), values };
+                if negated { Predicate::Not(Box::new(in_list)) } else { in_list }
+            }
+            expr => Predicate::Expr(expr),
+        }
+    }
+
+    /// Builds the two-predicate filter used by `filter-simd-logical`.
+    pub fn from_pair(col1: &str, op1: SimdOp, val1: i32, col2: &str, op2: SimdOp, val2: i32, logic_op: LogicalOp) -> Predicate {
+        let left = Predicate::Compare { column: col1.to_string(), op: op1, value: val1 };
+        let right = Predicate::Compare { column: col2.to_string(), op: op2, value: val2 };
+
+        match logic_op {
+            LogicalOp::And => Predicate::And(vec![left, right]),
+            LogicalOp::Or => Predicate::Or(vec![left, right]),
+        }
+    }
+
+    /// Returns the referenced column names in order of first appearance.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    /// The conjuncts that read nothing but `column`, an empty `And` (every row) if there are none.
+    pub fn restricted_to(&self, column: &str) -> Predicate {
+        let only_column = |p: &Predicate| p.columns().iter().all(|c| *c == column);
+        match self {
+            Predicate::And(children) => Predicate::And(children.iter().filter(|c| only_column(c)).cloned().collect()),
+            other if only_column(other) => other.clone(),
+            _ => Predicate::And(Vec::new()),
+        }
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Predicate::Compare { column, .. } | Predicate::Between { column, .. } | Predicate::In { column, .. } => {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+            Predicate::CompareColumns { left, right, .. } => {
+                for column in [left, right] {
+                    if !columns.contains(&column.as_str()) {
+                        columns.push(column);
+                    }
+                }
+            }
+            Predicate::Expr(expr) => {
+                for column in expr.columns() {
+                    if !columns.contains(&column) {
+                        columns.push(column);
+                    }
+                }
+            }
+            Predicate::And(children) | Predicate::Or(children) => {
+                for child in children {
+                    child.collect_columns(columns);
+                }
+            }
+            Predicate::Not(child) => child.collect_columns(columns),
+        }
+    }
+
+    /// Whether evaluating the predicate can fail on some rows, as dividing by zero does.
+    pub fn may_fail(&self) -> bool {
+        match self {
+            Predicate::Expr(expr) => expr.may_fail(),
+            Predicate::And(children) | Predicate::Or(children) => children.iter().any(Predicate::may_fail),
+            Predicate::Not(child) => child.may_fail(),
+            _ => false,
+        }
+    }
+
+    /// Checks that every SIMD leaf reads an existing int column and every expression leaf is boolean.
+    pub fn validate(&self, table: &TableSchema) -> Result<(), String> {
+        match self {
+            Predicate::Expr(expr) => match expr.result_type(table)? {
+                "bool" => Ok(()),
+                other => Err(format!("Filter '{}' produces {}, expected bool", expr, other)),
+            },
+            Predicate::And(children) | Predicate::Or(children) => children.iter().try_for_each(|child| child.validate(table)),
+            Predicate::Not(child) => child.validate(table),
+            leaf => {
+                for name in leaf.columns() {
+                    match table.columns.iter().find(|c| c.name == name) {
+                        Some(column) if column.data_type == "int" => {}
+                        Some(column) => {
+                            return Err(format!("Column '{}' is of type {}, SIMD filters only support int", name, column.data_type));
+                        }
+                        None => return Err(format!("Column '{}' not found in table '{}'", name, table.table_name)),
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Rough fraction of rows expected to match, used to order the children of `And`/`Or`
+    /// and to estimate the rows a scan produces.
+    ///
+    /// Leaves on analyzed int columns are estimated from the column's histogram; everything
+    /// else uses the classic fixed guesses: equality is rare, inequality common and ranges
+    /// match about a third of the rows.
+    pub fn estimated_selectivity(&self, stats: Option<&TableStats>) -> f64 {
+        let column = |name: &str| stats.and_then(|s| s.column(name));
+        let estimate = match self {
+            Predicate::Compare { column: name, op, value } => column(name).and_then(|c| c.compare_selectivity(*op, *value)),
+            Predicate::Between { column: name, low, high } => column(name).and_then(|c| c.between_selectivity(*low, *high)),
+            Predicate::In { column: name, values } => column(name).and_then(|c| c.in_selectivity(values)),
+            _ => None,
+        };
+        if let Some(estimate) = estimate {
+            return estimate;
+        }
+
+        match self {
+            Predicate::Compare { op, .. } | Predicate::CompareColumns { op, .. } => match op {
+                SimdOp::Eq => 0.1,
+                SimdOp::Ne => 0.9,
+                SimdOp::Lt | SimdOp::Gt | SimdOp::Le | SimdOp::Ge => 1.0 / 3.0,
+            },
+            Predicate::Between { .. } => 0.25,
+            Predicate::In { values, .. } => (0.1 * values.len() as f64).min(0.9),
+            Predicate::Expr(_) => 1.0 / 3.0,
+            Predicate::And(children) => children.iter().map(|c| c.estimated_selectivity(stats)).product(),
+            Predicate::Or(children) => 1.0 - children.iter().map(|c| 1.0 - c.estimated_selectivity(stats)).product::<f64>(),
+            Predicate::Not(child) => 1.0 - child.estimated_selectivity(stats),
+        }
+    }
+
+    /// Whether the zone maps leave any chance of a row in `chunk` matching.
+    ///
+    /// Leaves without a zone map (strings, computed expressions) and anything under `Not`
+    /// are assumed to match, so pruning is always conservative.
+    pub fn chunk_may_match(&self, zone_maps: &HashMap<String, ZoneMap>, chunk: usize) -> bool {
+        let bounds = |column: &String| zone_maps.get(column).and_then(|z| z.bounds(chunk));
+        match self {
+            Predicate::Compare { column, op, value } => zone_maps.get(column).is_none_or(|z| z.may_match(chunk, *op, *value)),
+            Predicate::CompareColumns { left, op, right } => match (bounds(left), bounds(right)) {
+                (Some((lmin, lmax)), Some((rmin, rmax))) => match op {
+                    SimdOp::Eq => lmax >= rmin && lmin <= rmax,
+                    SimdOp::Ne => !(lmin == lmax && rmin == rmax && lmin == rmin),
+                    SimdOp::Lt => lmin < rmax,
+                    SimdOp::Le => lmin <= rmax,
+                    SimdOp::Gt => lmax > rmin,
+                    SimdOp::Ge => lmax >= rmin,
+                },
+                _ => true,
+            },
+            Predicate::Between { column, low, high } => zone_maps.get(column).is_none_or(|z| z.may_overlap(chunk, *low, *high)),
+            Predicate::In { column, values } => {
+                zone_maps.get(column).is_none_or(|z| values.iter().any(|&v| z.may_overlap(chunk, v, v)))
+            }
+            Predicate::Expr(_) | Predicate::Not(_) => true,
+            Predicate::And(children) => children.iter().all(|c| c.chunk_may_match(zone_maps, chunk)),
+            Predicate::Or(children) => children.iter().any(|c| c.chunk_may_match(zone_maps, chunk)),
+        }
+    }
+
+    /// Evaluates the tree over the rows in `candidates` and returns the matching subset.
+    /// A leaf never matches a row where one of its columns is null, and neither does its negation.
+    ///
+    /// Leaves only read and compare the chunks that still hold a candidate row, so later
+    /// conjuncts of an `And` (and later disjuncts of an `Or`) touch less and less of their columns.
+    /// Children run in the order they are stored; see [`CostModel::order`](super::cost::CostModel::order).
+    pub fn evaluate(&self, columns: &mut ColumnChunks, candidates: &Bitmap) -> Result<Bitmap, String> {
+        self.evaluate_with(columns, candidates, &HashMap::new())
+    }
+
+    /// [`Predicate::evaluate`] with the SIMD backend chosen per column; columns missing from
+    /// `backends` use the best one the CPU supports.
+    pub fn evaluate_with(&self, columns: &mut ColumnChunks, candidates: &Bitmap, backends: &HashMap<String, SimdBackend>) -> Result<Bitmap, String> {
+        let use_avx = |column: &str| backends.get(column).copied().unwrap_or_else(SimdBackend::detect) == SimdBackend::Avx2;
+        Ok(match self {
+            Predicate::Compare { column, op, value } => {
+                let kernel = if use_avx(column) { filter_simd_32_avx_bitmap } else { filter_simd_32_bitmap };
+                Self::scan_chunks(columns, column, candidates, |buffer| kernel(buffer, *value, *op))?
+            }
+            Predicate::CompareColumns { left, op, right } => {
+                let kernel = if use_avx(left) { filter_columns_simd_32_avx_bitmap } else { filter_columns_simd_32_bitmap };
+                let mut matched = Bitmap::new(candidates.len());
+
+                for chunk in 0..columns.chunk_count() {
+                    let start = chunk * CHUNK_ROWS;
+                    if !candidates.any_in(start, start + CHUNK_ROWS) {
+                        continue;
+                    }
+
+                    let (lhs, rhs) = columns.chunk_pair(left, right, chunk)?;
+                    let mut chunk_bits = kernel(lhs, rhs, *op);
+                    for column in [left, right] {
+                        if let Some(nulls) = columns.null_rows(column, chunk)? {
+                            chunk_bits = chunk_bits.and_not(nulls);
+                        }
+                    }
+                    matched.or_words_at(start, chunk_bits.words());
+                }
+
+                matched.and(candidates)
+            }
+            Predicate::Between { column, low, high } => {
+                let kernel = if use_avx(column) { filter_between_simd_32_avx_bitmap } else { filter_between_simd_32_bitmap };
+                Self::scan_chunks(columns, column, candidates, |buffer| kernel(buffer, *low, *high))?
+            }
+            Predicate::In { column, values } => {
+                let kernel = if use_avx(column) { filter_in_simd_32_avx_bitmap } else { filter_in_simd_32_bitmap };
+                Self::scan_chunks(columns, column, candidates, |buffer| kernel(buffer, values))?
+            }
+            Predicate::Expr(expr) => {
+                let names = expr.columns();
+                let mut matched = Bitmap::new(candidates.len());
+
+                for chunk in 0..columns.chunk_count() {
+                    let start = chunk * CHUNK_ROWS;
+                    if !candidates.any_in(start, start + CHUNK_ROWS) {
+                        continue;
+                    }
+
+                    // Only the candidate rows are evaluated, so `b != 0 and a / b > 1` never divides
+                    // by zero, and rows where a column is null are not candidates.
+                    let batch = columns.batch(&names, chunk)?;
+                    let nulls = batch.null_rows(&names);
+                    let rows: Vec<usize> = (0..batch.len)
+                        .filter(|&row| candidates.get(start + row) && !nulls.as_ref().is_some_and(|n| n.get(row)))
+                        .collect();
+                    match expr.evaluate(&batch.take(&rows))? {
+                        Vector::Bool(values) => {
+                            for (row, _) in rows.iter().zip(values).filter(|(_, hit)| *hit) {
+                                matched.set(start + row);
+                            }
+                        }
+                        other => return Err(format!("Filter '{}' produced {}, expected bool", expr, other.data_type())),
+                    }
+                }
+
+                matched
+            }
+            Predicate::And(children) => {
+                let mut selected = candidates.clone();
+                for child in children {
+                    if !selected.any() {
+                        break;
+                    }
+                    selected = child.evaluate_with(columns, &selected, backends)?;
+                }
+                selected
+            }
+            Predicate::Or(children) => {
+                let mut matched = Bitmap::new(candidates.len());
+                let mut remaining = candidates.clone();
+                for child in children {
+                    if !remaining.any() {
+                        break;
+                    }
+                    let child_matched = child.evaluate_with(columns, &remaining, backends)?;
+                    remaining = remaining.and_not(&child_matched);
+                    matched = combine_bitmaps(&matched, &child_matched, LogicalOp::Or);
+                }
+                matched
+            }
+            // A leaf over a null is neither true nor false, so its negation does not hold either:
+            // `not` is pushed down to the leaves, which drop the null rows before negating.
+            Predicate::Not(child) => match child.as_ref() {
+                Predicate::And(children) => Predicate::Or(children.iter().cloned().map(|c| Predicate::Not(Box::new(c))).collect())
+                    .evaluate_with(columns, candidates, backends)?,
+                Predicate::Or(children) => Predicate::And(children.iter().cloned().map(|c| Predicate::Not(Box::new(c))).collect())
+                    .evaluate_with(columns, candidates, backends)?,
+                Predicate::Not(inner) => inner.evaluate_with(columns, candidates, backends)?,
+                leaf => {
+                    let known = columns.without_nulls(candidates, &leaf.columns())?;
+                    known.and_not(&leaf.evaluate_with(columns, &known, backends)?)
+                }
+            },
+        })
+    }
+
+    /// Runs `kernel` over every chunk of `column` that still holds a candidate row.
+    fn scan_chunks(columns: &mut ColumnChunks, column: &str, candidates: &Bitmap, kernel: impl Fn(&[i32]) -> Bitmap) -> Result<Bitmap, String> {
+        let mut matched = Bitmap::new(candidates.len());
+
+        for chunk in 0..columns.chunk_count() {
+            let start = chunk * CHUNK_ROWS;
+            if !candidates.any_in(start, start + CHUNK_ROWS) {
+                continue;
+            }
+
+            let mut chunk_bits = kernel(columns.chunk(column, chunk)?);
+            if let Some(nulls) = columns.null_rows(column, chunk)? {
+                chunk_bits = chunk_bits.and_not(nulls);
+            }
+            matched.or_words_at(start, chunk_bits.words());
+        }
+
+        Ok(matched.and(candidates))
+    }
+}
+
+fn flip(op: SimdOp) -> SimdOp {
+    match op {
+        SimdOp::Lt => SimdOp::Gt,
+        SimdOp::Gt => SimdOp::Lt,
+        SimdOp::Le => SimdOp::Ge,
+        SimdOp::Ge => SimdOp::Le,
+        op => op,
+    }
+}
+
+fn flatten(children: Vec<Predicate>, op: LogicalOp) -> Predicate {
+    let mut flat = Vec::with_capacity(children.len());
+    for child in children {
+        match (child, op) {
+            (Predicate::And(inner), LogicalOp::And) | (Predicate::Or(inner), LogicalOp::Or) => flat.extend(inner),
+            (child, _) => flat.push(child),
+        }
+    }
+
+    match op {
+        LogicalOp::And => Predicate::And(flat),
+        LogicalOp::Or => Predicate::Or(flat),
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nested = |child: &Predicate| match child {
+            Predicate::And(_) | Predicate::Or(_) => format!("({})", child),
+            child => child.to_string(),
+        };
+        match self {
+            Predicate::Compare { column, op, value } => write!(f, "{} {} {}", column, op_symbol(*op), value),
+            Predicate::CompareColumns { left, op, right } => write!(f, "{} {} {}", left, op_symbol(*op), right),
+            Predicate::Between { column, low, high } => write!(f, "{} between {} and {}", column, low, high),
+            Predicate::In { column, values } => {
+                let values: Vec<String> = values.iter().map(i32::to_string).collect();
+                write!(f, "{} in ({})", column, values.join(", "))
+            }
+            Predicate::Expr(expr) => write!(f, "{}", expr),
+            Predicate::And(children) => write!(f, "{}", children.iter().map(nested).collect::<Vec<_>>().join(" and ")),
+            Predicate::Or(children) => write!(f, "{}", children.iter().map(nested).collect::<Vec<_>>().join(" or ")),
+            Predicate::Not(child) => write!(f, "not {}", nested(child)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::chunk::ChunkReader;
+
+    fn compare(column: &str, op: SimdOp, value: i32) -> Predicate {
+        Predicate::Compare { column: column.to_string(), op, value }
+    }
+
+    fn table() -> TableSchema {
+        TableSchema::new("t".to_string(), ["id:int", "age:int", "score:int", "name:string"].map(String::from).to_vec())
+    }
+
+    #[test]
+    fn parse_follows_precedence_and_flattens() {
+        let table = table();
+        let parsed = Predicate::parse("age gt 25 and (id < 5 or not age = 54) and -3 <= id", &table).unwrap();
+        let expected = Predicate::And(vec![
+            compare("age", SimdOp::Gt, 25),
+            Predicate::Or(vec![compare("id", SimdOp::Lt, 5), Predicate::Not(Box::new(compare("age", SimdOp::Eq, 54)))]),
+            compare("id", SimdOp::Ge, -3),
+        ]);
+        assert_eq!(parsed, expected);
+        assert_eq!(parsed.columns(), ["age", "id"]);
+
+        assert_eq!(Predicate::parse("id = 1 or age <> 2 and score le 3", &table).unwrap(), Predicate::Or(vec![
+            compare("id", SimdOp::Eq, 1),
+            Predicate::And(vec![compare("age", SimdOp::Ne, 2), compare("score", SimdOp::Le, 3)]),
+        ]));
+    }
+
+    #[test]
+    fn parse_reads_ranges_lists_and_column_comparisons() {
+        let table = table();
+        assert_eq!(Predicate::parse("age between 20 and 30 and id in (1, -2, 3)", &table).unwrap(), Predicate::And(vec![
+            Predicate::Between { column: "age".to_string(), low: 20, high: 30 },
+            Predicate::In { column: "id".to_string(), values: vec![1, -2, 3] },
+        ]));
+        assert_eq!(
+            Predicate::parse("age not in (5)", &table).unwrap(),
+            Predicate::Not(Box::new(Predicate::In { column: "age".to_string(), values: vec![5] })),
+        );
+        assert_eq!(
+            Predicate::parse("id < age", &table).unwrap(),
+            Predicate::CompareColumns { left: "id".to_string(), op: SimdOp::Lt, right: "age".to_string() },
+        );
+        assert_eq!(Predicate::parse("id < age", &table).unwrap().columns(), ["id", "age"]);
+        for input in ["age between 1", "age between 1 or 2", "id in ()", "id in (1,)", "id in 1"] {
+            assert!(Predicate::parse(input, &table).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn other_shapes_stay_expressions() {
+        let table = table();
+        for input in ["id + 1 > age", "upper(name) = 'BOB'", "age in (1, id)", "age between id and 5"] {
+            assert!(matches!(Predicate::parse(input, &table).unwrap(), Predicate::Expr(_)), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_and_non_boolean_input() {
+        let table = table();
+        for input in ["", "age", "age + 1", "age gt", "age gt and", "(age gt 1", "age gt 1)", "age gt 1 and", "age ~ 1"] {
+            assert!(Predicate::parse(input, &table).is_err(), "{:?}", input);
+        }
+        assert!(Predicate::parse("id = 1 or missing > 2", &table).unwrap_err().contains("not found"));
+        assert!(Predicate::parse("name = 1", &table).is_err());
+    }
+
+    #[test]
+    fn simd_leaves_need_int_columns() {
+        let table = table();
+        assert!(Predicate::from_pair("id", SimdOp::Eq, 1, "age", SimdOp::Eq, 2, LogicalOp::And).validate(&table).is_ok());
+        let pair = Predicate::from_pair("id", SimdOp::Eq, 1, "name", SimdOp::Eq, 2, LogicalOp::Or);
+        assert!(pair.validate(&table).unwrap_err().contains("string"));
+    }
+
+    /// Chunk readers over int columns written to a directory of their own.
+    fn chunks(name: &str, columns: &[(&str, &[i32])]) -> ColumnChunks {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut readers = std::collections::HashMap::new();
+        for (column, values) in columns {
+            let path = dir.join(column);
+            std::fs::write(&path, values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+            readers.insert(column.to_string(), ChunkReader::open(path.to_str().unwrap(), "int", 0).unwrap());
+        }
+        // The open readers keep the files readable once the directory is gone.
+        std::fs::remove_dir_all(&dir).unwrap();
+        ColumnChunks::new(readers)
+    }
+
+    #[test]
+    fn evaluate_matches_per_row_logic() {
+        // Three chunks, the last one partial.
+        let id: Vec<i32> = (0..2500).collect();
+        let age: Vec<i32> = (0..2500).map(|row| row % 7).collect();
+        let predicate = Predicate::parse("id >= 10 and (age = 3 or not id < 1500) and age < id and id % 5 + age != 4", &table()).unwrap();
+        let expected: Vec<usize> = (0..2500)
+            .filter(|&row| id[row] >= 10 && (age[row] == 3 || id[row] >= 1500) && age[row] < id[row] && id[row] % 5 + age[row] != 4)
+            .collect();
+
+        for backend in [SimdBackend::Sse, SimdBackend::detect()] {
+            let mut columns = chunks("predicate-evaluate", &[("id", &id), ("age", &age)]);
+            let all_rows = Bitmap::full(columns.row_count());
+            let backends = HashMap::from([("id".to_string(), backend), ("age".to_string(), backend)]);
+            let matched: Vec<usize> = predicate.evaluate_with(&mut columns, &all_rows, &backends).unwrap().iter_ones().collect();
+            assert_eq!(matched, expected);
+        }
+    }
+
+    #[test]
+    fn and_only_reads_chunks_that_still_hold_candidates() {
+        let id: Vec<i32> = (0..3000).collect();
+        let mut columns = chunks("predicate-and", &[("id", &id), ("age", &id)]);
+        let all_rows = Bitmap::full(columns.row_count());
+
+        // `id = 5` runs first and leaves rows in the first chunk only.
+        let matched = Predicate::parse("id = 5 and age ge 0", &table()).unwrap().evaluate(&mut columns, &all_rows).unwrap();
+        assert_eq!(matched.iter_ones().collect::<Vec<_>>(), [5]);
+        assert_eq!(columns.chunks_read(), 3 + 1);
+
+        // Nothing is left after the first leaf, so the second is never read.
+        let mut columns = chunks("predicate-and", &[("id", &id), ("age", &id)]);
+        let predicate = Predicate::parse("id = -1 and age ge 0", &table()).unwrap();
+        assert!(!predicate.evaluate(&mut columns, &all_rows).unwrap().any());
+        assert_eq!(columns.chunks_read(), 3);
+    }
+
+    #[test]
+    fn or_stops_once_every_row_matched() {
+        let id: Vec<i32> = (0..3000).collect();
+        let mut columns = chunks("predicate-or", &[("id", &id), ("age", &id)]);
+        let all_rows = Bitmap::full(columns.row_count());
+
+        let matched = Predicate::parse("id ge 0 or age = 7", &table()).unwrap().evaluate(&mut columns, &all_rows).unwrap();
+        assert_eq!(matched, all_rows);
+        assert_eq!(columns.chunks_read(), 3);
+    }
+
+    #[test]
+    fn expression_leaves_only_see_candidate_rows() {
+        let id: Vec<i32> = (0..100).collect();
+        let zero_every_third: Vec<i32> = (0..100).map(|row| row % 3).collect();
+        let mut columns = chunks("predicate-guard", &[("id", &id), ("age", &zero_every_third)]);
+        let all_rows = Bitmap::full(columns.row_count());
+
+        // The division runs on the rows `age != 0` keeps, never on a zero.
+        let predicate = Predicate::parse("age != 0 and id / age > 10", &table()).unwrap();
+        let matched: Vec<usize> = predicate.evaluate(&mut columns, &all_rows).unwrap().iter_ones().collect();
+        let expected: Vec<usize> = (0..100).filter(|&row| row % 3 != 0 && id[row] / zero_every_third[row] > 10).collect();
+        assert_eq!(matched, expected);
+        assert!(Predicate::parse("id / age > 10", &table()).unwrap().evaluate(&mut columns, &all_rows).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+
+use super::{expr::{Expr, ExprParser}, lexer::Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+/// One entry of a `select` list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Star,
+    Expr { expr: Expr, name: String },
+    /// `arg` is `None` only for `count(*)`.
+    Aggregate { func: AggregateFunc, arg: Option<Expr>, name: String },
+}
+
+/// `join <table> on <left> = <right>`, an inner equi-join on one column of each side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinClause {
+    pub table: String,
+    pub left: String,
+    pub right: String,
+}
+
+/// A parsed query:
+/// `select <items> from <table> [join <table> on a = b] [where <expr>] [group by <exprs>]
+/// [order by <expr> [asc|desc], ...] [limit <n>]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub items: Vec<SelectItem>,
+    pub from: String,
+    pub join: Option<JoinClause>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub order_by: Vec<(Expr, bool)>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, String> {
+        let mut parser = ExprParser::new(input)?;
+
+        parser.expect_keyword("select")?;
+        let items = parse_items(&mut parser)?;
+
+        parser.expect_keyword("from")?;
+        let from = parser.expect_ident()?;
+
+        let join = if parser.eat_keyword("join") {
+            let table = parser.expect_ident()?;
+            parser.expect_keyword("on")?;
+            let left = parser.expect_ident()?;
+            parser.expect(Token::Op(crate::utils::simd::SimdOp::Eq))?;
+            let right = parser.expect_ident()?;
+            Some(JoinClause { table, left, right })
+        } else {
+            None
+        };
+
+        let filter = if parser.eat_keyword("where") { Some(parser.parse_expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if parser.eat_keyword("group") {
+            parser.expect_keyword("by")?;
+            group_by.push(parser.parse_expr()?);
+            while parser.peek() == Some(&Token::Comma) {
+                parser.advance();
+                group_by.push(parser.parse_expr()?);
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if parser.eat_keyword("order") {
+            parser.expect_keyword("by")?;
+            loop {
+                let key = parser.parse_expr()?;
+                let ascending = !parser.eat_keyword("desc");
+                if ascending {
+                    parser.eat_keyword("asc");
+                }
+                order_by.push((key, ascending));
+
+                if parser.peek() != Some(&Token::Comma) {
+                    break;
+                }
+                parser.advance();
+            }
+        }
+
+        let limit = if parser.eat_keyword("limit") {
+            match parser.advance() {
+                Some(Token::Int(n)) if n >= 0 => Some(n as usize),
+                other => return Err(format!("Expected a row count after LIMIT, found {:?}", other)),
+            }
+        } else {
+            None
+        };
+
+        parser.expect_end()?;
+        Ok(Query { items, from, join, filter, group_by, order_by, limit })
+    }
+
+    pub fn has_aggregates(&self) -> bool {
+        !self.group_by.is_empty() || self.items.iter().any(|item| matches!(item, SelectItem::Aggregate { .. }))
+    }
+}
+
+fn parse_items(parser: &mut ExprParser) -> Result<Vec<SelectItem>, String> {
+    let mut items = Vec::new();
+    loop {
+        items.push(parse_item(parser)?);
+        if parser.peek() != Some(&Token::Comma) {
+            return Ok(items);
+        }
+        parser.advance();
+    }
+}
+
+fn parse_item(parser: &mut ExprParser) -> Result<SelectItem, String> {
+    if parser.peek() == Some(&Token::Star) {
+        parser.advance();
+        return Ok(SelectItem::Star);
+    }
+
+    let func = match parser.peek() {
+        Some(Token::Word(word)) if parser.peek_at(1) == Some(&Token::LParen) => AggregateFunc::from_name(word),
+        _ => None,
+    };
+
+    let item = match func {
+        Some(func) => {
+            parser.advance();
+            parser.advance();
+            let arg = if func == AggregateFunc::Count && parser.peek() == Some(&Token::Star) {
+                parser.advance();
+                None
+            } else {
+                Some(parser.parse_expr()?)
+            };
+            parser.expect(Token::RParen)?;
+
+            let default_name = match &arg {
+                Some(arg) => format!("{}({})", func, arg),
+                None => format!("{}(*)", func),
+            };
+            let name = if parser.eat_keyword("as") { parser.expect_ident()? } else { default_name };
+            SelectItem::Aggregate { func, arg, name }
+        }
+        None => {
+            let expr = parser.parse_expr()?;
+            let name = if parser.eat_keyword("as") { parser.expect_ident()? } else { expr.to_string() };
+            SelectItem::Expr { expr, name }
+        }
+    };
+
+    Ok(item)
+}
+
+impl AggregateFunc {
+    fn from_name(name: &str) -> Option<AggregateFunc> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(AggregateFunc::Count),
+            "sum" => Some(AggregateFunc::Sum),
+            "min" => Some(AggregateFunc::Min),
+            "max" => Some(AggregateFunc::Max),
+            "avg" => Some(AggregateFunc::Avg),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggregateFunc::Count => "count",
+            AggregateFunc::Sum => "sum",
+            AggregateFunc::Min => "min",
+            AggregateFunc::Max => "max",
+            AggregateFunc::Avg => "avg",
+        };
+        write!(f, "{}", name)
+    }
+}
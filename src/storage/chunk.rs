@@ -0,0 +1,489 @@
+use std::{collections::HashMap, fs::File, io::{BufReader, Read, Seek, SeekFrom}};
+
+use super::{column::MinMaxIndex, format::read_data_header, timestamp, vector::{Batch, Vector}};
+use crate::utils::{bitmap::Bitmap, crc32c::crc32c};
+
+/// Number of rows read at a time when a column is read piecewise.
+/// A multiple of 64 so that a chunk always covers whole words of a selection bitmap.
+pub const CHUNK_ROWS: usize = 1024;
+
+/// Reads a column in `CHUNK_ROWS` sized pieces so callers only pay for the chunks they touch.
+///
+/// Int, float, bool and timestamp columns are fixed width and seek straight to the chunk. String columns have no row
+/// offsets on disk, so opening one walks the length prefixes once and remembers where each
+/// chunk starts; only the chunks asked for are decoded.
+///
+/// With [`ChunkReader::with_checksums`], the bytes under every index entry are checked against
+/// the entry's CRC32C the first time a chunk touching them is read.
+pub struct ChunkReader {
+    file: File,
+    data_type: String,
+    /// Where the values start, past the file's header if it has one.
+    start: u64,
+    row_count: usize,
+    /// Offset of every chunk of a string column from the first value, plus the end of the last one.
+    string_offsets: Option<Vec<u64>>,
+    /// One byte per row of a nullable column, non-zero where the row has no value.
+    nulls: Option<File>,
+    bytes_read: u64,
+    /// `table 'x', column 'y'`, naming the column in checksum errors.
+    location: String,
+    checksums: Vec<EntryChecksum>,
+}
+
+/// The bytes one index entry covers, from its offset to the next entry's, and their CRC32C.
+#[derive(Clone, Copy)]
+struct EntryChecksum {
+    start: u64,
+    end: u64,
+    crc: u32,
+    verified: bool,
+}
+
+/// Bytes per value of the fixed width types; strings are length prefixed instead.
+pub fn fixed_width(data_type: &str) -> Option<usize> {
+    match data_type {
+        "int" => Some(4),
+        "float" | "timestamp" => Some(8),
+        "bool" => Some(1),
+        _ => None,
+    }
+}
+
+impl ChunkReader {
+    /// Opens the `.data` file at `path` of a table of `format_version`.
+    pub fn open(path: &str, data_type: &str, format_version: u16) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let start = read_data_header(&mut file, path, data_type, format_version).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let values_len = file.metadata()?.len() - start;
+        let (row_count, string_offsets, bytes_read) = match (data_type, fixed_width(data_type)) {
+            (_, Some(width)) => (values_len as usize / width, None, 0),
+            ("string", None) => {
+                let (row_count, offsets) = string_chunk_offsets(&mut BufReader::new(file.try_clone()?), values_len)?;
+                (row_count, Some(offsets), row_count as u64 * 4)
+            }
+            (other, None) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Unsupported data type '{}'", other)));
+            }
+        };
+        Ok(Self {
+            file,
+            data_type: data_type.to_string(),
+            start,
+            row_count,
+            string_offsets,
+            nulls: None,
+            bytes_read,
+            location: path.to_string(),
+            checksums: Vec::new(),
+        })
+    }
+
+    /// Checks reads against the checksums of `entries`, the column's index. Entries written
+    /// before checksums existed are not checked.
+    pub fn with_checksums(mut self, table_name: &str, column_name: &str, entries: &[MinMaxIndex]) -> Self {
+        let values_len = self.file.metadata().map_or(0, |m| m.len()) - self.start;
+        let ends = entries.iter().skip(1).map(|entry| entry.chunk_offset).chain([values_len]);
+        self.checksums = entries
+            .iter()
+            .zip(ends)
+            .filter_map(|(entry, end)| Some(EntryChecksum { start: entry.chunk_offset, end, crc: entry.checksum?, verified: false }))
+            .collect();
+        self.location = format!("table '{}', column '{}'", table_name, column_name);
+        self
+    }
+
+    /// Reads null flags from `path` as well; a missing file means no row is null.
+    pub fn with_nulls(mut self, path: &str) -> Self {
+        self.nulls = File::open(path).ok();
+        self
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.row_count.div_ceil(CHUNK_ROWS)
+    }
+
+    /// Bytes loaded from the data file so far; for string columns this includes the length
+    /// prefixes walked when opening.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Where each chunk starts, counted from the first value as index entries are, plus where
+    /// the last one ends.
+    pub fn chunk_offsets(&self) -> Vec<u64> {
+        match &self.string_offsets {
+            Some(offsets) => offsets.clone(),
+            None => {
+                let width = fixed_width(&self.data_type).unwrap();
+                (0..=self.chunk_count()).map(|chunk| ((chunk * CHUNK_ROWS).min(self.row_count) * width) as u64).collect()
+            }
+        }
+    }
+
+    /// Reads `chunk`, or names the table, column and chunk whose bytes no longer match their
+    /// checksum.
+    pub fn try_read(&mut self, chunk: usize) -> Result<Vector, String> {
+        let start = chunk * CHUNK_ROWS;
+        let end = (start + CHUNK_ROWS).min(self.row_count);
+
+        if let Some(offsets) = &self.string_offsets {
+            let offset = offsets[chunk];
+            let mut bytes = vec![0u8; (offsets[chunk + 1] - offset) as usize];
+            self.read_at(offset, &mut bytes)?;
+            self.bytes_read += (bytes.len() - (end - start) * 4) as u64;
+            self.check(chunk, offset, &bytes)?;
+            return Ok(Vector::Str(read_strings(&mut bytes.as_slice()).map_err(|e| e.to_string())?));
+        }
+
+        let width = fixed_width(&self.data_type).unwrap();
+        let offset = (start * width) as u64;
+        let mut bytes = vec![0u8; (end - start) * width];
+        self.read_at(offset, &mut bytes)?;
+        self.bytes_read += bytes.len() as u64;
+        self.check(chunk, offset, &bytes)?;
+
+        Ok(match self.data_type.as_str() {
+            "int" => Vector::Int(bytes.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap())).collect()),
+            "float" => Vector::Float(bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect()),
+            "bool" => Vector::Bool(bytes.iter().map(|&b| b != 0).collect()),
+            _ => Vector::Str(bytes.chunks_exact(8).map(|b| timestamp::format(i64::from_le_bytes(b.try_into().unwrap()))).collect()),
+        })
+    }
+
+    /// Fills `bytes` from `offset`, counted from the first value.
+    fn read_at(&mut self, offset: u64, bytes: &mut [u8]) -> Result<(), String> {
+        self.file
+            .seek(SeekFrom::Start(self.start + offset))
+            .and_then(|_| self.file.read_exact(bytes))
+            .map_err(|e| format!("Failed to read {}: {}", self.location, e))
+    }
+
+    /// Checks every entry overlapping `bytes`, read from `offset` for `chunk`, that has not been
+    /// checked yet. An entry reaching past `bytes` is read whole. Entries are in offset order,
+    /// so the first overlapping one is found by binary search.
+    fn check(&mut self, chunk: usize, offset: u64, bytes: &[u8]) -> Result<(), String> {
+        let end = offset + bytes.len() as u64;
+        let first = self.checksums.partition_point(|entry| entry.end <= offset);
+        for i in first..self.checksums.len() {
+            let entry = self.checksums[i];
+            if entry.start >= end {
+                break;
+            }
+            if entry.verified {
+                continue;
+            }
+            let crc = if entry.start >= offset && entry.end <= end {
+                crc32c(&bytes[(entry.start - offset) as usize..(entry.end - offset) as usize])
+            } else {
+                let mut covered = vec![0u8; (entry.end - entry.start) as usize];
+                self.read_at(entry.start, &mut covered).map_err(|_| self.mismatch(chunk))?;
+                self.bytes_read += covered.len() as u64;
+                crc32c(&covered)
+            };
+            if crc != entry.crc {
+                return Err(self.mismatch(chunk));
+            }
+            self.checksums[i].verified = true;
+        }
+        Ok(())
+    }
+
+    fn mismatch(&self, chunk: usize) -> String {
+        format!("Checksum mismatch in {}, chunk {}", self.location, chunk)
+    }
+
+    /// Null flags of the rows in `chunk`, `None` for a column without a `.nulls` file. Rows
+    /// past the end of the file are not null.
+    pub fn read_nulls(&mut self, chunk: usize) -> Option<Vec<bool>> {
+        let start = chunk * CHUNK_ROWS;
+        let len = CHUNK_ROWS.min(self.row_count - start);
+        let file = self.nulls.as_mut()?;
+
+        let mut bytes = Vec::with_capacity(len);
+        file.seek(SeekFrom::Start(start as u64)).ok()?;
+        file.take(len as u64).read_to_end(&mut bytes).ok()?;
+        self.bytes_read += bytes.len() as u64;
+
+        let mut nulls: Vec<bool> = bytes.iter().map(|&b| b != 0).collect();
+        nulls.resize(len, false);
+        Some(nulls)
+    }
+}
+
+/// Walks the `values_len` bytes of length-prefixed strings from where `reader` is and returns
+/// the row count and the offset of every chunk plus the end of the last complete value. A value
+/// cut short at the end of the file is not counted.
+fn string_chunk_offsets(reader: &mut BufReader<File>, values_len: u64) -> std::io::Result<(usize, Vec<u64>)> {
+    let mut offsets = vec![0];
+    let mut offset = 0u64;
+    let mut rows = 0;
+    let mut len_buf = [0u8; 4];
+
+    while offset + 4 <= values_len {
+        reader.read_exact(&mut len_buf)?;
+        let len = u32::from_le_bytes(len_buf) as u64;
+        if offset + 4 + len > values_len {
+            break;
+        }
+        reader.seek_relative(len as i64)?;
+        offset += 4 + len;
+        rows += 1;
+        if rows % CHUNK_ROWS == 0 {
+            offsets.push(offset);
+        }
+    }
+    if rows % CHUNK_ROWS != 0 {
+        offsets.push(offset);
+    }
+
+    Ok((rows, offsets))
+}
+
+/// Decodes length-prefixed strings until the end of `reader`.
+pub fn read_strings(reader: &mut impl Read) -> std::io::Result<Vec<String>> {
+    let mut strings = Vec::new();
+    let mut len_buf = [0u8; 4];
+
+    loop {
+        match reader.read_exact(&mut len_buf) {
+            Ok(_) => {
+                let len = u32::from_le_bytes(len_buf) as usize;
+                let mut buffer = vec![0u8; len];
+                reader.read_exact(&mut buffer)?;
+                strings.push(String::from_utf8_lossy(&buffer).into_owned());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(strings),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Chunk readers for every column a query touches, with each chunk read at most once.
+///
+/// The null flags of a nullable column are loaded with its values. Null rows hold a placeholder
+/// value, so every caller must leave them out through [`ColumnChunks::null_rows`] or the nulls
+/// of a [`Batch`].
+pub struct ColumnChunks {
+    readers: HashMap<String, ChunkReader>,
+    cache: HashMap<(String, usize), Vector>,
+    /// Null rows of each loaded chunk, `None` for a chunk without any.
+    null_cache: HashMap<(String, usize), Option<Bitmap>>,
+    row_count: usize,
+    /// Keep only the last chunk read of each column, see [`ColumnChunks::streaming`].
+    streaming: bool,
+}
+
+impl ColumnChunks {
+    pub fn new(readers: HashMap<String, ChunkReader>) -> Self {
+        let row_count = readers.values().map(ChunkReader::row_count).min().unwrap_or(0);
+        Self {
+            readers,
+            cache: HashMap::new(),
+            null_cache: HashMap::new(),
+            row_count,
+            streaming: false,
+        }
+    }
+
+    /// Holds at most one chunk per column in memory, so a pass over a large table stays
+    /// small; a column read again by a later pass is loaded from disk again.
+    pub fn streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    /// Rows present in every column; ragged trailing rows are ignored.
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.row_count.div_ceil(CHUNK_ROWS)
+    }
+
+    /// Number of distinct column chunks loaded from disk so far.
+    pub fn chunks_read(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Bytes loaded from the data files of every column so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.readers.values().map(ChunkReader::bytes_read).sum()
+    }
+
+    /// Rows in `chunk`, shorter than `CHUNK_ROWS` only for the last one.
+    pub fn chunk_len(&self, chunk: usize) -> usize {
+        CHUNK_ROWS.min(self.row_count - chunk * CHUNK_ROWS)
+    }
+
+    /// One chunk of an int column, as fed to the SIMD kernels.
+    pub fn chunk(&mut self, column: &str, chunk: usize) -> Result<&[i32], String> {
+        self.load(column, chunk)?;
+        as_ints(column, &self.cache[&(column.to_string(), chunk)])
+    }
+
+    /// The same chunk of two int columns, for predicates comparing one column against another.
+    /// Fails when the columns do not have the same rows, as a damaged table may not.
+    pub fn chunk_pair(&mut self, left: &str, right: &str, chunk: usize) -> Result<(&[i32], &[i32]), String> {
+        self.load(left, chunk)?;
+        self.load(right, chunk)?;
+        let lhs = as_ints(left, &self.cache[&(left.to_string(), chunk)])?;
+        let rhs = as_ints(right, &self.cache[&(right.to_string(), chunk)])?;
+        if lhs.len() != rhs.len() {
+            return Err(format!("Columns '{}' and '{}' have {} and {} rows in chunk {}", left, right, lhs.len(), rhs.len(), chunk));
+        }
+        Ok((lhs, rhs))
+    }
+
+    /// One chunk of each of `columns` with their null rows, for evaluating computed expressions.
+    pub fn batch(&mut self, columns: &[&str], chunk: usize) -> Result<Batch, String> {
+        let mut vectors = HashMap::new();
+        let mut nulls = HashMap::new();
+        for column in columns {
+            self.load(column, chunk)?;
+            let key = (column.to_string(), chunk);
+            vectors.insert(column.to_string(), self.cache[&key].clone());
+            if let Some(column_nulls) = &self.null_cache[&key] {
+                nulls.insert(column.to_string(), column_nulls.clone());
+            }
+        }
+        Ok(Batch { columns: vectors, nulls, len: self.chunk_len(chunk) })
+    }
+
+    /// Where each chunk of `column` starts in its data file, plus where the last one ends.
+    pub fn chunk_offsets(&self, column: &str) -> Vec<u64> {
+        let mut offsets = self.readers[column].chunk_offsets();
+        offsets.truncate(self.chunk_count() + 1);
+        offsets
+    }
+
+    /// The null rows of one chunk of `column`, counted from the chunk's first row; `None` when
+    /// the chunk has none.
+    pub fn null_rows(&mut self, column: &str, chunk: usize) -> Result<Option<&Bitmap>, String> {
+        self.load(column, chunk)?;
+        Ok(self.null_cache[&(column.to_string(), chunk)].as_ref())
+    }
+
+    /// The rows of `rows` where none of `columns` is null.
+    pub fn without_nulls(&mut self, rows: &Bitmap, columns: &[&str]) -> Result<Bitmap, String> {
+        let mut nulls = Bitmap::new(rows.len());
+        for chunk in 0..self.chunk_count() {
+            let start = chunk * CHUNK_ROWS;
+            if !rows.any_in(start, start + CHUNK_ROWS) {
+                continue;
+            }
+            for column in columns {
+                if let Some(column_nulls) = self.null_rows(column, chunk)? {
+                    nulls.or_words_at(start, column_nulls.words());
+                }
+            }
+        }
+        Ok(rows.and_not(&nulls))
+    }
+
+    /// The value of `column` at `row` as text, `null` for a null.
+    pub fn display(&mut self, column: &str, row: usize) -> Result<String, String> {
+        let (chunk, offset) = (row / CHUNK_ROWS, row % CHUNK_ROWS);
+        if self.null_rows(column, chunk)?.is_some_and(|nulls| nulls.get(offset)) {
+            return Ok("null".to_string());
+        }
+        Ok(self.cache[&(column.to_string(), chunk)].display(offset))
+    }
+
+    /// Reads `chunk` of `column` into the cache unless it is there, failing on a chunk that
+    /// cannot be read or fails its checksum.
+    fn load(&mut self, column: &str, chunk: usize) -> Result<(), String> {
+        let key = (column.to_string(), chunk);
+        if !self.cache.contains_key(&key) {
+            if self.streaming {
+                self.cache.retain(|(cached, _), _| cached != column);
+                self.null_cache.retain(|(cached, _), _| cached != column);
+            }
+            let len = self.chunk_len(chunk);
+            let reader = self.readers.get_mut(column).unwrap();
+            let mut values = reader.try_read(chunk)?;
+            values.truncate(len);
+            let nulls = reader.read_nulls(chunk).and_then(|flags| {
+                let mut nulls = Bitmap::new(len);
+                for (row, _) in flags.iter().take(len).enumerate().filter(|(_, null)| **null) {
+                    nulls.set(row);
+                }
+                nulls.any().then_some(nulls)
+            });
+            self.cache.insert(key.clone(), values);
+            self.null_cache.insert(key, nulls);
+        }
+        Ok(())
+    }
+}
+
+fn as_ints<'a>(column: &str, vector: &'a Vector) -> Result<&'a [i32], String> {
+    match vector {
+        Vector::Int(values) => Ok(values),
+        other => Err(format!("Column '{}' holds {} values, SIMD kernels need int", column, other.data_type())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::query::expr::Expr;
+    use crate::storage::{column::ColumnStore, table::TableSchema};
+
+    #[test]
+    fn corrupted_chunk_fails_its_checksum() {
+        let dir = std::env::temp_dir().join(format!("cdbe-corrupted-chunk-{}", std::process::id()));
+        let base_path = dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(base_path);
+        let store = ColumnStore::new(base_path);
+        let table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        table.save(base_path).unwrap();
+        let values: Vec<String> = (0..2 * CHUNK_ROWS).map(|i| i.to_string()).collect();
+        let rows: Vec<Vec<&str>> = values.iter().map(|v| vec![v.as_str()]).collect();
+        store.insert_rows(&table, &rows).unwrap();
+
+        // Flips a byte of the last value, in the second chunk.
+        let path = format!("{}/t_id.data", base_path);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+
+        let mut chunks = store.open_chunks(&table, &["id"]).unwrap();
+        assert_eq!(chunks.chunk("id", 0).unwrap()[5], 5);
+        let error = chunks.batch(&["id"], 1).unwrap_err();
+        assert!(error.contains("Checksum mismatch") && error.contains("chunk 1"), "{}", error);
+        let error = store.select(&table, &[(Expr::Column("id".to_string()), "id".to_string())], None).unwrap_err();
+        assert!(error.contains("Checksum mismatch"), "{}", error);
+
+        fs::remove_dir_all(base_path).unwrap();
+    }
+
+    #[test]
+    fn entries_without_checksums_are_not_checked() {
+        let path = std::env::temp_dir().join(format!("cdbe-unchecked-chunk-{}", std::process::id()));
+        let mut bytes: Vec<u8> = (0..2 * CHUNK_ROWS as i32).flat_map(i32::to_le_bytes).collect();
+        let second = (CHUNK_ROWS * 4) as u64;
+        let entry = |chunk_offset, checksum| MinMaxIndex { chunk_offset, min_value: None, max_value: None, checksum, row: None };
+        let entries = [entry(0, None), entry(second, Some(crc32c(&bytes[second as usize..])))];
+
+        // The first entry predates checksums, so damage under it goes unnoticed.
+        bytes[0] ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        let mut reader = ChunkReader::open(path.to_str().unwrap(), "int", 0).unwrap().with_checksums("t", "id", &entries);
+        assert!(reader.try_read(0).is_ok());
+        assert!(reader.try_read(1).is_ok());
+
+        *bytes.last_mut().unwrap() ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        let mut reader = ChunkReader::open(path.to_str().unwrap(), "int", 0).unwrap().with_checksums("t", "id", &entries);
+        assert_eq!(reader.try_read(1).unwrap_err(), "Checksum mismatch in table 't', column 'id', chunk 1");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,190 +1,277 @@
 use serde::{Deserialize, Serialize};
-use std::{fs::{self, File, OpenOptions}, io::{BufRead, BufReader, Read, Seek, SeekFrom, Write}, vec};
+use std::{collections::HashMap, fs::{self, File}, io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom}, path::Path, vec};
 
-use super::table::TableSchema;
-use crate::utils::simd::{filter_simd_32, filter_simd_32_avx, filter_with_logical_op, LogicalOp, SimdOp};
+use super::{chunk::{fixed_width, read_strings, ChunkReader, ColumnChunks, CHUNK_ROWS}, config::{DatabaseConfig, Durability}, csv::{check_record, column_mapping, CsvOptions, CsvReader}, export::{ExportFormat, ExportWriter}, format::{read_data_header, read_index_header, FORMAT_VERSION}, ipc, json::{infer_schema, record_values, JsonReader}, stats::{ColumnStats, TableStats}, table::TableSchema, upgrade::{backup_dir, upgrade_table, Upgrade}, vector::Vector, verify::{rebuild_index, verify_table, Report}, wal::lock_for_rewrite, writer::{parse_row, parse_value, RowError, TableWriter}, zonemap::{chunk_bounds, ZoneMap}};
+use arrow_ipc::reader::FileReader;
+use crate::query::{cost::CostModel, expr::Expr, predicate::Predicate};
+use crate::utils::bitmap::Bitmap;
+use crate::utils::simd::{filter_simd_32, filter_simd_32_avx, LogicalOp, SimdOp};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Column {
     pub name: String,
-    pub data_type: String
+    pub data_type: String,
+    /// Whether rows may hold no value, recorded in the column's `.nulls` file.
+    #[serde(default)]
+    pub nullable: bool,
 }
 
+/// Bad records listed before an import gives up.
+const MAX_REPORTED_ERRORS: usize = 10;
+
 pub struct ColumnStore {
     pub base_path: String,
+    /// When writes are fsynced, from the database's `config.json`.
+    pub durability: Durability,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MinMaxIndex {
     pub chunk_offset: u64,
-    pub min_value: String,
-    pub max_value: String,
+    /// Bounds of the entry's values, leaving out nulls; `None` when every row is null.
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    /// CRC32C of the data bytes from `chunk_offset` up to the next entry, or the end of the
+    /// file for the last one. `None` in entries written before checksums were kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u32>,
+    /// Row of the entry's first value. `None` in entries written before rows were kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row: Option<u64>,
 }
 
 impl ColumnStore  {
     pub fn new(base_path: &str) -> Self {
         fs::create_dir_all(base_path).unwrap();
+        let config = DatabaseConfig::load(base_path).unwrap_or_else(|e| {
+            eprintln!("{}; using the default settings", e);
+            DatabaseConfig::default()
+        });
         Self {
-            base_path: base_path.to_string()
+            base_path: base_path.to_string(),
+            durability: config.durability,
         }
     }
 
-    pub fn insert_row(&self, table: &TableSchema, values: Vec<&str>) {
-        for(i, column) in table.columns.iter().enumerate() {
-            let data_path = format!("{}/{}_{}.data", self.base_path, table.table_name, column.name);
-            let index_path = format!("{}/{}_{}.idx", self.base_path, table.table_name, column.name);
-
-            let mut data_file = OpenOptions::new().create(true).append(true).open(data_path).unwrap();
-            let mut index_file = OpenOptions::new().create(true).append(true).open(&index_path).unwrap();
+    /// Appends one row given as text. The whole row is checked first, so a row with a wrong
+    /// number of values or values that do not fit their columns writes nothing.
+    pub fn insert_row(&self, table: &TableSchema, values: Vec<&str>) -> Result<(), RowError> {
+        let row = parse_row(&table.columns, &values)?;
+        let mut writer = TableWriter::open(self, table).map_err(RowError::Write)?;
+        writer.append_values(row)?;
+        writer.finish().map(|_| ()).map_err(RowError::Write)
+    }
 
-            let offset = data_file.seek(SeekFrom::End(0)).unwrap();
+    /// Appends `rows` through one [`TableWriter`], so files are opened once per batch
+    /// instead of once per value. Every row is checked before any is written, and the bad ones
+    /// are reported with their position. Returns the number of rows written.
+    pub fn insert_rows(&self, table: &TableSchema, rows: &[Vec<&str>]) -> Result<usize, String> {
+        let mut parsed = Vec::with_capacity(rows.len());
+        let mut errors = Vec::new();
+        for (position, row) in rows.iter().enumerate() {
+            match parse_row(&table.columns, row) {
+                Ok(row) => parsed.push(row),
+                Err(e) if errors.len() < MAX_REPORTED_ERRORS => errors.push(format!("row {}: {}", position + 1, e)),
+                Err(_) => {}
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
 
-            let (mut min_value, mut max_value) = match column.data_type.as_str() {
-                "int" => {
-                    let val:i32 = values[i].parse().unwrap();
-                    data_file.write_all(&val.to_le_bytes()).unwrap();
-                    (val.to_string(), val.to_string())
-                }
-                "string" => {
-                    let val = values[i];
-                    let len = val.len() as u32;
-                    data_file.write_all(&len.to_le_bytes()).unwrap();
-                    data_file.write_all(val.as_bytes()).unwrap();
-                    (val.to_string(), val.to_string())
-                }
-                _ => panic!("Unsupported data type"),
-            };
+        let mut writer = TableWriter::open(self, table)?;
+        for row in parsed {
+            writer.append_values(row)?;
+        }
+        writer.finish()
+    }
 
-            let final_min = min_value.clone();
-            let final_max = max_value.clone();
+    /// Loads the records of a CSV file into `table`, converting each field to its column's type.
+    ///
+    /// Every record is checked before any is written, so a file with a bad value (reported with
+    /// its line number) leaves the table untouched. Returns the number of rows imported.
+    pub fn import_csv(&self, table: &TableSchema, path: &str, options: CsvOptions) -> Result<usize, String> {
+        let open = || {
+            File::open(path)
+                .map(|file| CsvReader::new(BufReader::new(file), options))
+                .map_err(|e| format!("Failed to open '{}': {}", path, e))
+        };
 
-            if let Ok(existing_index) = File::open(&index_path) {
-                let reader = BufReader::new(existing_index);
-                for line in reader.lines() {
-                    let entry: MinMaxIndex = serde_json::from_str(&line.unwrap()).unwrap();
-                    min_value = min_value.min(entry.min_value);
-                    max_value = max_value.max(entry.max_value);
+        let mut reader = open()?;
+        let (mapping, width) = column_mapping(table, &mut reader)?;
+        let mut errors = Vec::new();
+        while errors.len() < MAX_REPORTED_ERRORS {
+            match reader.next_record() {
+                Ok(Some((line, record))) => {
+                    if let Err(e) = check_record(table, &mapping, width, &record) {
+                        errors.push(format!("line {}: {}", line, e));
+                    }
+                }
+                Ok(None) => break,
+                // Past a malformed record the reader cannot tell where the next one starts.
+                Err(e) => {
+                    errors.push(e);
+                    break;
                 }
             }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
 
-            let index_entry = MinMaxIndex {
-                chunk_offset: offset,
-                min_value: final_min,
-                max_value: final_max,
-            };
-
-            let serialized_index = serde_json::to_string(&index_entry).unwrap() + "\n";
-            index_file.write_all(serialized_index.as_bytes()).unwrap();
+        let mut reader = open()?;
+        column_mapping(table, &mut reader)?;
+        let mut writer = TableWriter::open(self, table)?;
+        while let Some((_, record)) = reader.next_record()? {
+            let values: Vec<&str> = mapping.iter().map(|&field| record[field].as_str()).collect();
+            writer.append(&values)?;
         }
+        writer.finish()
     }
 
-    pub fn scan_column(&self, table: &TableSchema, column_name: &str) {
-        let path = format!("{}/{}_{}.data", self.base_path, table.table_name, column_name);
-        let file = File::open(path).unwrap();
-        let mut reader = BufReader::new(file);
-
-        match table.columns.iter().find(|c| c.name == column_name).unwrap().data_type.as_str() {
-            "int" => {
-                let mut buffer = [0u8; 4];
-                loop {
-                    match reader.read_exact(&mut buffer) {
-                    Ok(_) => {
-                        let val = i32::from_le_bytes(buffer);
-                        println!("Read value: {}", val);
-                    }
-                    Err(e) => {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            break;
-                        } else {
-                        panic!("Failed to read int column: {:?}", e);
-                        }
+    /// Infers a schema named `table_name` from the first `sample` records of a newline-delimited
+    /// JSON file, for [`ColumnStore::import_json`] to create.
+    pub fn infer_json_schema(&self, table_name: &str, path: &str, sample: usize) -> Result<TableSchema, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+        infer_schema(table_name, &mut JsonReader::new(BufReader::new(file)), sample)
+    }
+
+    /// Loads the objects of a newline-delimited JSON file into `table`, matching fields to
+    /// columns by name. With `create` the table is new and its schema is saved once every
+    /// record has been checked, so a failed import leaves nothing behind.
+    ///
+    /// As with [`ColumnStore::import_csv`], bad records are reported with their line numbers
+    /// before anything is written. Returns the number of rows imported.
+    pub fn import_json(&self, table: &TableSchema, path: &str, create: bool) -> Result<usize, String> {
+        let open = || {
+            File::open(path)
+                .map(|file| JsonReader::new(BufReader::new(file)))
+                .map_err(|e| format!("Failed to open '{}': {}", path, e))
+        };
+
+        let mut reader = open()?;
+        let mut errors = Vec::new();
+        while errors.len() < MAX_REPORTED_ERRORS {
+            match reader.next_record() {
+                Ok(Some((line, record))) => {
+                    if let Err(e) = record_values(table, &record) {
+                        errors.push(format!("line {}: {}", line, e));
                     }
                 }
+                Ok(None) => break,
+                // Records end at line breaks, so reading resumes at the next line.
+                Err(e) => errors.push(e),
             }
         }
-        "string" => {
-            loop {
-                let mut len_buf = [0u8; 4];
-                match reader.read_exact(&mut len_buf) {
-                    Ok(_) => {
-                        let len = u32::from_le_bytes(len_buf) as usize;
-                        let mut buffer = vec![0u8; len];
-                        reader.read_exact(&mut buffer).unwrap();
-                        let val = String::from_utf8(buffer).unwrap();
-                        println!("Read value: {}", val);
-                    }
-                    Err(e) => {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            break;
-                        } else {
-                            panic!("Failed to read string column: {:?}", e);
-                        }
-                        }
-                    }
-                }
-            }
-            _ => panic!("Unsupported data type"),
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
         }
-    }
 
-    pub fn filter_column(&self, table: &TableSchema, column_name: &str, predicate: &str) -> Vec<String> {
-        let index_path = format!("{}/{}_{}.idx", self.base_path, table.table_name, column_name);
-        let data_path = format!("{}/{}_{}.data", self.base_path, table.table_name, column_name);
+        if create {
+            table.save(&self.base_path)?;
+        }
+        let mut reader = open()?;
+        let mut writer = TableWriter::open(self, table)?;
+        while let Some((_, record)) = reader.next_record()? {
+            writer.append_values(record_values(table, &record)?)?;
+        }
+        writer.finish()
+    }
 
-        let index_file = File::open(&index_path).unwrap();
-        let mut data_file = File::open(&data_path).unwrap();
+    /// Loads an Arrow IPC file as a new table named `table_name`, one column per field.
+    ///
+    /// Every batch is converted before the schema is saved, so a file with values no column
+    /// can hold leaves nothing behind. Returns the new schema and the number of rows imported.
+    pub fn import_arrow(&self, table_name: &str, path: &str) -> Result<(TableSchema, usize), String> {
+        let open = || {
+            let file = File::open(path).map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+            FileReader::try_new(BufReader::new(file), None).map_err(|e| format!("Failed to read '{}': {}", path, e))
+        };
 
-        let reader = BufReader::new(index_file);
-        let mut results = Vec::new();
+        let reader = open()?;
+        let table = ipc::table_schema(table_name, &reader.schema())?;
+        let mut errors = Vec::new();
+        for (index, batch) in reader.enumerate() {
+            let batch = batch.map_err(|e| format!("batch {}: {}", index, e))?;
+            if let Err(e) = ipc::batch_values(&table, &batch) {
+                errors.push(format!("batch {}: {}", index, e));
+            }
+            if errors.len() == MAX_REPORTED_ERRORS {
+                break;
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
 
-        for line in reader.lines() {
-            let index_entry: MinMaxIndex = serde_json::from_str(&line.unwrap()).unwrap();
+        table.save(&self.base_path)?;
+        let mut writer = TableWriter::open(self, &table)?;
+        for batch in open()? {
+            let columns = ipc::batch_values(&table, &batch.map_err(|e| e.to_string())?)?;
+            for row in 0..columns.first().map_or(0, Vec::len) {
+                writer.append_values(columns.iter().map(|values| values[row].clone()).collect())?;
+            }
+        }
+        let count = writer.finish()?;
+        Ok((table, count))
+    }
 
-            if predicate < &index_entry.min_value || predicate > &index_entry.max_value {
-                continue;
+    /// Prints every value of a column, checking each chunk against its checksum first.
+    pub fn scan_column(&self, table: &TableSchema, column_name: &str) -> Result<(), String> {
+        let mut reader = self.open_reader(table, column_name)?;
+        for chunk in 0..reader.chunk_count() {
+            let values = reader.try_read(chunk)?;
+            let nulls = reader.read_nulls(chunk).unwrap_or_default();
+            for row in 0..values.len() {
+                let value = if nulls.get(row).copied().unwrap_or(false) { "null".to_string() } else { values.display(row) };
+                println!("Read value: {}", value);
             }
+        }
+        Ok(())
+    }
 
-            data_file.seek(SeekFrom::Start(index_entry.chunk_offset)).unwrap();
-            
-            match table.columns.iter().find(|c| c.name == column_name).unwrap().data_type.as_str() {
-                "int" => {
-                    let mut buffer = [0u8; 4];
-                    while data_file.read_exact(&mut buffer).is_ok() {
-                        let val = i32::from_le_bytes(buffer);
-                        if val.to_string() == predicate {
-                            results.push(val.to_string());
-                        }
-                    }
-                }
-                "string" => {
-                    let mut len_buf = [0u8; 4];
-                    while data_file.read_exact(&mut len_buf).is_ok() {
-                        let len = u32::from_le_bytes(len_buf) as usize;
-                        let mut buffer = vec![0u8; len];
-                        data_file.read_exact(&mut buffer).unwrap();
-                        let val = String::from_utf8(buffer).unwrap();
-                        if val == predicate {
-                            results.push(val);
-                        }
-                    }
+    /// The values of a column equal to `predicate`, read as a value of the column's type.
+    /// Null rows never match.
+    pub fn filter_column(&self, table: &TableSchema, column_name: &str, predicate: &str) -> Result<Vec<String>, String> {
+        let column = table
+            .columns
+            .iter()
+            .find(|c| c.name == column_name)
+            .ok_or_else(|| format!("Column '{}' not found in table '{}'", column_name, table.table_name))?;
+        let target = parse_value(&column.data_type, predicate)?.to_string();
+
+        let mut reader = self.open_reader(table, column_name)?;
+        let mut results = Vec::new();
+        for chunk in 0..reader.chunk_count() {
+            let values = reader.try_read(chunk)?;
+            let nulls = reader.read_nulls(chunk).unwrap_or_default();
+            for row in (0..values.len()).filter(|&row| !nulls.get(row).copied().unwrap_or(false)) {
+                let value = values.display(row);
+                if value == target {
+                    results.push(value);
                 }
-                _ => panic!("Unsupported data type"),
             }
         }
-
-        results
+        Ok(results)
     }
 
     pub fn filter_column_simd(&self, table: &TableSchema, column_name: &str, threshold_value: i32, op: SimdOp) {
-        let buffer = self.read_column_i32(table, column_name);
+        let (buffer, nulls) = match self.read_column_i32(table, column_name) {
+            Ok(read) => read,
+            Err(e) => {
+                println!("Read failed: {}", e);
+                return;
+            }
+        };
     
         let matching_indices = filter_simd_32(&buffer, threshold_value, op);
     
-        for idx in matching_indices {
+        for idx in matching_indices.into_iter().filter(|&idx| !nulls[idx]) {
             println!("Matched value at index {}: {}", idx, buffer[idx]);
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn filter_columns_logical_simd(
         &self,
         table: &TableSchema,
@@ -196,42 +283,453 @@ impl ColumnStore  {
         val2: i32,
         logic_op: LogicalOp,
     ) {
-        let buf1 = self.read_column_i32(table, col1);
-        let buf2 = self.read_column_i32(table, col2);
-    
-        let final_res = filter_with_logical_op(&buf1, op1, val1, &buf2, op2, val2, logic_op);
-    
-        for idx in final_res {
+        let predicate = Predicate::from_pair(col1, op1, val1, col2, op2, val2, logic_op);
+        self.filter_predicate_simd(table, &predicate);
+    }
+
+    pub fn filter_predicate_simd(&self, table: &TableSchema, predicate: &Predicate) {
+        let column_names = predicate.columns();
+        let mut columns = match self.open_chunks(table, &column_names) {
+            Ok(columns) => columns,
+            Err(e) => {
+                println!("Filter failed: {}", e);
+                return;
+            }
+        };
+
+        let stats = TableStats::load(&self.base_path, &table.table_name);
+        let ordered = CostModel::new(table, stats.as_ref()).order(predicate.clone());
+        let candidates = Bitmap::full(columns.row_count());
+        let final_res = match ordered.evaluate(&mut columns, &candidates) {
+            Ok(bitmap) => bitmap,
+            Err(e) => {
+                println!("Filter failed: {}", e);
+                return;
+            }
+        };
+
+        for idx in final_res.iter_ones() {
+            let values = column_names
+                .iter()
+                .map(|name| Ok(format!("{} = {}", name, columns.display(name, idx)?)))
+                .collect::<Result<Vec<String>, String>>();
+            match values {
+                Ok(values) => println!("Matched row at index {}: {}", idx, values.join(", ")),
+                Err(e) => {
+                    println!("Filter failed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Evaluates `projection` for every row matching `predicate` (or every row without one)
+    /// a chunk at a time, and prints the computed values.
+    pub fn select(&self, table: &TableSchema, projection: &[(Expr, String)], predicate: Option<&Predicate>) -> Result<(), String> {
+        for (expr, _) in projection {
+            expr.result_type(table)?;
+        }
+
+        let projected: Vec<&str> = projection.iter().flat_map(|(expr, _)| expr.columns()).collect();
+        let mut column_names: Vec<&str> = Vec::new();
+        for name in projected.iter().copied().chain(predicate.map(Predicate::columns).unwrap_or_default()) {
+            if !column_names.contains(&name) {
+                column_names.push(name);
+            }
+        }
+        if column_names.is_empty() {
+            // Nothing to read but the row count, which any column can tell.
+            column_names.extend(table.columns.first().map(|c| c.name.as_str()));
+        }
+        let mut columns = self.open_chunks(table, &column_names)?;
+
+        let all_rows = Bitmap::full(columns.row_count());
+        let stats = TableStats::load(&self.base_path, &table.table_name);
+        let selected = match predicate {
+            Some(predicate) => CostModel::new(table, stats.as_ref()).order(predicate.clone()).evaluate(&mut columns, &all_rows)?,
+            None => all_rows,
+        };
+
+        for chunk in 0..columns.chunk_count() {
+            let start = chunk * CHUNK_ROWS;
+            if !selected.any_in(start, start + CHUNK_ROWS) {
+                continue;
+            }
+
+            // The projection only sees the selected rows, so the filter guards what it computes.
+            let rows: Vec<usize> = (0..columns.chunk_len(chunk)).filter(|row| selected.get(start + row)).collect();
+            let batch = columns.batch(&projected, chunk)?.take(&rows);
+            let vectors = projection
+                .iter()
+                .map(|(expr, _)| expr.evaluate_nullable(&batch))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for (i, row) in rows.iter().enumerate() {
+                let values: Vec<String> = projection
+                    .iter()
+                    .zip(&vectors)
+                    .map(|((_, name), (vector, nulls))| {
+                        let value = if nulls.as_ref().is_some_and(|n| n.get(i)) { "null".to_string() } else { vector.display(i) };
+                        format!("{} = {}", name, value)
+                    })
+                    .collect();
+                println!("Row at index {}: {}", start + row, values.join(", "));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes every row of `table` matching `predicate` (or every row without one) to `path`
+    /// and returns how many were written.
+    ///
+    /// The table is streamed: the filter runs holding one chunk per column, then matching
+    /// rows are written a chunk at a time, so memory does not grow with the table.
+    pub fn export(&self, table: &TableSchema, path: &str, format: ExportFormat, predicate: Option<&Predicate>) -> Result<usize, String> {
+        let selected = match predicate {
+            Some(predicate) => {
+                let mut columns = self.open_chunks(table, &predicate.columns())?.streaming();
+                let stats = TableStats::load(&self.base_path, &table.table_name);
+                let ordered = CostModel::new(table, stats.as_ref()).order(predicate.clone());
+                let all_rows = Bitmap::full(columns.row_count());
+                Some(ordered.evaluate(&mut columns, &all_rows)?)
+            }
+            None => None,
+        };
+
+        let column_names: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+        let mut columns = self.open_chunks(table, &column_names)?.streaming();
+        let mut bounds = HashMap::new();
+        if format == ExportFormat::Parquet {
+            // The index of a nullable column written before nulls were left out of its bounds
+            // covers their placeholders, so the Parquet writer takes those statistics from the
+            // values instead.
+            for column in table.columns.iter().filter(|c| !c.nullable) {
+                let entries = self.read_index(table, &column.name)?;
+                bounds.insert(column.name.clone(), chunk_bounds(&entries, &columns.chunk_offsets(&column.name), &column.data_type));
+            }
+        }
+
+        let out = File::create(path).map_err(|e| format!("Failed to create '{}': {}", path, e))?;
+        let mut writer = ExportWriter::new(BufWriter::new(out), table, format, bounds)?;
+        let mut exported = 0;
+        for chunk in 0..columns.chunk_count() {
+            let start = chunk * CHUNK_ROWS;
+            if selected.as_ref().is_some_and(|s| !s.any_in(start, start + CHUNK_ROWS)) {
+                continue;
+            }
+
+            let batch = columns.batch(&column_names, chunk)?;
+            let rows: Vec<usize> = (0..batch.len).filter(|row| selected.as_ref().is_none_or(|s| s.get(start + row))).collect();
+            writer.write_rows(chunk, &batch, &rows)?;
+            exported += rows.len();
+        }
+
+        writer.finish()?;
+        Ok(exported)
+    }
+
+    /// Reads every column of `table` in full, then writes and prints its statistics.
+    pub fn analyze(&self, table: &TableSchema) -> Result<TableStats, String> {
+        let mut stats = TableStats { table_name: table.table_name.clone(), row_count: 0, columns: Vec::new() };
+
+        for column in &table.columns {
+            let name = column.name.as_str();
+            let mut chunks = self.open_chunks(table, &[name])?;
+            let mut values = Vector::empty(&column.data_type);
+            let mut null_count = 0;
+            for chunk in 0..chunks.chunk_count() {
+                let mut batch = chunks.batch(&[name], chunk)?;
+                let chunk_values = batch.columns.remove(name).unwrap();
+                match batch.nulls.get(name) {
+                    Some(nulls) => {
+                        let present: Vec<usize> = (0..batch.len).filter(|&row| !nulls.get(row)).collect();
+                        null_count += batch.len - present.len();
+                        values.append(chunk_values.take(&present));
+                    }
+                    None => values.append(chunk_values),
+                }
+            }
+
+            stats.row_count = stats.row_count.max((values.len() + null_count) as u64);
+            let mut column_stats = match values {
+                Vector::Int(values) => ColumnStats::from_ints(name, values),
+                Vector::Float(values) => ColumnStats::from_floats(name, &values),
+                Vector::Str(values) => ColumnStats::from_strings(name, &values),
+                Vector::Bool(values) => ColumnStats::from_strings(name, &values.iter().map(bool::to_string).collect::<Vec<_>>()),
+            };
+            column_stats.data_type = column.data_type.clone();
+            column_stats.null_count = null_count as u64;
+            stats.columns.push(column_stats);
+        }
+
+        stats.save(&self.base_path)?;
+
+        println!("Analyzed '{}': {} rows", table.table_name, stats.row_count);
+        for column in &stats.columns {
             println!(
-                "Matched row at index {}: {} = {}, {} = {}",
-                idx, col1, buf1[idx], col2, buf2[idx]
+                "- {}: nulls {}, min {}, max {}, ~{} distinct, {} histogram buckets",
+                column.name,
+                column.null_count,
+                column.min_value.as_deref().unwrap_or("-"),
+                column.max_value.as_deref().unwrap_or("-"),
+                column.distinct_count,
+                column.histogram.len()
             );
         }
+
+        Ok(stats)
+    }
+
+    /// Chunk readers over `column_names`, failing on a column `table` does not have.
+    pub fn open_chunks(&self, table: &TableSchema, column_names: &[&str]) -> Result<ColumnChunks, String> {
+        let mut readers = HashMap::new();
+        for name in column_names {
+            readers.insert(name.to_string(), self.open_reader(table, name)?);
+        }
+        Ok(ColumnChunks::new(readers))
+    }
+
+    /// A chunk reader over one column that checks the checksums of its index.
+    fn open_reader(&self, table: &TableSchema, column_name: &str) -> Result<ChunkReader, String> {
+        let column = table
+            .columns
+            .iter()
+            .find(|c| c.name == column_name)
+            .ok_or_else(|| format!("Column '{}' not found in table '{}'", column_name, table.table_name))?;
+        let path = format!("{}/{}_{}.data", self.base_path, table.table_name, column_name);
+        let mut reader = ChunkReader::open(&path, &column.data_type, table.format_version)
+            .map_err(|e| format!("Failed to open '{}': {}", path, e))?
+            .with_checksums(&table.table_name, column_name, &self.read_index(table, column_name)?);
+        if column.nullable {
+            reader = reader.with_nulls(&format!("{}/{}_{}.nulls", self.base_path, table.table_name, column_name));
+        }
+        Ok(reader)
+    }
+
+    /// Rows in `table`, from the first fixed width column's file size when there is one, else
+    /// from the last entry of the first column's index. Only indexes from before entries kept
+    /// their row have every string counted.
+    pub fn row_count(&self, table: &TableSchema) -> usize {
+        if let Some((column, width)) = table.columns.iter().find_map(|c| Some((c, fixed_width(&c.data_type)?))) {
+            return self.open_data(table, &column.name).map_or(0, |(_, len)| len as usize / width);
+        }
+        let Some(column) = table.columns.first() else {
+            return 0;
+        };
+        self.indexed_row_count(table, &column.name)
+            .unwrap_or_else(|| self.open_chunks(table, &[column.name.as_str()]).map_or(0, |chunks| chunks.row_count()))
+    }
+
+    /// Rows of a string column from the row of its last index entry and the values from there
+    /// on, at most a chunk of them. `None` when the entry has no row or does not match the data.
+    fn indexed_row_count(&self, table: &TableSchema, column_name: &str) -> Option<usize> {
+        let last = self.read_index(table, column_name).ok()?.pop()?;
+        let (mut file, len) = self.open_data(table, column_name).ok()?;
+        let tail_len = len.checked_sub(last.chunk_offset)?;
+        file.seek(SeekFrom::Current(last.chunk_offset as i64)).ok()?;
+        let tail = read_strings(&mut BufReader::new(file).take(tail_len)).ok()?.len();
+        Some(last.row? as usize + tail)
     }
 
     pub fn filter_column_simd_avx(&self, table: &TableSchema, column_name: &str, threshold_value: i32, op: SimdOp) {
-        let buffer = self.read_column_i32(table, column_name);
+        let (buffer, nulls) = match self.read_column_i32(table, column_name) {
+            Ok(read) => read,
+            Err(e) => {
+                println!("Read failed: {}", e);
+                return;
+            }
+        };
     
         let matching_indices = filter_simd_32_avx(&buffer, threshold_value, op);
     
-        for idx in matching_indices {
+        for idx in matching_indices.into_iter().filter(|&idx| !nulls[idx]) {
             println!("Matched value at index {}: {}", idx, buffer[idx]);
         }
     }
 
-    fn read_column_i32(&self, table: &TableSchema, column_name: &str) -> Vec<i32> {
+    /// The entries of a column's index, none when it has no `.idx` file. Fails on a header that
+    /// does not match the schema and on any other line that is not an entry.
+    pub fn read_index(&self, table: &TableSchema, column_name: &str) -> Result<Vec<MinMaxIndex>, String> {
+        let column = table
+            .columns
+            .iter()
+            .find(|c| c.name == column_name)
+            .ok_or_else(|| format!("Column '{}' not found in table '{}'", column_name, table.table_name))?;
+        let index_path = format!("{}/{}_{}.idx", self.base_path, table.table_name, column_name);
+        let index_file = match File::open(&index_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to open '{}': {}", index_path, e)),
+        };
+
+        let mut entries = Vec::new();
+        for (line, text) in BufReader::new(index_file).lines().enumerate() {
+            let text = text.map_err(|e| format!("Failed to read '{}': {}", index_path, e))?;
+            if line == 0 && read_index_header(&text, &index_path, &column.data_type, table.format_version)? {
+                continue;
+            }
+            let entry = serde_json::from_str(&text)
+                .map_err(|e| format!("line {} of '{}' is not a valid index entry: {}", line + 1, index_path, e))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Size of the `.idx` file of a column, 0 when it has none.
+    pub fn index_bytes(&self, table: &TableSchema, column_name: &str) -> u64 {
+        let index_path = format!("{}/{}_{}.idx", self.base_path, table.table_name, column_name);
+        fs::metadata(index_path).map_or(0, |m| m.len())
+    }
+
+    /// Size of the `.data` files of every column of `table`.
+    pub fn data_bytes(&self, table: &TableSchema) -> u64 {
+        table
+            .columns
+            .iter()
+            .map(|c| format!("{}/{}_{}.data", self.base_path, table.table_name, c.name))
+            .map(|path| fs::metadata(path).map_or(0, |m| m.len()))
+            .sum()
+    }
+
+    /// Checks that the schema and every `.data` and `.idx` file of `table` have a format version
+    /// this build reads, and that the files hold the schema's types. The files of a schema of
+    /// version 0 are from before headers existed and pass; those of a later one must have one.
+    pub fn check_format(&self, table: &TableSchema) -> Result<(), String> {
+        if table.format_version > FORMAT_VERSION {
+            return Err(format!(
+                "its schema has format version {}, this build reads versions up to {}",
+                table.format_version, FORMAT_VERSION
+            ));
+        }
+        if Path::new(&backup_dir(&self.base_path, &table.table_name)).exists() {
+            return Err("an upgrade of it was cut short, run `upgrade` to finish it".to_string());
+        }
+        for column in &table.columns {
+            let data_path = format!("{}/{}_{}.data", self.base_path, table.table_name, column.name);
+            if let Ok(mut file) = File::open(&data_path) {
+                read_data_header(&mut file, &data_path, &column.data_type, table.format_version)?;
+            }
+            let index_path = format!("{}/{}_{}.idx", self.base_path, table.table_name, column.name);
+            if let Some(Ok(line)) = File::open(&index_path).ok().and_then(|file| BufReader::new(file).lines().next()) {
+                read_index_header(&line, &index_path, &column.data_type, table.format_version)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the files of `table` are consistent; see [`verify_table`].
+    pub fn verify(&self, table: &TableSchema) -> Report {
+        verify_table(&self.base_path, table)
+    }
+
+    /// Rewrites the files of `table` from before the current layout; see [`upgrade_table`].
+    pub fn upgrade(&self, table: &TableSchema) -> Result<Upgrade, String> {
+        upgrade_table(&self.base_path, table)
+    }
+
+    /// Rebuilds the `.idx` files of `table` from its `.data` files, for every column or only
+    /// `column_name`, holding the table lock so no write appends meanwhile. Returns the columns
+    /// rebuilt with their number of index entries.
+    pub fn reindex(&self, table: &TableSchema, column_name: Option<&str>) -> Result<Vec<(String, usize)>, String> {
+        let columns: Vec<&Column> = match column_name {
+            Some(name) => vec![table
+                .columns
+                .iter()
+                .find(|c| c.name == name)
+                .ok_or_else(|| format!("Column '{}' not found in table '{}'", name, table.table_name))?],
+            None => table.columns.iter().collect(),
+        };
+        let _lock = lock_for_rewrite(&self.base_path, &table.table_name)?;
+        columns
+            .into_iter()
+            .map(|column| rebuild_index(&self.base_path, table, column).map(|entries| (column.name.clone(), entries)))
+            .collect()
+    }
+
+    /// Zone map of an int column from its `.idx` file, `None` for other types.
+    pub fn load_zone_map(&self, table: &TableSchema, column_name: &str) -> Option<ZoneMap> {
+        let column = table.columns.iter().find(|c| c.name == column_name)?;
+        if column.data_type != "int" {
+            return None;
+        }
+
+        let row_count = self.open_data(table, column_name).ok()?.1 as usize / 4;
+        Some(ZoneMap::from_index(&self.read_index(table, column_name).ok()?, row_count))
+    }
+
+    /// Opens the `.data` file of a column at its first value, returning it with the length of
+    /// its values.
+    fn open_data(&self, table: &TableSchema, column_name: &str) -> Result<(File, u64), String> {
+        let column = table
+            .columns
+            .iter()
+            .find(|c| c.name == column_name)
+            .ok_or_else(|| format!("Column '{}' not found in table '{}'", column_name, table.table_name))?;
         let path = format!("{}/{}_{}.data", self.base_path, table.table_name, column_name);
-        let file = File::open(path).unwrap();
-        let mut reader = BufReader::new(file);
-        let mut buffer = Vec::new();
-        let mut val_buf = [0u8; 4];
-    
-        while reader.read_exact(&mut val_buf).is_ok() {
-            buffer.push(i32::from_le_bytes(val_buf));
+        let mut file = File::open(&path).map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+        let start = read_data_header(&mut file, &path, &column.data_type, table.format_version)?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        Ok((file, len - start))
+    }
+
+    /// Every value of an int column, with a flag per row telling whether it is null.
+    fn read_column_i32(&self, table: &TableSchema, column_name: &str) -> Result<(Vec<i32>, Vec<bool>), String> {
+        let mut reader = self.open_reader(table, column_name)?;
+        let mut buffer = Vec::with_capacity(reader.row_count());
+        let mut nulls = Vec::with_capacity(reader.row_count());
+        for chunk in 0..reader.chunk_count() {
+            match reader.try_read(chunk)? {
+                Vector::Int(values) => {
+                    nulls.extend(reader.read_nulls(chunk).unwrap_or_else(|| vec![false; values.len()]).into_iter().take(values.len()));
+                    buffer.extend(values);
+                }
+                other => return Err(format!("Column '{}' holds {} values, SIMD kernels need int", column_name, other.data_type())),
+            }
         }
-    
-        buffer
+        Ok((buffer, nulls))
     }
     
     
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_column_matches_typed_values_and_skips_nulls() {
+        let dir = std::env::temp_dir().join(format!("cdbe-column-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let table = TableSchema::new("t".to_string(), ["score:float?", "at:timestamp"].map(String::from).to_vec());
+        store
+            .insert_rows(&table, &[vec!["1.5", "2024-01-01"], vec!["", "2024-01-01T00:00:00Z"], vec!["1.50", "2024-01-02"]])
+            .unwrap();
+
+        // The placeholder of the null row is 0, which must not match.
+        assert_eq!(store.filter_column(&table, "score", "1.5").unwrap(), ["1.5", "1.5"]);
+        assert!(store.filter_column(&table, "score", "0").unwrap().is_empty());
+        assert_eq!(store.filter_column(&table, "at", "2024-01-01 00:00").unwrap().len(), 2);
+        assert_eq!(store.filter_column(&table, "score", "x").unwrap_err(), "'x' is not a valid float");
+        assert_eq!(store.filter_column(&table, "nope", "1").unwrap_err(), "Column 'nope' not found in table 't'");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_index_fails_on_a_line_that_is_not_an_entry() {
+        let dir = std::env::temp_dir().join(format!("cdbe-column-index-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        assert!(store.read_index(&table, "id").unwrap().is_empty());
+
+        store.insert_rows(&table, &[vec!["3"]]).unwrap();
+        assert_eq!(store.read_index(&table, "id").unwrap().len(), 1);
+        let index_path = dir.join("t_id.idx");
+        fs::write(&index_path, fs::read_to_string(&index_path).unwrap() + "{\"chunk_offset\":\n").unwrap();
+        let error = store.read_index(&table, "id").unwrap_err();
+        assert!(error.starts_with("line 3 of '"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// When writes are forced to disk with fsync, trading ingest speed against what a crash of the
+/// machine can lose. A crash of the process alone loses nothing at any level: what was written
+/// is in the operating system's cache, and unfinished writes are rolled back on the next start.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Durability {
+    /// Never fsync. A crash of the machine may lose recent writes or keep part of one.
+    None,
+    /// Fsync every file a write appended to before the write counts as finished, so an insert
+    /// or import is on disk once it returns.
+    #[default]
+    PerBatch,
+    /// Fsync after every row, which is committed on its own: a crash loses at most the row being
+    /// written, but an insert or import cut short keeps the rows before it.
+    PerRow,
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::None => write!(f, "none"),
+            Durability::PerBatch => write!(f, "per-batch"),
+            Durability::PerRow => write!(f, "per-row"),
+        }
+    }
+}
+
+/// Settings of one database, kept in `config.json` in its data directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DatabaseConfig {
+    #[serde(default)]
+    pub durability: Durability,
+}
+
+impl DatabaseConfig {
+    /// The settings of the database in `base_path`, the defaults if none were saved.
+    pub fn load(base_path: &str) -> Result<Self, String> {
+        let path = config_path(base_path);
+        match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("'{}' is not a valid config: {}", path, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read '{}': {}", path, e)),
+        }
+    }
+
+    /// Replaces the saved settings, renaming a complete and synced new file into place.
+    pub fn save(&self, base_path: &str) -> Result<(), String> {
+        let path = config_path(base_path);
+        write_atomic(&path, serde_json::to_string_pretty(self).unwrap().as_bytes(), true)
+            .map_err(|e| format!("Failed to write '{}': {}", path, e))
+    }
+}
+
+fn config_path(base_path: &str) -> String {
+    format!("{}/config.json", base_path)
+}
+
+/// Fsyncs the directory at `path`, making the files created, renamed or removed in it durable.
+pub fn sync_dir(path: &str) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Replaces the file at `path` with `bytes`, written under a temporary name next to it and
+/// renamed into place once complete, so a crash leaves either the old file or the new one. With
+/// `sync`, the new file is fsynced before the rename and its directory after it.
+pub fn write_atomic(path: &str, bytes: &[u8], sync: bool) -> io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path)?;
+    file.write_all(bytes)?;
+    if sync {
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)?;
+    match Path::new(path).parent().and_then(Path::to_str) {
+        Some(dir) if sync => sync_dir(if dir.is_empty() { "." } else { dir }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_default_until_saved() {
+        let dir = std::env::temp_dir().join(format!("cdbe-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let base_path = dir.to_str().unwrap();
+        assert_eq!(DatabaseConfig::load(base_path).unwrap().durability, Durability::PerBatch);
+
+        DatabaseConfig { durability: Durability::PerRow }.save(base_path).unwrap();
+        assert_eq!(fs::read_to_string(config_path(base_path)).unwrap(), "{\n  \"durability\": \"per-row\"\n}");
+        assert_eq!(DatabaseConfig::load(base_path).unwrap().durability, Durability::PerRow);
+
+        fs::write(config_path(base_path), "{\"durability\": \"always\"}").unwrap();
+        assert!(DatabaseConfig::load(base_path).unwrap_err().contains("is not a valid config"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_whole_file() {
+        let dir = std::env::temp_dir().join(format!("cdbe-write-atomic-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("f.json");
+        let path = path.to_str().unwrap();
+
+        for (bytes, sync) in [(&b"a longer first version"[..], true), (b"short", false)] {
+            write_atomic(path, bytes, sync).unwrap();
+            assert_eq!(fs::read(path).unwrap(), bytes);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::BufRead;
+
+use super::{table::TableSchema, writer::parse_row};
+
+/// How fields of a CSV file are separated and quoted.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    /// Whether the first record names the columns.
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: ',', quote: '"', header: true }
+    }
+}
+
+/// Reads CSV records one at a time. Quoted fields may contain the delimiter, line breaks and
+/// doubled quotes; `\r\n` line endings are accepted.
+pub struct CsvReader<R> {
+    reader: R,
+    options: CsvOptions,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R, options: CsvOptions) -> Self {
+        Self { reader, options, line: 0 }
+    }
+
+    /// The next record and the line it starts on, `None` at the end of the input.
+    /// Blank lines are skipped.
+    pub fn next_record(&mut self) -> Result<Option<(usize, Vec<String>)>, String> {
+        let mut text = String::new();
+        loop {
+            text.clear();
+            if self.read_line(&mut text)? == 0 {
+                return Ok(None);
+            }
+            if !text.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+
+        let start = self.line;
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        let mut quoted = false;
+        let mut was_quoted = false;
+
+        loop {
+            if i == chars.len() {
+                if !quoted {
+                    break;
+                }
+                // A quoted field runs on to the next line.
+                let mut more = String::new();
+                if self.read_line(&mut more)? == 0 {
+                    return Err(format!("line {}: unterminated quoted field", start));
+                }
+                chars.extend(more.chars());
+                continue;
+            }
+
+            let c = chars[i];
+            i += 1;
+            if quoted {
+                if c == quote {
+                    if chars.get(i) == Some(&quote) {
+                        field.push(quote);
+                        i += 1;
+                    } else {
+                        quoted = false;
+                    }
+                } else {
+                    field.push(c);
+                }
+            } else if c == quote && field.is_empty() && !was_quoted {
+                quoted = true;
+                was_quoted = true;
+            } else if c == delimiter {
+                fields.push(std::mem::take(&mut field));
+                was_quoted = false;
+            } else if c == '\n' || (c == '\r' && chars.get(i) == Some(&'\n')) {
+                break;
+            } else if was_quoted {
+                return Err(format!("line {}: unexpected '{}' after closing quote", self.line, c));
+            } else {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+
+        Ok(Some((start, fields)))
+    }
+
+    fn read_line(&mut self, buffer: &mut String) -> Result<usize, String> {
+        let read = self.reader.read_line(buffer).map_err(|e| format!("line {}: {}", self.line + 1, e))?;
+        if read > 0 {
+            self.line += 1;
+        }
+        Ok(read)
+    }
+}
+
+/// For each column of `table`, the position of its field in a record.
+///
+/// With a header the fields are matched by name, in any order; without one they must follow
+/// the schema. Returns the mapping and the number of fields every record must have.
+pub fn column_mapping<R: BufRead>(table: &TableSchema, reader: &mut CsvReader<R>) -> Result<(Vec<usize>, usize), String> {
+    if !reader.options.header {
+        return Ok(((0..table.columns.len()).collect(), table.columns.len()));
+    }
+
+    let Some((line, header)) = reader.next_record()? else {
+        return Err("file is empty, expected a header".to_string());
+    };
+    let header: Vec<&str> = header.iter().map(|name| name.trim()).collect();
+    if let Some(unknown) = header.iter().find(|name| table.columns.iter().all(|c| c.name != **name)) {
+        return Err(format!("line {}: column '{}' is not in table '{}'", line, unknown, table.table_name));
+    }
+
+    let mapping = table
+        .columns
+        .iter()
+        .map(|column| {
+            header
+                .iter()
+                .position(|name| *name == column.name)
+                .ok_or_else(|| format!("line {}: header has no column '{}'", line, column.name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((mapping, header.len()))
+}
+
+/// Checks that `record` has `width` fields and each mapped field converts to its column's type,
+/// or is empty for a nullable column.
+pub fn check_record(table: &TableSchema, mapping: &[usize], width: usize, record: &[String]) -> Result<(), String> {
+    if record.len() != width {
+        return Err(format!("expected {} fields, found {}", width, record.len()));
+    }
+    let values: Vec<&str> = mapping.iter().map(|&field| record[field].as_str()).collect();
+    parse_row(&table.columns, &values)?;
+    Ok(())
+}
+
+/// Formats one field for writing, quoting it when it contains the delimiter, the quote or a
+/// line break so `CsvReader` reads back the same text.
+pub fn quote_field(field: &str, options: &CsvOptions) -> String {
+    if !field.contains([options.delimiter, options.quote, '\n', '\r']) {
+        return field.to_string();
+    }
+    let doubled = format!("{0}{0}", options.quote);
+    format!("{1}{0}{1}", field.replace(options.quote, &doubled), options.quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(text: &str) -> Vec<(usize, Vec<String>)> {
+        let mut reader = CsvReader::new(text.as_bytes(), CsvOptions::default());
+        std::iter::from_fn(|| reader.next_record().unwrap()).collect()
+    }
+
+    #[test]
+    fn quoted_field_spans_lines() {
+        let records = records("id,note\r\n1,\"first\nsecond\r\nthird\"\n2,plain\n");
+        let fields: Vec<Vec<&str>> = records.iter().map(|(_, fields)| fields.iter().map(String::as_str).collect()).collect();
+        assert_eq!(fields, [vec!["id", "note"], vec!["1", "first\nsecond\r\nthird"], vec!["2", "plain"]]);
+        // Records are numbered by the line they start on.
+        assert_eq!(records.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [1, 2, 5]);
+    }
+
+    #[test]
+    fn doubled_quotes_are_one_quote() {
+        let records = records("\"say \"\"hi\"\"\",\"\"\"\",\"a,b\"\n");
+        assert_eq!(records[0].1, ["say \"hi\"", "\"", "a,b"]);
+
+        let options = CsvOptions::default();
+        let field = "a \"quoted\",\nfield";
+        let line = quote_field(field, &options) + "\n";
+        assert_eq!(self::records(&line)[0].1, [field]);
+        assert_eq!(quote_field("plain", &options), "plain");
+    }
+
+    #[test]
+    fn unterminated_quote_fails() {
+        let mut reader = CsvReader::new("1,\"open\n2,3\n".as_bytes(), CsvOptions::default());
+        assert_eq!(reader.next_record().unwrap_err(), "line 1: unterminated quoted field");
+    }
+
+    #[test]
+    fn header_maps_fields_by_name() {
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string"].map(String::from).to_vec());
+        let mut reader = CsvReader::new("name, id\nann,1\n".as_bytes(), CsvOptions::default());
+        let (mapping, width) = column_mapping(&table, &mut reader).unwrap();
+        assert_eq!((mapping.clone(), width), (vec![1, 0], 2));
+
+        let (_, record) = reader.next_record().unwrap().unwrap();
+        assert!(check_record(&table, &mapping, width, &record).is_ok());
+        let bad = ["ann".to_string(), "x".to_string()];
+        assert_eq!(check_record(&table, &mapping, width, &bad).unwrap_err(), "column 'id': 'x' is not a valid int");
+        assert_eq!(check_record(&table, &mapping, width, &bad[..1]).unwrap_err(), "expected 2 fields, found 1");
+
+        let mut reader = CsvReader::new("id,age\n".as_bytes(), CsvOptions::default());
+        assert_eq!(column_mapping(&table, &mut reader).unwrap_err(), "line 1: column 'age' is not in table 't'");
+        let mut reader = CsvReader::new("id\n".as_bytes(), CsvOptions::default());
+        assert_eq!(column_mapping(&table, &mut reader).unwrap_err(), "line 1: header has no column 'name'");
+    }
+}
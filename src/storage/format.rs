@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// First bytes of every `.data` file, and the `magic` of every `.idx` header.
+pub const MAGIC: &[u8; 4] = b"CDBE";
+
+/// The file layout this build writes. Files from before headers existed count as version 0.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Bytes ahead of the first value of a `.data` file: the magic, the version as a little endian
+/// u16, the column type's code and reserved zeros. Index entries count offsets from the first
+/// value, so they mean the same with and without a header.
+pub const DATA_HEADER_LEN: u64 = 16;
+
+/// The first line of an `.idx` file, ahead of its entries.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexHeader {
+    pub magic: String,
+    pub version: u16,
+    pub data_type: String,
+}
+
+fn type_code(data_type: &str) -> u8 {
+    match data_type {
+        "int" => 1,
+        "float" => 2,
+        "bool" => 3,
+        "string" => 4,
+        "timestamp" => 5,
+        _ => 0,
+    }
+}
+
+fn type_name(code: u8) -> Option<&'static str> {
+    ["int", "float", "bool", "string", "timestamp"].into_iter().find(|&name| type_code(name) == code)
+}
+
+/// The header of a new `.data` file holding `data_type` values.
+pub fn data_header(data_type: &str) -> [u8; DATA_HEADER_LEN as usize] {
+    let mut header = [0u8; DATA_HEADER_LEN as usize];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[6] = type_code(data_type);
+    header
+}
+
+/// Reads the header of the `.data` file at `path`, which should hold `data_type` values, and
+/// leaves `file` at the first value. Returns where the values start: after the header, or at 0
+/// when the table's `format_version` is 0 and its files have none, whatever their first bytes.
+/// Refuses a file without a header in a table that has them, a version newer than this build
+/// and a type other than the schema's.
+pub fn read_data_header(file: &mut File, path: &str, data_type: &str, format_version: u16) -> Result<u64, String> {
+    if format_version == 0 {
+        file.seek(SeekFrom::Start(0)).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+        return Ok(0);
+    }
+
+    let mut header = Vec::with_capacity(DATA_HEADER_LEN as usize);
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.take(DATA_HEADER_LEN).read_to_end(&mut header))
+        .map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+
+    if !header.starts_with(MAGIC) {
+        return Err(format!("'{}' does not start with a cdbe header", path));
+    }
+    if header.len() < DATA_HEADER_LEN as usize {
+        return Err(format!("'{}' has a truncated header", path));
+    }
+    check_version(path, u16::from_le_bytes([header[4], header[5]]))?;
+    match type_name(header[6]) {
+        Some(name) if name == data_type => Ok(DATA_HEADER_LEN),
+        Some(name) => Err(format!("'{}' holds {} values, the schema says {}", path, name, data_type)),
+        None => Err(format!("'{}' holds values of unknown type code {}", path, header[6])),
+    }
+}
+
+/// The header line of a new `.idx` file for a column of `data_type`, newline included.
+pub fn index_header(data_type: &str) -> String {
+    let header = IndexHeader { magic: String::from_utf8_lossy(MAGIC).into_owned(), version: FORMAT_VERSION, data_type: data_type.to_string() };
+    serde_json::to_string(&header).unwrap() + "\n"
+}
+
+/// Checks the first line of the `.idx` file at `path`. Returns whether it is a header, which it
+/// must be unless the table's `format_version` is 0; then the first line is already an entry.
+pub fn read_index_header(line: &str, path: &str, data_type: &str, format_version: u16) -> Result<bool, String> {
+    if format_version == 0 {
+        return Ok(false);
+    }
+    let header = serde_json::from_str::<IndexHeader>(line).ok().filter(|header| header.magic.as_bytes() == MAGIC);
+    let Some(header) = header else {
+        return Err(format!("'{}' does not start with a cdbe index header", path));
+    };
+    check_version(path, header.version)?;
+    if header.data_type != data_type {
+        return Err(format!("'{}' indexes {} values, the schema says {}", path, header.data_type, data_type));
+    }
+    Ok(true)
+}
+
+fn check_version(path: &str, version: u16) -> Result<(), String> {
+    match version {
+        1..=FORMAT_VERSION => Ok(()),
+        _ => Err(format!("'{}' has format version {}, this build reads versions up to {}", path, version, FORMAT_VERSION)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::{column::ColumnStore, table::TableSchema};
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn data_headers_name_the_version_and_type() {
+        let dir = test_dir("format-data");
+        let path = dir.join("t_a.data");
+        let path_str = path.to_str().unwrap();
+
+        let mut bytes = data_header("float").to_vec();
+        bytes.extend(1.5f64.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(read_data_header(&mut file, path_str, "float", FORMAT_VERSION).unwrap(), DATA_HEADER_LEN);
+        assert_eq!(file.stream_position().unwrap(), DATA_HEADER_LEN);
+        assert!(read_data_header(&mut file, path_str, "int", FORMAT_VERSION).unwrap_err().ends_with("holds float values, the schema says int"));
+
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let error = read_data_header(&mut File::open(&path).unwrap(), path_str, "float", FORMAT_VERSION).unwrap_err();
+        assert!(error.ends_with(&format!("has format version {}, this build reads versions up to {}", FORMAT_VERSION + 1, FORMAT_VERSION)));
+
+        // A file from before headers existed starts with its first value, which only a table of
+        // version 0 expects.
+        fs::write(&path, 7i32.to_le_bytes()).unwrap();
+        assert_eq!(read_data_header(&mut File::open(&path).unwrap(), path_str, "int", 0).unwrap(), 0);
+        let error = read_data_header(&mut File::open(&path).unwrap(), path_str, "int", FORMAT_VERSION).unwrap_err();
+        assert!(error.ends_with("does not start with a cdbe header"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn index_headers_are_checked_unless_the_table_predates_them() {
+        let header = index_header("int");
+        assert!(read_index_header(header.trim_end(), "t.idx", "int", FORMAT_VERSION).unwrap());
+        assert_eq!(read_index_header(header.trim_end(), "t.idx", "string", FORMAT_VERSION).unwrap_err(), "'t.idx' indexes int values, the schema says string");
+        let entry = r#"{"chunk_offset":0,"min_value":"1","max_value":"2"}"#;
+        assert!(!read_index_header(entry, "t.idx", "int", 0).unwrap());
+        assert_eq!(read_index_header(entry, "t.idx", "int", FORMAT_VERSION).unwrap_err(), "'t.idx' does not start with a cdbe index header");
+        assert_eq!(
+            read_index_header(r#"{"magic":"XXXX","version":1,"data_type":"int"}"#, "t.idx", "int", FORMAT_VERSION).unwrap_err(),
+            "'t.idx' does not start with a cdbe index header"
+        );
+    }
+
+    #[test]
+    fn headerless_files_are_read_and_appended_to_as_they_are() {
+        let dir = test_dir("format-legacy");
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let mut table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        table.format_version = 0;
+        fs::write(dir.join("t_id.data"), [3i32, 4].map(i32::to_le_bytes).concat()).unwrap();
+        fs::write(dir.join("t_id.idx"), "{\"chunk_offset\":0,\"min_value\":\"3\",\"max_value\":\"4\"}\n").unwrap();
+
+        store.check_format(&table).unwrap();
+        store.insert_rows(&table, &[vec!["5"]]).unwrap();
+        assert_eq!(fs::metadata(dir.join("t_id.data")).unwrap().len(), 12);
+        assert_eq!(store.read_index(&table, "id").unwrap().len(), 2);
+        assert_eq!(store.open_chunks(&table, &["id"]).unwrap().display("id", 2).unwrap(), "5");
+
+        // A schema of the current version expects headers the files do not have.
+        table.format_version = FORMAT_VERSION;
+        assert!(store.check_format(&table).unwrap_err().ends_with("does not start with a cdbe header"));
+
+        // Its new files get headers, and a header of the wrong type keeps the table from loading.
+        let other = TableSchema::new("u".to_string(), vec!["id:int".to_string()]);
+        store.insert_rows(&other, &[vec!["1"]]).unwrap();
+        assert!(fs::read(dir.join("u_id.data")).unwrap().starts_with(MAGIC));
+        let renamed = TableSchema::new("u".to_string(), vec!["id:float".to_string()]);
+        assert!(store.check_format(&renamed).unwrap_err().ends_with("holds int values, the schema says float"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
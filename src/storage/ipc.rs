@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{
+        Date32Type, Date64Type, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, TimestampMicrosecondType,
+        TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+    },
+    Array, ArrayRef, ArrowPrimitiveType, BooleanArray, Float64Array, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+
+use super::{
+    column::Column,
+    format::FORMAT_VERSION,
+    table::TableSchema,
+    timestamp,
+    vector::{Batch, Vector},
+    writer::Value,
+};
+
+/// The Arrow schema of `table`: int is Int32, float Float64, bool Boolean, string Utf8 and
+/// timestamp a UTC Timestamp in microseconds.
+pub fn arrow_schema(table: &TableSchema) -> Schema {
+    let fields: Vec<Field> = table
+        .columns
+        .iter()
+        .map(|column| {
+            let data_type = match column.data_type.as_str() {
+                "int" => DataType::Int32,
+                "float" => DataType::Float64,
+                "bool" => DataType::Boolean,
+                "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                _ => DataType::Utf8,
+            };
+            Field::new(&column.name, data_type, column.nullable)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+/// The given `rows` of `batch` as an Arrow record batch of `schema`, with the batch's nulls as
+/// Arrow nulls.
+pub fn record_batch(table: &TableSchema, schema: &Arc<Schema>, batch: &Batch, rows: &[usize]) -> Result<RecordBatch, ArrowError> {
+    let arrays = table
+        .columns
+        .iter()
+        .map(|column| {
+            let vector = &batch.columns[&column.name];
+            let present = |row: usize| !batch.is_null(&column.name, row);
+            let array: ArrayRef = match (column.data_type.as_str(), vector) {
+                ("timestamp", Vector::Str(v)) => Arc::new(
+                    TimestampMicrosecondArray::from_iter(rows.iter().map(|&r| present(r).then(|| timestamp::parse(&v[r]).unwrap())))
+                        .with_timezone("UTC"),
+                ),
+                (_, Vector::Int(v)) => Arc::new(Int32Array::from_iter(rows.iter().map(|&r| present(r).then_some(v[r])))),
+                (_, Vector::Float(v)) => Arc::new(Float64Array::from_iter(rows.iter().map(|&r| present(r).then_some(v[r])))),
+                (_, Vector::Bool(v)) => Arc::new(BooleanArray::from_iter(rows.iter().map(|&r| present(r).then_some(v[r])))),
+                (_, Vector::Str(v)) => Arc::new(StringArray::from_iter(rows.iter().map(|&r| present(r).then_some(v[r].as_str())))),
+            };
+            array
+        })
+        .collect();
+    RecordBatch::try_new(schema.clone(), arrays)
+}
+
+/// A schema for a table named `table_name` holding the columns of an Arrow file.
+///
+/// Integer types become int, floating point float, Boolean bool, the string types string, and
+/// timestamps and dates timestamp; a field of any other type is an error.
+pub fn table_schema(table_name: &str, schema: &Schema) -> Result<TableSchema, String> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let data_type = match field.data_type() {
+                DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => "int",
+                DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => "int",
+                DataType::Float32 | DataType::Float64 => "float",
+                DataType::Boolean => "bool",
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "string",
+                DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => "timestamp",
+                other => return Err(format!("field '{}' has Arrow type {}, which no column type holds", field.name(), other)),
+            };
+            Ok(Column { name: field.name().clone(), data_type: data_type.to_string(), nullable: field.is_nullable() })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(TableSchema { table_name: table_name.to_string(), columns, format_version: FORMAT_VERSION })
+}
+
+/// The values of every column of `batch`, `None` for nulls. Fails on integers that do not
+/// fit an int and nulls in a field declared non-nullable.
+pub fn batch_values(table: &TableSchema, batch: &RecordBatch) -> Result<Vec<Vec<Option<Value>>>, String> {
+    table
+        .columns
+        .iter()
+        .zip(batch.columns())
+        .map(|(column, array)| {
+            if !column.nullable && array.null_count() > 0 {
+                return Err(format!("column '{}' is not nullable but holds nulls", column.name));
+            }
+            array_values(array.as_ref()).map_err(|e| format!("column '{}': {}", column.name, e))
+        })
+        .collect()
+}
+
+fn array_values(array: &dyn Array) -> Result<Vec<Option<Value>>, String> {
+    let int = |v: i64| i32::try_from(v).map(Value::Int).map_err(|_| format!("{} does not fit an int", v));
+    let micros = |v: i64, per_unit: i64| {
+        v.checked_mul(per_unit).map(Value::Timestamp).ok_or_else(|| format!("{} is out of range for a timestamp", v))
+    };
+
+    match array.data_type() {
+        DataType::Int8 => primitive::<Int8Type>(array, |v| int(v as i64)),
+        DataType::Int16 => primitive::<Int16Type>(array, |v| int(v as i64)),
+        DataType::Int32 => primitive::<Int32Type>(array, |v| Ok(Value::Int(v))),
+        DataType::Int64 => primitive::<Int64Type>(array, int),
+        DataType::UInt8 => primitive::<UInt8Type>(array, |v| int(v as i64)),
+        DataType::UInt16 => primitive::<UInt16Type>(array, |v| int(v as i64)),
+        DataType::UInt32 => primitive::<UInt32Type>(array, |v| int(v as i64)),
+        DataType::UInt64 => primitive::<UInt64Type>(array, |v| i64::try_from(v).map_err(|_| format!("{} does not fit an int", v)).and_then(int)),
+        DataType::Float32 => primitive::<Float32Type>(array, |v| Ok(Value::Float(v as f64))),
+        DataType::Float64 => primitive::<Float64Type>(array, |v| Ok(Value::Float(v))),
+        DataType::Timestamp(TimeUnit::Second, _) => primitive::<TimestampSecondType>(array, |v| micros(v, 1_000_000)),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => primitive::<TimestampMillisecondType>(array, |v| micros(v, 1_000)),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => primitive::<TimestampMicrosecondType>(array, |v| Ok(Value::Timestamp(v))),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            primitive::<TimestampNanosecondType>(array, |v| Ok(Value::Timestamp(v.div_euclid(1_000))))
+        }
+        DataType::Date32 => primitive::<Date32Type>(array, |v| micros(v as i64, 86_400_000_000)),
+        DataType::Date64 => primitive::<Date64Type>(array, |v| micros(v, 1_000)),
+        DataType::Boolean => {
+            let array = array.as_boolean();
+            Ok((0..array.len()).map(|row| array.is_valid(row).then(|| Value::Bool(array.value(row)))).collect())
+        }
+        DataType::Utf8 => {
+            let array = array.as_string::<i32>();
+            Ok((0..array.len()).map(|row| array.is_valid(row).then(|| Value::Str(array.value(row).to_string()))).collect())
+        }
+        DataType::LargeUtf8 => {
+            let array = array.as_string::<i64>();
+            Ok((0..array.len()).map(|row| array.is_valid(row).then(|| Value::Str(array.value(row).to_string()))).collect())
+        }
+        DataType::Utf8View => {
+            let array = array.as_string_view();
+            Ok((0..array.len()).map(|row| array.is_valid(row).then(|| Value::Str(array.value(row).to_string()))).collect())
+        }
+        other => Err(format!("Arrow type {} is not supported", other)),
+    }
+}
+
+fn primitive<T: ArrowPrimitiveType>(array: &dyn Array, convert: impl Fn(T::Native) -> Result<Value, String>) -> Result<Vec<Option<Value>>, String> {
+    let array = array.as_primitive::<T>();
+    (0..array.len())
+        .map(|row| if array.is_valid(row) { convert(array.value(row)).map(Some) } else { Ok(None) })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Date32Array, Int64Array};
+
+    use super::*;
+    use crate::storage::{column::ColumnStore, export::ExportFormat};
+
+    #[test]
+    fn export_then_import_keeps_values_types_and_nulls() {
+        let dir = std::env::temp_dir().join(format!("cdbe-ipc-roundtrip-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let table = TableSchema::new("t".to_string(), ["id:int", "score:float?", "ok:bool", "at:timestamp?", "note:string?"].map(String::from).to_vec());
+        store
+            .insert_rows(&table, &[vec!["1", "2.5", "true", "2024-01-02T03:04:05.5Z", "a"], vec!["2", "", "false", "", ""]])
+            .unwrap();
+
+        let path = dir.join("t.arrow");
+        assert_eq!(store.export(&table, path.to_str().unwrap(), ExportFormat::Arrow, None).unwrap(), 2);
+        let (imported, count) = store.import_arrow("copy", path.to_str().unwrap()).unwrap();
+        assert_eq!(count, 2);
+        let columns: Vec<(&str, &str, bool)> = imported.columns.iter().map(|c| (c.name.as_str(), c.data_type.as_str(), c.nullable)).collect();
+        let expected: Vec<(&str, &str, bool)> = table.columns.iter().map(|c| (c.name.as_str(), c.data_type.as_str(), c.nullable)).collect();
+        assert_eq!(columns, expected);
+
+        let mut chunks = store.open_chunks(&imported, &["score", "at", "note"]).unwrap();
+        assert_eq!(chunks.display("at", 0).unwrap(), "2024-01-02T03:04:05.5");
+        assert_eq!([chunks.display("score", 1).unwrap(), chunks.display("at", 1).unwrap(), chunks.display("note", 1).unwrap()], ["null", "null", "null"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn values_convert_from_wider_arrow_types() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, true), Field::new("d", DataType::Date32, false)]));
+        let table = table_schema("t", &schema).unwrap();
+        assert_eq!(table.columns.iter().map(|c| c.data_type.as_str()).collect::<Vec<_>>(), ["int", "timestamp"]);
+
+        let arrays: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![Some(7), None])), Arc::new(Date32Array::from(vec![1, 2]))];
+        let batch = RecordBatch::try_new(schema.clone(), arrays).unwrap();
+        let values = batch_values(&table, &batch).unwrap();
+        assert_eq!(values[0], [Some(Value::Int(7)), None]);
+        assert_eq!(values[1][0], Some(Value::Timestamp(86_400_000_000)));
+
+        let arrays: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1 << 40, 0])), Arc::new(Date32Array::from(vec![1, 2]))];
+        let batch = RecordBatch::try_new(schema, arrays).unwrap();
+        assert_eq!(batch_values(&table, &batch).unwrap_err(), "column 'n': 1099511627776 does not fit an int");
+    }
+
+    #[test]
+    fn unsupported_fields_and_unexpected_nulls_fail() {
+        let schema = Schema::new(vec![Field::new("b", DataType::Binary, false)]);
+        assert_eq!(table_schema("t", &schema).unwrap_err(), "field 'b' has Arrow type Binary, which no column type holds");
+
+        let table = TableSchema::new("t".to_string(), vec!["n:int".to_string()]);
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![Some(1), None]))]).unwrap();
+        assert_eq!(batch_values(&table, &batch).unwrap_err(), "column 'n' is not nullable but holds nulls");
+    }
+}
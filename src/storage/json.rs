@@ -0,0 +1,229 @@
+use std::io::BufRead;
+
+use serde_json::{Map, Value as JsonValue};
+
+use super::{
+    column::Column,
+    format::FORMAT_VERSION,
+    table::TableSchema,
+    timestamp,
+    writer::{parse_value, Value},
+};
+
+/// One object of a newline-delimited JSON file.
+pub type Record = Map<String, JsonValue>;
+
+/// Reads newline-delimited JSON, one object per line. A malformed line is reported on its
+/// own and the next call carries on with the line after it.
+pub struct JsonReader<R> {
+    reader: R,
+    line: usize,
+}
+
+impl<R: BufRead> JsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+
+    /// The next object and its line number, `None` at the end of the input.
+    /// Blank lines are skipped.
+    pub fn next_record(&mut self) -> Result<Option<(usize, Record)>, String> {
+        let mut text = String::new();
+        loop {
+            text.clear();
+            let read = self.reader.read_line(&mut text).map_err(|e| format!("line {}: {}", self.line + 1, e))?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if !text.trim().is_empty() {
+                break;
+            }
+        }
+
+        match serde_json::from_str(&text) {
+            Ok(JsonValue::Object(record)) => Ok(Some((self.line, record))),
+            Ok(_) => Err(format!("line {}: expected a JSON object", self.line)),
+            Err(e) => Err(format!("line {}: invalid JSON at column {}", self.line, e.column())),
+        }
+    }
+}
+
+/// The type a field is given from the values seen for it so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Inferred {
+    /// Only nulls so far.
+    Unknown,
+    Int,
+    Float,
+    Bool,
+    Timestamp,
+    String,
+}
+
+impl Inferred {
+    fn of(value: &JsonValue) -> Inferred {
+        match value {
+            JsonValue::Null => Inferred::Unknown,
+            JsonValue::Bool(_) => Inferred::Bool,
+            JsonValue::Number(n) if n.as_i64().is_some_and(|v| i32::try_from(v).is_ok()) => Inferred::Int,
+            // Integers too wide for an int column keep their exact digits as text.
+            JsonValue::Number(n) if n.is_i64() || n.is_u64() => Inferred::String,
+            JsonValue::Number(_) => Inferred::Float,
+            JsonValue::String(s) if timestamp::parse(s).is_ok() => Inferred::Timestamp,
+            _ => Inferred::String,
+        }
+    }
+
+    /// The narrowest type holding values of both; anything but int and float mixes to string.
+    fn unify(self, other: Inferred) -> Inferred {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Inferred::Unknown, t) | (t, Inferred::Unknown) => t,
+            (Inferred::Int, Inferred::Float) | (Inferred::Float, Inferred::Int) => Inferred::Float,
+            _ => Inferred::String,
+        }
+    }
+
+    fn data_type(self) -> &'static str {
+        match self {
+            Inferred::Int => "int",
+            Inferred::Float => "float",
+            Inferred::Bool => "bool",
+            Inferred::Timestamp => "timestamp",
+            Inferred::Unknown | Inferred::String => "string",
+        }
+    }
+}
+
+/// Infers a schema for `table_name` from the first `sample` records: one column per field in
+/// the order fields first appear, typed to hold every sampled value. A field that is null or
+/// missing in any sampled record makes a nullable column.
+pub fn infer_schema<R: BufRead>(table_name: &str, reader: &mut JsonReader<R>, sample: usize) -> Result<TableSchema, String> {
+    // Name, type, whether a null was seen and how many records had the field.
+    let mut fields: Vec<(String, Inferred, bool, usize)> = Vec::new();
+    let mut records = 0;
+
+    while records < sample {
+        let Some((_, record)) = reader.next_record()? else {
+            break;
+        };
+        records += 1;
+        for (name, value) in record {
+            let position = match fields.iter().position(|f| f.0 == name) {
+                Some(position) => position,
+                None => {
+                    fields.push((name, Inferred::Unknown, false, 0));
+                    fields.len() - 1
+                }
+            };
+            let field = &mut fields[position];
+            field.1 = field.1.unify(Inferred::of(&value));
+            field.2 |= value.is_null();
+            field.3 += 1;
+        }
+    }
+
+    if fields.is_empty() {
+        return Err("no fields to infer a schema from".to_string());
+    }
+    let columns = fields
+        .into_iter()
+        .map(|(name, inferred, has_null, seen)| Column {
+            name,
+            data_type: inferred.data_type().to_string(),
+            nullable: has_null || seen < records || inferred == Inferred::Unknown,
+        })
+        .collect();
+    Ok(TableSchema { table_name: table_name.to_string(), columns, format_version: FORMAT_VERSION })
+}
+
+/// The values of `record` in the column order of `table`, `None` for nulls. Every field must
+/// name a column and every column without a field must be nullable.
+pub fn record_values(table: &TableSchema, record: &Record) -> Result<Vec<Option<Value>>, String> {
+    if let Some(unknown) = record.keys().find(|name| table.columns.iter().all(|c| c.name != **name)) {
+        return Err(format!("field '{}' is not in table '{}'", unknown, table.table_name));
+    }
+
+    table
+        .columns
+        .iter()
+        .map(|column| match record.get(&column.name) {
+            None | Some(JsonValue::Null) if column.nullable => Ok(None),
+            None => Err(format!("missing field '{}', the column is not nullable", column.name)),
+            Some(JsonValue::Null) => Err(format!("field '{}' is null, the column is not nullable", column.name)),
+            Some(value) => convert(&column.data_type, value).map(Some).map_err(|e| format!("field '{}': {}", column.name, e)),
+        })
+        .collect()
+}
+
+/// Converts a JSON value to `data_type`. Strings go through the same parsing as CSV fields,
+/// and a string column takes any value as its JSON text.
+fn convert(data_type: &str, value: &JsonValue) -> Result<Value, String> {
+    match (data_type, value) {
+        ("int", JsonValue::Number(n)) => n
+            .as_i64()
+            .and_then(|v| i32::try_from(v).ok())
+            .map(Value::Int)
+            .ok_or_else(|| format!("{} is not a valid int", n)),
+        ("float", JsonValue::Number(n)) => Ok(Value::Float(n.as_f64().unwrap())),
+        ("bool", JsonValue::Bool(b)) => Ok(Value::Bool(*b)),
+        (_, JsonValue::String(s)) => parse_value(data_type, s),
+        ("string", other) => Ok(Value::Str(other.to_string())),
+        (_, other) => Err(format!("{} is not a valid {}", other, data_type)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(text: &str) -> JsonReader<&[u8]> {
+        JsonReader::new(text.as_bytes())
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_one_at_a_time() {
+        let mut reader = reader("{\"a\":1}\n\n[1]\n{\"a\":\n{\"a\":2}\n");
+        assert_eq!(reader.next_record().unwrap().unwrap().0, 1);
+        assert_eq!(reader.next_record().unwrap_err(), "line 3: expected a JSON object");
+        assert!(reader.next_record().unwrap_err().starts_with("line 4: invalid JSON"));
+        let (line, record) = reader.next_record().unwrap().unwrap();
+        assert_eq!((line, &record["a"]), (5, &JsonValue::from(2)));
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn inference_widens_types_and_marks_nullable_fields() {
+        let text = "{\"id\":1,\"score\":2,\"at\":\"2024-01-02T03:04:05Z\",\"ok\":true,\"big\":5000000000}\n\
+                    {\"id\":2,\"score\":2.5,\"at\":\"2024-01-03\",\"ok\":null,\"big\":1,\"note\":\"x\"}\n";
+        let schema = infer_schema("t", &mut reader(text), 10).unwrap();
+        let columns: Vec<(&str, &str, bool)> =
+            schema.columns.iter().map(|c| (c.name.as_str(), c.data_type.as_str(), c.nullable)).collect();
+        assert_eq!(
+            columns,
+            [
+                ("id", "int", false),
+                ("score", "float", false),
+                ("at", "timestamp", false),
+                ("ok", "bool", true),
+                ("big", "string", false),
+                ("note", "string", true),
+            ]
+        );
+        assert!(infer_schema("t", &mut reader(""), 10).is_err());
+    }
+
+    #[test]
+    fn record_values_follow_the_schema() {
+        let table = TableSchema::new("t".to_string(), ["id:int", "note:string?"].map(String::from).to_vec());
+        let record = |text: &str| serde_json::from_str::<Record>(text).unwrap();
+
+        assert_eq!(record_values(&table, &record("{\"note\":\"x\",\"id\":1}")).unwrap(), [Some(Value::Int(1)), Some(Value::Str("x".to_string()))]);
+        assert_eq!(record_values(&table, &record("{\"id\":\"2\"}")).unwrap(), [Some(Value::Int(2)), None]);
+        assert_eq!(record_values(&table, &record("{\"id\":1,\"note\":[1]}")).unwrap()[1], Some(Value::Str("[1]".to_string())));
+        assert_eq!(record_values(&table, &record("{\"note\":null}")).unwrap_err(), "missing field 'id', the column is not nullable");
+        assert_eq!(record_values(&table, &record("{\"id\":1,\"x\":1}")).unwrap_err(), "field 'x' is not in table 't'");
+        assert_eq!(record_values(&table, &record("{\"id\":1.5}")).unwrap_err(), "field 'id': 1.5 is not a valid int");
+    }
+}
@@ -1,2 +1,18 @@
+pub mod chunk;
 pub mod column;
-pub mod table;
\ No newline at end of file
+pub mod config;
+pub mod csv;
+pub mod export;
+pub mod format;
+pub mod ipc;
+pub mod json;
+pub mod parquet;
+pub mod stats;
+pub mod table;
+pub mod timestamp;
+pub mod upgrade;
+pub mod vector;
+pub mod verify;
+pub mod wal;
+pub mod writer;
+pub mod zonemap;
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, io::Write, sync::Arc};
+
+use ::parquet::{
+    basic::{ConvertedType, LogicalType, Repetition, Type as PhysicalType},
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    errors::ParquetError,
+    file::{
+        properties::{EnabledStatistics, WriterProperties},
+        writer::SerializedFileWriter,
+    },
+    format::{MicroSeconds, TimeUnit},
+    schema::types::{ColumnPath, Type},
+};
+
+use super::{
+    column::Column,
+    table::TableSchema,
+    timestamp,
+    vector::{Batch, Vector},
+    writer::Value,
+};
+
+/// Min and max of one chunk of a column, as read from its `.idx` file.
+pub type ChunkBounds = Option<(Value, Value)>;
+
+/// Writes a table as Parquet with one row group per chunk.
+///
+/// int is INT32, float DOUBLE, bool BOOLEAN, string a UTF8 BYTE_ARRAY and timestamp an INT64
+/// UTC timestamp in microseconds; nullable columns are OPTIONAL. String columns are dictionary
+/// encoded (the writer falls back to plain once a dictionary page fills up), the others plain.
+pub struct ParquetWriter<'a, W: Write + Send> {
+    table: &'a TableSchema,
+    writer: SerializedFileWriter<W>,
+    /// Per column, the `.idx` bounds of every chunk, used as the row group statistics.
+    bounds: HashMap<String, Vec<ChunkBounds>>,
+}
+
+impl<'a, W: Write + Send> ParquetWriter<'a, W> {
+    pub fn new(out: W, table: &'a TableSchema, bounds: HashMap<String, Vec<ChunkBounds>>) -> Result<Self, ParquetError> {
+        let fields = table.columns.iter().map(|column| parquet_type(column).map(Arc::new)).collect::<Result<Vec<_>, _>>()?;
+        let schema = Type::group_type_builder(&table.table_name).with_fields(fields).build()?;
+
+        let mut properties = WriterProperties::builder()
+            .set_dictionary_enabled(false)
+            .set_statistics_enabled(EnabledStatistics::Chunk)
+            .set_created_by(format!("cdbe version {}", env!("CARGO_PKG_VERSION")));
+        for column in table.columns.iter().filter(|c| c.data_type == "string") {
+            properties = properties.set_column_dictionary_enabled(ColumnPath::from(column.name.as_str()), true);
+        }
+
+        let writer = SerializedFileWriter::new(out, Arc::new(schema), Arc::new(properties.build()))?;
+        Ok(Self { table, writer, bounds })
+    }
+
+    /// Writes `rows` of chunk `chunk` as one row group. `batch` holds every column of the
+    /// table with its nulls.
+    pub fn write_chunk(&mut self, chunk: usize, batch: &Batch, rows: &[usize]) -> Result<(), ParquetError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut row_group = self.writer.next_row_group()?;
+        for column in &self.table.columns {
+            let is_null = |row: usize| batch.is_null(&column.name, row);
+            let present: Vec<usize> = rows.iter().copied().filter(|&row| !is_null(row)).collect();
+            let levels: Option<Vec<i16>> = column.nullable.then(|| rows.iter().map(|&row| if is_null(row) { 0 } else { 1 }).collect());
+            let levels = levels.as_deref();
+            let bounds = self.bounds.get(&column.name).and_then(|b| b.get(chunk)).cloned().flatten();
+
+            let mut column_writer = row_group.next_column()?.unwrap();
+            match (column_writer.untyped(), &batch.columns[&column.name], bounds) {
+                (ColumnWriter::Int32ColumnWriter(writer), Vector::Int(v), bounds) => {
+                    let (min, max) = match bounds {
+                        Some((Value::Int(min), Value::Int(max))) => (Some(min), Some(max)),
+                        _ => (None, None),
+                    };
+                    let values: Vec<i32> = present.iter().map(|&row| v[row]).collect();
+                    writer.write_batch_with_statistics(&values, levels, None, min.as_ref(), max.as_ref(), None)?;
+                }
+                (ColumnWriter::DoubleColumnWriter(writer), Vector::Float(v), bounds) => {
+                    let (min, max) = match bounds {
+                        Some((Value::Float(min), Value::Float(max))) => (Some(min), Some(max)),
+                        _ => (None, None),
+                    };
+                    let values: Vec<f64> = present.iter().map(|&row| v[row]).collect();
+                    writer.write_batch_with_statistics(&values, levels, None, min.as_ref(), max.as_ref(), None)?;
+                }
+                (ColumnWriter::BoolColumnWriter(writer), Vector::Bool(v), bounds) => {
+                    let (min, max) = match bounds {
+                        Some((Value::Bool(min), Value::Bool(max))) => (Some(min), Some(max)),
+                        _ => (None, None),
+                    };
+                    let values: Vec<bool> = present.iter().map(|&row| v[row]).collect();
+                    writer.write_batch_with_statistics(&values, levels, None, min.as_ref(), max.as_ref(), None)?;
+                }
+                (ColumnWriter::Int64ColumnWriter(writer), Vector::Str(v), bounds) => {
+                    let (min, max) = match bounds {
+                        Some((Value::Timestamp(min), Value::Timestamp(max))) => (Some(min), Some(max)),
+                        _ => (None, None),
+                    };
+                    let values: Vec<i64> = present.iter().map(|&row| timestamp::parse(&v[row]).unwrap()).collect();
+                    writer.write_batch_with_statistics(&values, levels, None, min.as_ref(), max.as_ref(), None)?;
+                }
+                (ColumnWriter::ByteArrayColumnWriter(writer), Vector::Str(v), bounds) => {
+                    let (min, max) = match bounds {
+                        Some((Value::Str(min), Value::Str(max))) => (Some(ByteArray::from(min.as_str())), Some(ByteArray::from(max.as_str()))),
+                        _ => (None, None),
+                    };
+                    let values: Vec<ByteArray> = present.iter().map(|&row| ByteArray::from(v[row].as_str())).collect();
+                    writer.write_batch_with_statistics(&values, levels, None, min.as_ref(), max.as_ref(), None)?;
+                }
+                (_, vector, _) => {
+                    return Err(ParquetError::General(format!("column '{}' read {} values", column.name, vector.data_type())));
+                }
+            }
+            column_writer.close()?;
+        }
+        row_group.close()?;
+        Ok(())
+    }
+
+    /// Writes the footer and hands back the output.
+    pub fn into_inner(self) -> Result<W, ParquetError> {
+        self.writer.into_inner()
+    }
+}
+
+fn parquet_type(column: &Column) -> Result<Type, ParquetError> {
+    let repetition = if column.nullable { Repetition::OPTIONAL } else { Repetition::REQUIRED };
+    let builder = match column.data_type.as_str() {
+        "int" => Type::primitive_type_builder(&column.name, PhysicalType::INT32),
+        "float" => Type::primitive_type_builder(&column.name, PhysicalType::DOUBLE),
+        "bool" => Type::primitive_type_builder(&column.name, PhysicalType::BOOLEAN),
+        "timestamp" => Type::primitive_type_builder(&column.name, PhysicalType::INT64).with_logical_type(Some(LogicalType::Timestamp {
+            is_adjusted_to_u_t_c: true,
+            unit: TimeUnit::MICROS(MicroSeconds {}),
+        })),
+        _ => Type::primitive_type_builder(&column.name, PhysicalType::BYTE_ARRAY)
+            .with_logical_type(Some(LogicalType::String))
+            .with_converted_type(ConvertedType::UTF8),
+    };
+    builder.with_repetition(repetition).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::parquet::file::{
+        reader::{FileReader, SerializedFileReader},
+        statistics::Statistics,
+    };
+
+    use super::*;
+    use crate::storage::{column::ColumnStore, export::ExportFormat};
+
+    #[test]
+    fn chunks_become_row_groups_with_index_statistics() {
+        let dir = std::env::temp_dir().join(format!("cdbe-parquet-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let table = TableSchema::new("t".to_string(), ["id:int", "note:string?"].map(String::from).to_vec());
+        let ids: Vec<String> = (0..1500).map(|i| i.to_string()).collect();
+        let rows: Vec<Vec<&str>> = ids.iter().enumerate().map(|(i, id)| vec![id.as_str(), if i % 2 == 0 { "" } else { "n" }]).collect();
+        store.insert_rows(&table, &rows).unwrap();
+
+        let path = dir.join("t.parquet");
+        assert_eq!(store.export(&table, path.to_str().unwrap(), ExportFormat::Parquet, None).unwrap(), 1500);
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 2);
+
+        let groups: Vec<_> = metadata.row_groups().iter().map(|g| g.num_rows()).collect();
+        assert_eq!(groups, [1024, 476]);
+        match metadata.row_group(1).column(0).statistics() {
+            Some(Statistics::Int32(stats)) => assert_eq!((stats.min_opt(), stats.max_opt()), (Some(&1024), Some(&1499))),
+            other => panic!("unexpected statistics {:?}", other),
+        }
+        let nulls = metadata.row_group(0).column(1).statistics().and_then(|s| s.null_count_opt());
+        assert_eq!(nulls, Some(512));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filtered_out_chunks_write_no_row_group() {
+        let dir = std::env::temp_dir().join(format!("cdbe-parquet-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ColumnStore::new(dir.to_str().unwrap());
+        let table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        let ids: Vec<String> = (0..3000).map(|i| i.to_string()).collect();
+        let rows: Vec<Vec<&str>> = ids.iter().map(|id| vec![id.as_str()]).collect();
+        store.insert_rows(&table, &rows).unwrap();
+
+        let predicate = crate::query::predicate::Predicate::parse("id >= 2500", &table).unwrap();
+        let path = dir.join("t.parquet");
+        assert_eq!(store.export(&table, path.to_str().unwrap(), ExportFormat::Parquet, Some(&predicate)).unwrap(), 500);
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 1);
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 500);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
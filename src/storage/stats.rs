@@ -0,0 +1,429 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::write_atomic;
+use crate::utils::simd::SimdOp;
+
+/// Registers of the distinct count sketch are addressed by this many hash bits.
+const HLL_PRECISION: u32 = 12;
+
+/// Upper bound on the buckets of an int column's histogram.
+pub const HISTOGRAM_BUCKETS: usize = 32;
+
+/// Statistics of every column of a table, as written by `analyze` to `<table>.stats`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TableStats {
+    pub table_name: String,
+    pub row_count: u64,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColumnStats {
+    pub name: String,
+    pub data_type: String,
+    pub null_count: u64,
+    /// `None` when the column has no rows.
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    /// HyperLogLog estimate of the number of distinct values.
+    pub distinct_count: u64,
+    /// The sketch behind `distinct_count`, hex encoded, so appends can keep it current.
+    #[serde(default)]
+    pub sketch: String,
+    /// Equi-depth histogram, int columns only.
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// Rows above the previous bucket's `upper` (or from the column minimum) up to and including `upper`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistogramBucket {
+    pub upper: i32,
+    pub count: u64,
+    pub distinct: u64,
+}
+
+impl TableStats {
+    /// Writes `<table>.stats` under a temporary name and renames it into place, so a reader
+    /// never sees half of it.
+    pub fn save(&self, base_path: &str) -> Result<(), String> {
+        let path = format!("{}/{}.stats", base_path, self.table_name);
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(&path, json.as_bytes(), false).map_err(|e| format!("Failed to write '{}': {}", path, e))
+    }
+
+    /// The statistics last written by `analyze`, `None` if the table was never analyzed
+    /// or the file cannot be read.
+    pub fn load(base_path: &str, table_name: &str) -> Option<Self> {
+        let path = format!("{}/{}.stats", base_path, table_name);
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+impl ColumnStats {
+    pub fn from_ints(name: &str, mut values: Vec<i32>) -> Self {
+        let mut sketch = HyperLogLog::new();
+        values.iter().for_each(|&v| sketch.insert_int(v));
+        values.sort_unstable();
+
+        Self {
+            name: name.to_string(),
+            data_type: "int".to_string(),
+            null_count: 0,
+            min_value: values.first().map(i32::to_string),
+            max_value: values.last().map(i32::to_string),
+            distinct_count: sketch.estimate(),
+            sketch: sketch.to_hex(),
+            histogram: equi_depth(&values),
+        }
+    }
+
+    pub fn from_strings(name: &str, values: &[String]) -> Self {
+        let mut sketch = HyperLogLog::new();
+        values.iter().for_each(|v| sketch.insert_str(v));
+
+        Self {
+            name: name.to_string(),
+            data_type: "string".to_string(),
+            null_count: 0,
+            min_value: values.iter().min().cloned(),
+            max_value: values.iter().max().cloned(),
+            distinct_count: sketch.estimate(),
+            sketch: sketch.to_hex(),
+            histogram: Vec::new(),
+        }
+    }
+
+    pub fn from_floats(name: &str, values: &[f64]) -> Self {
+        let mut sketch = HyperLogLog::new();
+        values.iter().for_each(|v| sketch.insert_str(&v.to_string()));
+
+        Self {
+            name: name.to_string(),
+            data_type: "float".to_string(),
+            null_count: 0,
+            min_value: values.iter().copied().min_by(f64::total_cmp).map(|v| v.to_string()),
+            max_value: values.iter().copied().max_by(f64::total_cmp).map(|v| v.to_string()),
+            distinct_count: sketch.estimate(),
+            sketch: sketch.to_hex(),
+            histogram: Vec::new(),
+        }
+    }
+
+    /// Folds one appended value into min, max and the histogram; the distinct count is kept
+    /// by the caller's sketch. New extremes widen the first or last bucket, so the histogram
+    /// stays exact in row counts but drifts from equal depth until the next `analyze`.
+    ///
+    /// A value landing inside a bucket only adds to its count: whether it is new to the bucket
+    /// is not known, so the bucket's distinct count stays as `analyze` left it. Equality
+    /// estimates, a bucket's rows over its distinct values, go stale as appends bring new values
+    /// and overestimate until the next `analyze`.
+    pub fn observe(&mut self, value: &str) {
+        if self.data_type == "float" {
+            let Ok(value) = value.parse::<f64>() else {
+                return;
+            };
+            let bound = |text: &Option<String>| text.as_deref().and_then(|t| t.parse::<f64>().ok());
+            if bound(&self.min_value).is_none_or(|min| value < min) {
+                self.min_value = Some(value.to_string());
+            }
+            if bound(&self.max_value).is_none_or(|max| value > max) {
+                self.max_value = Some(value.to_string());
+            }
+        } else if self.data_type == "int" {
+            let Ok(value) = value.parse::<i32>() else {
+                return;
+            };
+            let (min, max) = self.int_bounds().map_or((value, value), |(min, max)| (min.min(value), max.max(value)));
+            self.min_value = Some(min.to_string());
+            self.max_value = Some(max.to_string());
+
+            match self.histogram.iter_mut().find(|b| value <= b.upper) {
+                Some(bucket) => bucket.count += 1,
+                None => match self.histogram.last_mut() {
+                    Some(last) => {
+                        last.upper = value;
+                        last.count += 1;
+                        last.distinct += 1;
+                    }
+                    None => self.histogram.push(HistogramBucket { upper: value, count: 1, distinct: 1 }),
+                },
+            }
+        } else {
+            if self.min_value.as_deref().is_none_or(|min| value < min) {
+                self.min_value = Some(value.to_string());
+            }
+            if self.max_value.as_deref().is_none_or(|max| value > max) {
+                self.max_value = Some(value.to_string());
+            }
+        }
+    }
+
+    fn int_bounds(&self) -> Option<(i32, i32)> {
+        let min = self.min_value.as_ref()?.parse().ok()?;
+        let max = self.max_value.as_ref()?.parse().ok()?;
+        Some((min, max))
+    }
+
+    fn rows(&self) -> u64 {
+        self.histogram.iter().map(|b| b.count).sum()
+    }
+
+    /// Fraction of rows with a value `<= value`, interpolating linearly inside a bucket.
+    fn fraction_le(&self, value: i64) -> f64 {
+        let (Some((min, _)), rows) = (self.int_bounds(), self.rows()) else {
+            return 0.0;
+        };
+        if rows == 0 {
+            return 0.0;
+        }
+
+        let mut lower = min as i64 - 1;
+        let mut below = 0.0;
+        for bucket in &self.histogram {
+            let upper = bucket.upper as i64;
+            if value >= upper {
+                below += bucket.count as f64;
+            } else {
+                if value > lower {
+                    below += bucket.count as f64 * (value - lower) as f64 / (upper - lower) as f64;
+                }
+                break;
+            }
+            lower = upper;
+        }
+        below / rows as f64
+    }
+
+    /// Fraction of rows equal to `value`: the average share of one distinct value of its bucket.
+    fn fraction_eq(&self, value: i32) -> f64 {
+        let (Some((min, max)), rows) = (self.int_bounds(), self.rows()) else {
+            return 0.0;
+        };
+        if rows == 0 || value < min || value > max {
+            return 0.0;
+        }
+
+        let bucket = self.histogram.iter().find(|b| value <= b.upper).unwrap();
+        bucket.count as f64 / bucket.distinct.max(1) as f64 / rows as f64
+    }
+
+    /// Estimated fraction of rows where `column op value` holds, `None` without a histogram.
+    pub fn compare_selectivity(&self, op: SimdOp, value: i32) -> Option<f64> {
+        if self.histogram.is_empty() {
+            return None;
+        }
+        let value = value as i64;
+        Some(match op {
+            SimdOp::Eq => self.fraction_eq(value as i32),
+            SimdOp::Ne => 1.0 - self.fraction_eq(value as i32),
+            SimdOp::Lt => self.fraction_le(value - 1),
+            SimdOp::Le => self.fraction_le(value),
+            SimdOp::Gt => 1.0 - self.fraction_le(value),
+            SimdOp::Ge => 1.0 - self.fraction_le(value - 1),
+        })
+    }
+
+    pub fn between_selectivity(&self, low: i32, high: i32) -> Option<f64> {
+        if self.histogram.is_empty() {
+            return None;
+        }
+        Some((self.fraction_le(high as i64) - self.fraction_le(low as i64 - 1)).max(0.0))
+    }
+
+    pub fn in_selectivity(&self, values: &[i32]) -> Option<f64> {
+        if self.histogram.is_empty() {
+            return None;
+        }
+        let mut distinct = values.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        Some(distinct.iter().map(|&v| self.fraction_eq(v)).sum::<f64>().min(1.0))
+    }
+}
+
+/// Splits sorted `values` into up to `HISTOGRAM_BUCKETS` buckets of about equal row count,
+/// never splitting a run of equal values across two buckets.
+fn equi_depth(values: &[i32]) -> Vec<HistogramBucket> {
+    let depth = values.len().div_ceil(HISTOGRAM_BUCKETS).max(1);
+    let mut buckets = Vec::new();
+    let mut start = 0;
+
+    while start < values.len() {
+        let mut end = (start + depth).min(values.len());
+        while end < values.len() && values[end] == values[end - 1] {
+            end += 1;
+        }
+
+        let rows = &values[start..end];
+        let distinct = 1 + rows.windows(2).filter(|w| w[0] != w[1]).count();
+        buckets.push(HistogramBucket { upper: rows[rows.len() - 1], count: rows.len() as u64, distinct: distinct as u64 });
+        start = end;
+    }
+
+    buckets
+}
+
+/// Approximate distinct counting in a fixed 4 KiB of registers.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self { registers: vec![0; 1 << HLL_PRECISION] }
+    }
+
+    /// Decodes a sketch written by [`HyperLogLog::to_hex`], `None` if it is missing or malformed.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 2 << HLL_PRECISION {
+            return None;
+        }
+        let registers = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Self { registers })
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.registers.iter().map(|r| format!("{:02x}", r)).collect()
+    }
+
+    pub fn insert_int(&mut self, value: i32) {
+        self.insert(mix(value as u32 as u64));
+    }
+
+    pub fn insert_str(&mut self, value: &str) {
+        self.insert(hash_bytes(value.as_bytes()));
+    }
+
+    fn insert(&mut self, hash: u64) {
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 { m * (m / zeros as f64).ln() } else { raw };
+        estimate.round() as u64
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The splitmix64 finalizer, spreading every input bit over the whole hash.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// FNV-1a over the bytes, finished with `mix` so the top bits are usable as a register index.
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    mix(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn int_stats_have_bounds_distinct_count_and_histogram() {
+        let values: Vec<i32> = (0..10_000).map(|i| i % 1000).collect();
+        let stats = ColumnStats::from_ints("a", values);
+        assert_eq!(stats.min_value.as_deref(), Some("0"));
+        assert_eq!(stats.max_value.as_deref(), Some("999"));
+        assert!((900..=1100).contains(&stats.distinct_count), "estimate {}", stats.distinct_count);
+
+        assert!(stats.histogram.len() <= HISTOGRAM_BUCKETS);
+        assert_eq!(stats.histogram.iter().map(|b| b.count).sum::<u64>(), 10_000);
+        assert_eq!(stats.histogram.last().unwrap().upper, 999);
+    }
+
+    #[test]
+    fn runs_of_equal_values_stay_in_one_bucket() {
+        let mut values = vec![7; 500];
+        values.extend(0..10);
+        let stats = ColumnStats::from_ints("a", values);
+        let bucket = stats.histogram.iter().find(|b| b.upper >= 7).unwrap();
+        assert!(bucket.count >= 500);
+    }
+
+    #[test]
+    fn selectivity_follows_the_histogram() {
+        let stats = ColumnStats::from_ints("a", (0..1000).collect());
+        let lt = stats.compare_selectivity(SimdOp::Lt, 250).unwrap();
+        assert!((lt - 0.25).abs() < 0.02, "lt {}", lt);
+        let eq = stats.compare_selectivity(SimdOp::Eq, 10).unwrap();
+        assert!((eq - 0.001).abs() < 0.0005, "eq {}", eq);
+        assert_eq!(stats.compare_selectivity(SimdOp::Eq, 5000), Some(0.0));
+
+        let between = stats.between_selectivity(100, 199).unwrap();
+        assert!((between - 0.1).abs() < 0.02, "between {}", between);
+        let list = stats.in_selectivity(&[1, 2, 2, 3]).unwrap();
+        assert!((list - 0.003).abs() < 0.001, "in {}", list);
+    }
+
+    #[test]
+    fn string_stats_have_no_histogram() {
+        let values: Vec<String> = ["b", "a", "c", "a"].iter().map(|s| s.to_string()).collect();
+        let stats = ColumnStats::from_strings("s", &values);
+        assert_eq!(stats.min_value.as_deref(), Some("a"));
+        assert_eq!(stats.max_value.as_deref(), Some("c"));
+        assert_eq!(stats.distinct_count, 3);
+        assert_eq!(stats.compare_selectivity(SimdOp::Eq, 1), None);
+    }
+
+    #[test]
+    fn observe_widens_bounds_but_keeps_bucket_distinct_counts() {
+        let mut stats = ColumnStats::from_ints("a", (0..100).map(|i| i * 2).collect());
+        let before = stats.histogram.clone();
+
+        // 1 is new to the first bucket, yet only its row count grows.
+        stats.observe("1");
+        assert_eq!(stats.histogram[0].count, before[0].count + 1);
+        assert_eq!(stats.histogram[0].distinct, before[0].distinct);
+
+        stats.observe("500");
+        assert_eq!(stats.max_value.as_deref(), Some("500"));
+        let last = stats.histogram.last().unwrap();
+        assert_eq!((last.upper, last.count), (500, before.last().unwrap().count + 1));
+
+        stats.observe("-1");
+        assert_eq!(stats.min_value.as_deref(), Some("-1"));
+    }
+
+    #[test]
+    fn save_replaces_the_stats_file_whole() {
+        let dir = std::env::temp_dir().join(format!("cdbe-stats-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base_path = dir.to_str().unwrap();
+
+        let mut stats = TableStats { table_name: "t".to_string(), row_count: 3, columns: vec![ColumnStats::from_ints("a", vec![1, 2, 3])] };
+        stats.save(base_path).unwrap();
+        stats.row_count = 4;
+        stats.save(base_path).unwrap();
+
+        assert_eq!(TableStats::load(base_path, "t").unwrap().row_count, 4);
+        assert!(!dir.join("t.stats.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
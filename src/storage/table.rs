@@ -1,13 +1,16 @@
-use std::{collections::HashMap, fs::{self}};
+use std::{collections::HashMap, fs};
 
 use serde::{Deserialize, Serialize};
 
-use super::column::Column;
+use super::{column::Column, config::{write_atomic, DatabaseConfig, Durability}, format::FORMAT_VERSION};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TableSchema {
     pub table_name: String,
     pub columns: Vec<Column>,
+    /// The file layout of the table; 0 for a schema saved before versions were recorded.
+    #[serde(default)]
+    pub format_version: u16,
 }
 
 impl TableSchema {
@@ -17,11 +20,13 @@ impl TableSchema {
             .map(|col| {
                 let parts: Vec<&str> = col.split(':').collect();
                 if parts.len() != 2 {
-                    panic!("Invalid column format. Use 'name:type'. Example: 'age:int'");
+                    panic!("Invalid column format. Use 'name:type' or 'name:type?' for a nullable column. Example: 'age:int'");
                 }
+                let data_type = parts[1].strip_suffix('?');
                 Column {
                     name: parts[0].to_string(),
-                    data_type: parts[1].to_string(),
+                    data_type: data_type.unwrap_or(parts[1]).to_string(),
+                    nullable: data_type.is_some(),
                 }
             })
             .collect();
@@ -29,21 +34,38 @@ impl TableSchema {
         Self {
             table_name,
             columns: parsed_columns,
+            format_version: FORMAT_VERSION,
         }
     }
 
-    pub fn save(&self, base_path: &str) {
+    /// The columns as listed by `list-tables`, e.g. `id (int), note (string, nullable)`.
+    pub fn describe_columns(&self) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|col| if col.nullable { format!("{} ({}, nullable)", col.name, col.data_type) } else { format!("{} ({})", col.name, col.data_type) })
+            .collect();
+        columns.join(", ")
+    }
+
+    /// Writes the schema to `<table>.meta` by renaming a complete new file over it, so a crash
+    /// leaves the old schema or the new one, never part of one. The file and the directory are
+    /// fsynced unless the database's durability is `none`.
+    pub fn save(&self, base_path: &str) -> Result<(), String> {
         let path = format!("{}/{}.meta", base_path, self.table_name);
-        let json = serde_json::to_string_pretty(self).unwrap();
-        fs::write(path, json).unwrap();
+        let sync = DatabaseConfig::load(base_path).map_or(true, |config| config.durability != Durability::None);
+        write_atomic(&path, serde_json::to_string_pretty(self).unwrap().as_bytes(), sync)
+            .map_err(|e| format!("Failed to write '{}': {}", path, e))
     }
 
-    pub fn load(base_path: &str, table_name: &str) -> Self  {
+    pub fn load(base_path: &str, table_name: &str) -> Result<Self, String> {
         let path = format!("{}/{}.meta", base_path, table_name);
-        let json = fs::read_to_string(path).unwrap();
-        serde_json::from_str(&json).unwrap()
+        let json = fs::read_to_string(&path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+        serde_json::from_str(&json).map_err(|e| format!("'{}' is not a valid schema: {}", path, e))
     }
 
+    /// Loads every schema in `base_path`. A `.meta` file that cannot be read is reported and its
+    /// table skipped, so one damaged table does not stop the others from being used.
     pub fn load_metadata(base_path: &str) -> HashMap<String, TableSchema> {
         let mut tables = HashMap::new();
 
@@ -52,8 +74,12 @@ impl TableSchema {
             let path = entry.unwrap().path();
             if path.extension().and_then(|ext| ext.to_str()) == Some("meta") {
                 if let Some(file_stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    let table_schema = TableSchema::load(base_path, file_stem);
-                    tables.insert(file_stem.to_string(), table_schema);
+                    match TableSchema::load(base_path, file_stem) {
+                        Ok(table_schema) => {
+                            tables.insert(file_stem.to_string(), table_schema);
+                        }
+                        Err(e) => println!("Skipping table '{}': {}", file_stem, e),
+                    }
                 }
             }
         }
@@ -63,11 +89,37 @@ impl TableSchema {
         } else {
             println!("Tables present in the database:");
             for (table_name, schema) in &tables {
-                let columns: Vec<String> = schema.columns.iter().map(|col| format!("{} ({})", col.name, col.data_type)).collect();
-                println!("- {} [{}]", table_name, columns.join(", "));
+                println!("- {} [{}]", table_name, schema.describe_columns());
             }
         }
 
         tables
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_are_replaced_whole_and_damaged_ones_skipped() {
+        let dir = std::env::temp_dir().join(format!("cdbe-table-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let base_path = dir.to_str().unwrap();
+
+        let mut table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        table.save(base_path).unwrap();
+        table.columns.push(Column { name: "note".to_string(), data_type: "string".to_string(), nullable: true });
+        table.save(base_path).unwrap();
+        assert!(!dir.join("t.meta.tmp").exists());
+        assert_eq!(TableSchema::load(base_path, "t").unwrap().describe_columns(), "id (int), note (string, nullable)");
+
+        // A schema cut short, as a plain write could leave it, keeps only its own table from loading.
+        fs::write(dir.join("u.meta"), "{\"table_name\": \"u\", \"colu").unwrap();
+        assert!(TableSchema::load(base_path, "u").unwrap_err().contains("is not a valid schema"));
+        let tables = TableSchema::load_metadata(base_path);
+        assert_eq!(tables.keys().collect::<Vec<_>>(), ["t"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/// Parses `YYYY-MM-DD`, optionally followed by `T` or a space and `HH:MM[:SS[.fraction]]`,
+/// then `Z` or a `+HH:MM` offset, into microseconds since the Unix epoch. Without an offset
+/// the time is taken as UTC.
+pub fn parse(text: &str) -> Result<i64, String> {
+    parse_micros(text.trim()).ok_or_else(|| format!("'{}' is not a valid timestamp", text))
+}
+
+/// The text form of `micros` in UTC, `YYYY-MM-DDTHH:MM:SS` plus a fraction without trailing
+/// zeros when there is one. Text in this layout sorts in the same order as the instants it
+/// names, so string comparisons, `min`/`max` and `order by` all work on timestamps as read.
+pub fn format(micros: i64) -> String {
+    let (year, month, day) = civil_from_days(micros.div_euclid(MICROS_PER_DAY));
+    let of_day = micros.rem_euclid(MICROS_PER_DAY);
+    let seconds = of_day / 1_000_000;
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    if of_day % 1_000_000 != 0 {
+        text.push_str(format!(".{:06}", of_day % 1_000_000).trim_end_matches('0'));
+    }
+    text
+}
+
+fn parse_micros(text: &str) -> Option<i64> {
+    let mut cursor = Cursor { bytes: text.as_bytes(), pos: 0 };
+
+    let year = cursor.number(4)?;
+    cursor.expect(b'-')?;
+    let month = cursor.number(2)?;
+    cursor.expect(b'-')?;
+    let day = cursor.number(2)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut micros = days_from_civil(year, month, day) * MICROS_PER_DAY;
+    if cursor.eat(b'T') || cursor.eat(b't') || cursor.eat(b' ') {
+        let hour = cursor.number(2)?;
+        cursor.expect(b':')?;
+        let minute = cursor.number(2)?;
+        let second = if cursor.eat(b':') { cursor.number(2)? } else { 0 };
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        micros += ((hour * 60 + minute) * 60 + second) * 1_000_000;
+
+        if cursor.eat(b'.') {
+            let digits = cursor.digits();
+            if digits.is_empty() {
+                return None;
+            }
+            // Anything past microseconds is dropped.
+            let padded = format!("{:0<6}", &digits[..digits.len().min(6)]);
+            micros += padded.parse::<i64>().ok()?;
+        }
+
+        if !(cursor.eat(b'Z') || cursor.eat(b'z')) {
+            let sign = if cursor.eat(b'+') { -1 } else if cursor.eat(b'-') { 1 } else { 0 };
+            if sign != 0 {
+                let hours = cursor.number(2)?;
+                cursor.eat(b':');
+                let minutes = cursor.number(2)?;
+                micros += sign * (hours * 60 + minutes) * 60_000_000;
+            }
+        }
+    }
+
+    let range = days_from_civil(0, 1, 1) * MICROS_PER_DAY..days_from_civil(10000, 1, 1) * MICROS_PER_DAY;
+    (cursor.pos == cursor.bytes.len() && range.contains(&micros)).then_some(micros)
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.bytes.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    /// Exactly `len` ASCII digits.
+    fn number(&mut self, len: usize) -> Option<i64> {
+        let digits = self.bytes.get(self.pos..self.pos + len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.pos += len;
+        Some(digits.iter().fold(0, |n, d| n * 10 + (d - b'0') as i64))
+    }
+
+    /// Every ASCII digit from here on.
+    fn digits(&mut self) -> &str {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos]).unwrap()
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_dates_times_fractions_and_offsets() {
+        assert_eq!(parse("1970-01-01").unwrap(), 0);
+        assert_eq!(parse("1970-01-02 00:00:01.5").unwrap(), MICROS_PER_DAY + 1_500_000);
+        assert_eq!(parse("1970-01-01T01:00:00+01:00").unwrap(), 0);
+        assert_eq!(parse("1969-12-31T23:00Z").unwrap(), -3_600_000_000);
+        for invalid in ["2023-02-29", "2024-13-01", "2024-01-01T24:00", "2024-1-01", "2024-01-01T00:00:00.", "10000-01-01"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn format_round_trips_and_sorts_as_text() {
+        for text in ["2024-02-29T12:34:56.789", "1969-07-20T20:17:40", "0001-01-01T00:00:00.000001"] {
+            assert_eq!(format(parse(text).unwrap()), text);
+        }
+        let (earlier, later) = (format(parse("1999-12-31T23:59:59").unwrap()), format(parse("2000-01-01").unwrap()));
+        assert!(earlier < later);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fs, path::Path};
+
+use super::{
+    config::write_atomic,
+    format::{data_header, FORMAT_VERSION},
+    table::TableSchema,
+    verify::{rebuild_index, verify_table, ProblemKind},
+    wal::lock_for_rewrite,
+};
+
+/// What [`upgrade_table`] did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upgrade {
+    /// Every file already had the current layout.
+    UpToDate,
+    /// `files` were rewritten and the table verified with its `row_count` rows afterwards.
+    Upgraded { files: usize, row_count: usize },
+}
+
+/// Rewrites the files of a `table` whose schema has an older format version in the current
+/// layout: `.data` files get a header, `.idx` files are rebuilt with a header and one entry per
+/// chunk, and `.meta` records the format version.
+///
+/// The schema's version says which layout every file has, so the files are rewritten first and
+/// the schema saved last. The original files are copied to `backup/<table>/` beforehand and
+/// removed once the table verifies with the same number of rows; otherwise they are put back.
+/// An upgrade cut short leaves its backup, which keeps the table from being read until the next
+/// upgrade puts the originals back and starts over.
+///
+/// The table lock is held throughout, and a table with an unfinished write is not upgraded
+/// until the write has been rolled back.
+pub fn upgrade_table(base_path: &str, table: &TableSchema) -> Result<Upgrade, String> {
+    let _lock = lock_for_rewrite(base_path, &table.table_name)?;
+    let backup_dir = backup_dir(base_path, &table.table_name);
+    let table = if Path::new(&backup_dir).exists() {
+        restore(base_path, &backup_dir)?;
+        TableSchema::load(base_path, &table.table_name)?
+    } else {
+        table.clone()
+    };
+    if table.format_version > FORMAT_VERSION {
+        return Err(format!("its schema has format version {}, newer than this build's {}", table.format_version, FORMAT_VERSION));
+    }
+    if table.format_version == FORMAT_VERSION {
+        return Ok(Upgrade::UpToDate);
+    }
+    let legacy = legacy_files(base_path, &table);
+
+    let before = verify_table(base_path, &table);
+    if let Some(problem) = before.problems.iter().find(|p| p.kind == ProblemKind::Data) {
+        return Err(format!("{}; the data must be repaired before upgrading", problem));
+    }
+
+    fs::create_dir_all(&backup_dir).map_err(|e| format!("Failed to create '{}': {}", backup_dir, e))?;
+    for name in &legacy {
+        // Copied under a temporary name, so the backup only ever holds whole files.
+        let backup = format!("{}/{}", backup_dir, name);
+        let temp_path = format!("{}.tmp", backup);
+        fs::copy(format!("{}/{}", base_path, name), &temp_path)
+            .and_then(|_| fs::rename(&temp_path, &backup))
+            .map_err(|e| format!("Failed to back up '{}': {}", name, e))?;
+    }
+
+    let mut upgraded = table.clone();
+    upgraded.format_version = FORMAT_VERSION;
+    let rewritten = rewrite(base_path, &upgraded, &legacy).and_then(|_| {
+        let after = verify_table(base_path, &upgraded);
+        match after.problems.first() {
+            Some(problem) => Err(format!("the upgraded table does not verify: {}", problem)),
+            None if after.row_count != before.row_count => {
+                Err(format!("the upgraded table has {} rows, it had {}", after.row_count, before.row_count))
+            }
+            None => Ok(after.row_count),
+        }
+    });
+
+    match rewritten {
+        Ok(row_count) => {
+            fs::remove_dir_all(&backup_dir).map_err(|e| format!("Failed to remove '{}': {}", backup_dir, e))?;
+            // Only removed once no other table has a backup in it.
+            let _ = fs::remove_dir(format!("{}/backup", base_path));
+            Ok(Upgrade::Upgraded { files: legacy.len(), row_count })
+        }
+        Err(e) => {
+            restore(base_path, &backup_dir).map_err(|restore| format!("{}; {}", e, restore))?;
+            Err(format!("{}; the original files were restored", e))
+        }
+    }
+}
+
+/// Where the original files of `table_name` are kept while it is upgraded.
+pub fn backup_dir(base_path: &str, table_name: &str) -> String {
+    format!("{}/backup/{}", base_path, table_name)
+}
+
+/// The files of a `table` of an older format version that an upgrade rewrites: every `.data`
+/// and `.idx` file it has, and its `.meta`.
+fn legacy_files(base_path: &str, table: &TableSchema) -> Vec<String> {
+    let mut legacy = Vec::new();
+    for column in &table.columns {
+        for extension in ["data", "idx"] {
+            let name = format!("{}_{}.{}", table.table_name, column.name, extension);
+            if Path::new(&format!("{}/{}", base_path, name)).exists() {
+                legacy.push(name);
+            }
+        }
+    }
+    legacy.push(format!("{}.meta", table.table_name));
+    legacy
+}
+
+/// Rewrites the `legacy` files in the layout of the `upgraded` schema, data files first and the
+/// schema last.
+fn rewrite(base_path: &str, upgraded: &TableSchema, legacy: &[String]) -> Result<(), String> {
+    for column in &upgraded.columns {
+        let data_name = format!("{}_{}.data", upgraded.table_name, column.name);
+        if legacy.contains(&data_name) {
+            let path = format!("{}/{}", base_path, data_name);
+            let mut bytes = data_header(&column.data_type).to_vec();
+            fs::read(&path)
+                .and_then(|values| {
+                    bytes.extend(values);
+                    write_atomic(&path, &bytes, true)
+                })
+                .map_err(|e| format!("Failed to rewrite '{}': {}", path, e))?;
+        }
+    }
+    for column in &upgraded.columns {
+        if legacy.contains(&format!("{}_{}.idx", upgraded.table_name, column.name)) {
+            rebuild_index(base_path, upgraded, column)?;
+        }
+    }
+    upgraded.save(base_path)
+}
+
+/// Moves every file in `backup_dir` back into `base_path`, then removes the backup. Files are
+/// moved one at a time, so a restore cut short is finished by the next one.
+fn restore(base_path: &str, backup_dir: &str) -> Result<(), String> {
+    let entries = fs::read_dir(backup_dir).map_err(|e| format!("Failed to read '{}': {}", backup_dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".tmp") {
+            continue;
+        }
+        fs::rename(entry.path(), format!("{}/{}", base_path, name))
+            .map_err(|e| format!("restoring '{}' from '{}' failed: {}", name, backup_dir, e))?;
+    }
+    fs::remove_dir_all(backup_dir).map_err(|e| format!("Failed to remove '{}': {}", backup_dir, e))?;
+    let _ = fs::remove_dir(format!("{}/backup", base_path));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{column::ColumnStore, format::{DATA_HEADER_LEN, MAGIC}, wal};
+
+    /// A store holding table `t` written before headers existed, with `rows` rows.
+    fn legacy_table(name: &str, rows: usize) -> (ColumnStore, String, TableSchema) {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let base_path = dir.to_str().unwrap().to_string();
+        let store = ColumnStore::new(&base_path);
+        let mut table = TableSchema::new("t".to_string(), ["id:int", "name:string?"].map(String::from).to_vec());
+        table.format_version = 0;
+        table.save(&base_path).unwrap();
+        let values: Vec<[String; 2]> = (0..rows).map(|i| [i.to_string(), if i % 2 == 0 { format!("n{}", i) } else { String::new() }]).collect();
+        let rows: Vec<Vec<&str>> = values.iter().map(|row| row.iter().map(String::as_str).collect()).collect();
+        store.insert_rows(&table, &rows).unwrap();
+        (store, base_path, table)
+    }
+
+    #[test]
+    fn legacy_tables_are_rewritten_with_headers() {
+        let (store, base_path, table) = legacy_table("upgrade-legacy", 1500);
+        assert!(!fs::read(format!("{}/t_id.data", base_path)).unwrap().starts_with(MAGIC));
+
+        // Two data files, two indexes and the schema; the null flags keep their layout.
+        assert_eq!(upgrade_table(&base_path, &table).unwrap(), Upgrade::Upgraded { files: 5, row_count: 1500 });
+        let upgraded = TableSchema::load(&base_path, "t").unwrap();
+        assert_eq!(upgraded.format_version, FORMAT_VERSION);
+        assert!(fs::read(format!("{}/t_name.data", base_path)).unwrap().starts_with(MAGIC));
+        assert_eq!(store.read_index(&upgraded, "id").unwrap().len(), 2);
+        store.check_format(&upgraded).unwrap();
+        assert!(store.verify(&upgraded).problems.is_empty());
+        assert_eq!(store.open_chunks(&upgraded, &["name"]).unwrap().display("name", 1498).unwrap(), "n1498");
+        assert!(!Path::new(&format!("{}/backup", base_path)).exists());
+
+        assert_eq!(upgrade_table(&base_path, &upgraded).unwrap(), Upgrade::UpToDate);
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn an_upgrade_cut_short_is_restored_and_started_over() {
+        let (store, base_path, table) = legacy_table("upgrade-restore", 3);
+        let original = fs::read(format!("{}/t_id.data", base_path)).unwrap();
+
+        // The data file was rewritten, the schema not yet.
+        let backup = backup_dir(&base_path, "t");
+        fs::create_dir_all(&backup).unwrap();
+        fs::copy(format!("{}/t_id.data", base_path), format!("{}/t_id.data", backup)).unwrap();
+        let mut rewritten = data_header("int").to_vec();
+        rewritten.extend(&original);
+        fs::write(format!("{}/t_id.data", base_path), rewritten).unwrap();
+        assert_eq!(store.check_format(&table).unwrap_err(), "an upgrade of it was cut short, run `upgrade` to finish it");
+
+        assert_eq!(upgrade_table(&base_path, &table).unwrap(), Upgrade::Upgraded { files: 5, row_count: 3 });
+        assert_eq!(fs::read(format!("{}/t_id.data", base_path)).unwrap()[DATA_HEADER_LEN as usize..], original);
+        store.check_format(&TableSchema::load(&base_path, "t").unwrap()).unwrap();
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_and_unfinished_writes_are_refused() {
+        let (_, base_path, mut table) = legacy_table("upgrade-refused", 1);
+
+        // A log left by a writer that died must be rolled back by recovery first.
+        drop(wal::WriteAheadLog::begin(&base_path, "t", Vec::new(), false).unwrap());
+        assert!(upgrade_table(&base_path, &table).unwrap_err().starts_with("Table 't' has an unfinished write"));
+        wal::recover(&base_path).unwrap();
+
+        table.format_version = FORMAT_VERSION + 1;
+        assert_eq!(
+            upgrade_table(&base_path, &table).unwrap_err(),
+            format!("its schema has format version {}, newer than this build's {}", FORMAT_VERSION + 1, FORMAT_VERSION)
+        );
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+}
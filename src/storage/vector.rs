@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use crate::utils::bitmap::Bitmap;
+
+/// A batch worth of values of one column or computed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vector {
+    Int(Vec<i32>),
+    Float(Vec<f64>),
+    Str(Vec<String>),
+    Bool(Vec<bool>),
+}
+
+impl Vector {
+    pub fn len(&self) -> usize {
+        match self {
+            Vector::Int(v) => v.len(),
+            Vector::Float(v) => v.len(),
+            Vector::Str(v) => v.len(),
+            Vector::Bool(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The schema type name of the values, as used in `Column::data_type`.
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            Vector::Int(_) => "int",
+            Vector::Float(_) => "float",
+            Vector::Str(_) => "string",
+            Vector::Bool(_) => "bool",
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            Vector::Int(v) => v.truncate(len),
+            Vector::Float(v) => v.truncate(len),
+            Vector::Str(v) => v.truncate(len),
+            Vector::Bool(v) => v.truncate(len),
+        }
+    }
+
+    pub fn slice(&self, start: usize, end: usize) -> Vector {
+        match self {
+            Vector::Int(v) => Vector::Int(v[start..end].to_vec()),
+            Vector::Float(v) => Vector::Float(v[start..end].to_vec()),
+            Vector::Str(v) => Vector::Str(v[start..end].to_vec()),
+            Vector::Bool(v) => Vector::Bool(v[start..end].to_vec()),
+        }
+    }
+
+    pub fn empty(data_type: &str) -> Vector {
+        match data_type {
+            "int" => Vector::Int(Vec::new()),
+            "float" => Vector::Float(Vec::new()),
+            "bool" => Vector::Bool(Vec::new()),
+            _ => Vector::Str(Vec::new()),
+        }
+    }
+
+    /// Gathers the given rows, in the given order.
+    pub fn take(&self, rows: &[usize]) -> Vector {
+        match self {
+            Vector::Int(v) => Vector::Int(rows.iter().map(|&r| v[r]).collect()),
+            Vector::Float(v) => Vector::Float(rows.iter().map(|&r| v[r]).collect()),
+            Vector::Str(v) => Vector::Str(rows.iter().map(|&r| v[r].clone()).collect()),
+            Vector::Bool(v) => Vector::Bool(rows.iter().map(|&r| v[r]).collect()),
+        }
+    }
+
+    /// A vector of `len` rows with value `i` at row `rows[i]`; the other rows, which are null,
+    /// hold the type's zero value.
+    pub fn spread(&self, rows: &[usize], len: usize) -> Vector {
+        fn place<T: Clone + Default>(values: &[T], rows: &[usize], len: usize) -> Vec<T> {
+            let mut out = vec![T::default(); len];
+            for (value, &row) in values.iter().zip(rows) {
+                out[row] = value.clone();
+            }
+            out
+        }
+        match self {
+            Vector::Int(v) => Vector::Int(place(v, rows, len)),
+            Vector::Float(v) => Vector::Float(place(v, rows, len)),
+            Vector::Str(v) => Vector::Str(place(v, rows, len)),
+            Vector::Bool(v) => Vector::Bool(place(v, rows, len)),
+        }
+    }
+
+    /// Appends the values of `other`, which must hold the same type.
+    pub fn append(&mut self, other: Vector) {
+        match (self, other) {
+            (Vector::Int(a), Vector::Int(b)) => a.extend(b),
+            (Vector::Float(a), Vector::Float(b)) => a.extend(b),
+            (Vector::Str(a), Vector::Str(b)) => a.extend(b),
+            (Vector::Bool(a), Vector::Bool(b)) => a.extend(b),
+            (a, b) => panic!("Cannot append {} values to a {} vector", b.data_type(), a.data_type()),
+        }
+    }
+
+    /// Orders two rows of the same vector; floats use a total order so sorting never panics.
+    pub fn compare_rows(&self, a: usize, b: usize) -> std::cmp::Ordering {
+        match self {
+            Vector::Int(v) => v[a].cmp(&v[b]),
+            Vector::Float(v) => v[a].total_cmp(&v[b]),
+            Vector::Str(v) => v[a].cmp(&v[b]),
+            Vector::Bool(v) => v[a].cmp(&v[b]),
+        }
+    }
+
+    pub fn display(&self, row: usize) -> String {
+        match self {
+            Vector::Int(v) => v[row].to_string(),
+            Vector::Float(v) => v[row].to_string(),
+            Vector::Str(v) => v[row].clone(),
+            Vector::Bool(v) => v[row].to_string(),
+        }
+    }
+}
+
+/// The same rows of several columns, keyed by column name.
+///
+/// A null row of a column holds its type's zero value in `columns` and is set in that column's
+/// bitmap in `nulls`; columns without nulls in the batch have no bitmap.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub columns: HashMap<String, Vector>,
+    pub nulls: HashMap<String, Bitmap>,
+    pub len: usize,
+}
+
+impl Batch {
+    /// Gathers the given rows of every column, in the given order.
+    pub fn take(&self, rows: &[usize]) -> Batch {
+        let columns = self.columns.iter().map(|(name, vector)| (name.clone(), vector.take(rows))).collect();
+        let nulls = self.nulls.iter().map(|(name, nulls)| (name.clone(), nulls.take(rows))).collect();
+        Batch { columns, nulls, len: rows.len() }
+    }
+
+    /// Whether `row` of `column` is null.
+    pub fn is_null(&self, column: &str, row: usize) -> bool {
+        self.nulls.get(column).is_some_and(|nulls| nulls.get(row))
+    }
+
+    /// The rows where any of `columns` is null, `None` when there are none.
+    pub fn null_rows(&self, columns: &[&str]) -> Option<Bitmap> {
+        columns
+            .iter()
+            .filter_map(|column| self.nulls.get(*column))
+            .fold(None, |union: Option<Bitmap>, nulls| Some(union.map_or_else(|| nulls.clone(), |union| union.or(nulls))))
+            .filter(Bitmap::any)
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader, ErrorKind, Read},
+};
+
+use super::{
+    chunk::{fixed_width, CHUNK_ROWS},
+    column::{Column, MinMaxIndex},
+    config::{write_atomic, DatabaseConfig, Durability},
+    format::{index_header, read_data_header, read_index_header},
+    table::TableSchema,
+    writer::{parse_value, Bounds, Value},
+};
+use crate::utils::crc32c::crc32c_append;
+
+/// Something wrong with a table's files, as found by [`verify_table`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// The column whose files are at fault, `None` when the columns disagree with each other.
+    pub column: Option<String>,
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// A `.data` or `.nulls` file is damaged, or the columns have different row counts.
+    Data,
+    /// An `.idx` file does not describe its `.data` file.
+    Index,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "column '{}': {}", column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The outcome of checking one table.
+#[derive(Debug)]
+pub struct Report {
+    pub table_name: String,
+    /// Rows of the first column that could be read.
+    pub row_count: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// The columns with index problems, which [`rebuild_index`] can repair. Columns whose data
+    /// is damaged are left out, as a rebuilt index would checksum the damaged values.
+    pub fn damaged_indexes(&self) -> Vec<&str> {
+        let damaged_data = |column: &str| self.problems.iter().any(|p| p.kind == ProblemKind::Data && p.column.as_deref() == Some(column));
+        let mut columns: Vec<&str> = self
+            .problems
+            .iter()
+            .filter(|p| p.kind == ProblemKind::Index)
+            .filter_map(|p| p.column.as_deref())
+            .filter(|column| !damaged_data(column))
+            .collect();
+        columns.dedup();
+        columns
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.problems.is_empty() {
+            return write!(f, "Table '{}': ok, {} rows", self.table_name, self.row_count);
+        }
+        write!(f, "Table '{}': {} problems", self.table_name, self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the values of a `.data` file one at a time, reporting a damaged file instead of
+/// misreading it: a trailing partial value, a string whose length runs past the end of the file
+/// or is not UTF-8, or a bool byte other than 0 or 1.
+pub struct ValueReader {
+    reader: BufReader<File>,
+    data_type: String,
+    offset: u64,
+    len: u64,
+}
+
+impl ValueReader {
+    /// `None` when the column has no `.data` file yet. Offsets count from the first value, past
+    /// the file's header if the table's `format_version` has one.
+    pub fn open(path: &str, data_type: &str, format_version: u16) -> Result<Option<Self>, String> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to open '{}': {}", path, e)),
+        };
+        let start = read_data_header(&mut file, path, data_type, format_version)?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len() - start;
+        Ok(Some(Self { reader: BufReader::new(file), data_type: data_type.to_string(), offset: 0, len }))
+    }
+
+    /// The next value and the offset it starts at, `None` at the end of the file.
+    pub fn next_value(&mut self) -> Result<Option<(u64, Value)>, String> {
+        let start = self.offset;
+        let remaining = self.len - start;
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        let width = fixed_width(&self.data_type).unwrap_or(4) as u64;
+        if remaining < width {
+            return Err(format!("{} trailing bytes at offset {} do not make a whole value", remaining, start));
+        }
+        let mut bytes = vec![0u8; width as usize];
+        self.read(&mut bytes)?;
+
+        let value = match self.data_type.as_str() {
+            "int" => Value::Int(i32::from_le_bytes(bytes.try_into().unwrap())),
+            "float" => Value::Float(f64::from_le_bytes(bytes.try_into().unwrap())),
+            "timestamp" => Value::Timestamp(i64::from_le_bytes(bytes.try_into().unwrap())),
+            "bool" => match bytes[0] {
+                0 | 1 => Value::Bool(bytes[0] == 1),
+                other => return Err(format!("bool at offset {} is byte {}", start, other)),
+            },
+            _ => {
+                let length = u32::from_le_bytes(bytes.try_into().unwrap()) as u64;
+                if length > remaining - 4 {
+                    return Err(format!(
+                        "string at offset {} is {} bytes long, past the end of the file at {} bytes",
+                        start, length, self.len
+                    ));
+                }
+                let mut text = vec![0u8; length as usize];
+                self.read(&mut text)?;
+                Value::Str(String::from_utf8(text).map_err(|_| format!("string at offset {} is not valid UTF-8", start))?)
+            }
+        };
+        Ok(Some((start, value)))
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        self.reader.read_exact(buffer).map_err(|e| e.to_string())?;
+        self.offset += buffer.len() as u64;
+        Ok(())
+    }
+}
+
+/// Checks the files of every column of `table`: each `.data` file reads back as whole values,
+/// every `.idx` entry starts at a value and holds the min, max and checksum of the values up to
+/// the next one, `.nulls` files have a flag per row, and all columns have the same number of rows.
+pub fn verify_table(base_path: &str, table: &TableSchema) -> Report {
+    let mut problems = Vec::new();
+    let mut row_counts = Vec::new();
+    for column in &table.columns {
+        let rows = verify_column(base_path, table, column, &mut problems);
+        row_counts.push((column.name.as_str(), rows));
+    }
+
+    if row_counts.iter().any(|&(_, rows)| rows != row_counts[0].1) {
+        let counts: Vec<String> = row_counts.iter().map(|(name, rows)| format!("{} {}", name, rows)).collect();
+        problems.push(Problem {
+            column: None,
+            kind: ProblemKind::Data,
+            message: format!("columns have different row counts: {}", counts.join(", ")),
+        });
+    }
+    Report { table_name: table.table_name.clone(), row_count: row_counts.first().map_or(0, |&(_, rows)| rows), problems }
+}
+
+/// Checks one column, adding what is wrong to `problems`. Returns the rows read before the end
+/// of the data or the first damaged value.
+fn verify_column(base_path: &str, table: &TableSchema, column: &Column, problems: &mut Vec<Problem>) -> usize {
+    let table_name = &table.table_name;
+    let problem = |kind, message: String| Problem { column: Some(column.name.clone()), kind, message };
+    let data_path = format!("{}/{}_{}.data", base_path, table_name, column.name);
+    let index_path = format!("{}/{}_{}.idx", base_path, table_name, column.name);
+    let nulls_path = format!("{}/{}_{}.nulls", base_path, table_name, column.name);
+
+    let mut reader = match ValueReader::open(&data_path, &column.data_type, table.format_version) {
+        Ok(Some(reader)) => reader,
+        Ok(None) => return 0,
+        Err(e) => {
+            problems.push(problem(ProblemKind::Data, e));
+            return 0;
+        }
+    };
+
+    let mut entries: Vec<MinMaxIndex> = Vec::new();
+    match File::open(&index_path) {
+        Ok(file) => {
+            for (line, text) in BufReader::new(file).lines().enumerate() {
+                if line == 0 {
+                    match text.as_deref().map(|t| read_index_header(t, &index_path, &column.data_type, table.format_version)) {
+                        Ok(Ok(true)) => continue,
+                        Ok(Err(e)) => {
+                            problems.push(problem(ProblemKind::Index, e));
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let entry = match text.map_err(|e| e.to_string()).and_then(|t| serde_json::from_str::<MinMaxIndex>(&t).map_err(|e| e.to_string())) {
+                    Ok(entry) => entry,
+                    Err(_) => {
+                        problems.push(problem(ProblemKind::Index, format!("line {} of the index is not a valid entry", line + 1)));
+                        continue;
+                    }
+                };
+                if entries.last().is_some_and(|last| entry.chunk_offset <= last.chunk_offset) {
+                    problems.push(problem(
+                        ProblemKind::Index,
+                        format!("index entry at offset {} does not come after the entry before it", entry.chunk_offset),
+                    ));
+                    continue;
+                }
+                entries.push(entry);
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => problems.push(problem(ProblemKind::Index, format!("Failed to open '{}': {}", index_path, e))),
+    }
+
+    // Walks the values and the entries together: an entry must start exactly at a value, and
+    // covers the values from there up to the next entry. Null rows count towards the checksum
+    // but not the bounds.
+    let null_flags = read_null_flags(column, &nulls_path);
+    let is_null = |row: usize| null_flags.get(row).is_some_and(|&flag| flag != 0);
+    let mut rows = 0;
+    let mut next_entry = 0;
+    let mut current: Option<(&MinMaxIndex, Bounds, u32)> = None;
+    let mut damaged = false;
+    loop {
+        let (offset, value) = match reader.next_value() {
+            Ok(Some(next)) => next,
+            Ok(None) => break,
+            Err(e) => {
+                problems.push(problem(ProblemKind::Data, e));
+                damaged = true;
+                break;
+            }
+        };
+
+        while let Some(entry) = entries.get(next_entry).filter(|entry| entry.chunk_offset < offset) {
+            problems.push(problem(ProblemKind::Index, format!("index entry at offset {} does not start at a value", entry.chunk_offset)));
+            next_entry += 1;
+        }
+        if let Some(entry) = entries.get(next_entry).filter(|entry| entry.chunk_offset == offset) {
+            if let Some(finished) = current.take() {
+                check_entry(column, finished, problems);
+            }
+            if let Some(row) = entry.row.filter(|&row| row != rows as u64) {
+                problems.push(problem(ProblemKind::Index, format!("index entry at offset {} says it starts at row {}, not {}", entry.chunk_offset, row, rows)));
+            }
+            let bounds = (!is_null(rows)).then(|| value.widen(None));
+            current = Some((entry, bounds, crc32c_append(0, &value.to_bytes())));
+            next_entry += 1;
+        } else if let Some((_, bounds, crc)) = &mut current {
+            *crc = crc32c_append(*crc, &value.to_bytes());
+            if !is_null(rows) {
+                *bounds = Some(value.widen(bounds.take()));
+            }
+        } else if rows == 0 {
+            problems.push(problem(ProblemKind::Index, "no index entry covers the first rows".to_string()));
+        }
+        rows += 1;
+    }
+
+    if let Some(finished) = current.take() {
+        check_entry(column, finished, problems);
+    }
+    if !damaged {
+        for entry in &entries[next_entry..] {
+            problems.push(problem(ProblemKind::Index, format!("index entry at offset {} is past the end of the data", entry.chunk_offset)));
+        }
+    }
+
+    if column.nullable {
+        if let Ok(metadata) = fs::metadata(&nulls_path) {
+            if metadata.len() != rows as u64 {
+                problems.push(problem(ProblemKind::Data, format!("the null flags cover {} rows, the data holds {}", metadata.len(), rows)));
+            }
+        }
+    }
+    rows
+}
+
+/// Compares an index entry with the bounds and checksum of the values it covers, `None` bounds
+/// when they are all null. A checksum that does not match is a data problem: rebuilding the
+/// index would hide it.
+fn check_entry(column: &Column, (entry, bounds, crc): (&MinMaxIndex, Bounds, u32), problems: &mut Vec<Problem>) {
+    if entry.checksum.is_some_and(|checksum| checksum != crc) {
+        problems.push(Problem {
+            column: Some(column.name.clone()),
+            kind: ProblemKind::Data,
+            message: format!("the values of the index entry at offset {} fail its checksum", entry.chunk_offset),
+        });
+    }
+    let indexed = match (&entry.min_value, &entry.max_value) {
+        (Some(min), Some(max)) => parse_value(&column.data_type, min).ok().zip(parse_value(&column.data_type, max).ok()).map(Some),
+        (None, None) => Some(None),
+        _ => None,
+    };
+    if indexed.is_some_and(|indexed| same_bounds(&indexed, &bounds)) {
+        return;
+    }
+    let says = match (&entry.min_value, &entry.max_value) {
+        (None, None) => "only nulls".to_string(),
+        (min, max) => format!("min '{}' and max '{}'", min.as_deref().unwrap_or("-"), max.as_deref().unwrap_or("-")),
+    };
+    let holds = match bounds {
+        Some((min, max)) => format!("'{}' to '{}'", min, max),
+        None => "only nulls".to_string(),
+    };
+    problems.push(Problem {
+        column: Some(column.name.clone()),
+        kind: ProblemKind::Index,
+        message: format!("index entry at offset {} says {}, the data holds {}", entry.chunk_offset, says, holds),
+    });
+}
+
+/// Whether two bounds are the same. Floats are compared in their total order with every NaN
+/// equal, as the text form in the index keeps neither the sign nor the payload of a NaN.
+fn same_bounds(a: &Bounds, b: &Bounds) -> bool {
+    let same = |a: &Value, b: &Value| match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.total_cmp(b).is_eq() || (a.is_nan() && b.is_nan()),
+        _ => a == b,
+    };
+    match (a, b) {
+        (Some((a_min, a_max)), Some((b_min, b_max))) => same(a_min, b_min) && same(a_max, b_max),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The null flag bytes of a nullable column, empty for a column without nulls or a `.nulls` file.
+fn read_null_flags(column: &Column, path: &str) -> Vec<u8> {
+    if column.nullable { fs::read(path).unwrap_or_default() } else { Vec::new() }
+}
+
+/// Rewrites the `.idx` file of `column` of `table` from its `.data` file, one entry per
+/// `CHUNK_ROWS` chunk as `TableWriter` writes them, with a header if the table's format version
+/// has one. The new index replaces the old one only once it is complete and, unless the
+/// durability is `none`, synced. The caller holds the table lock from `wal::lock_for_rewrite`.
+/// Fails on a damaged data file. Returns the number of entries written.
+pub fn rebuild_index(base_path: &str, table: &TableSchema, column: &Column) -> Result<usize, String> {
+    let table_name = &table.table_name;
+    let data_path = format!("{}/{}_{}.data", base_path, table_name, column.name);
+    let index_path = format!("{}/{}_{}.idx", base_path, table_name, column.name);
+    let null_flags = read_null_flags(column, &format!("{}/{}_{}.nulls", base_path, table_name, column.name));
+
+    let mut entries = Vec::new();
+    if let Some(mut reader) = ValueReader::open(&data_path, &column.data_type, table.format_version)? {
+        let mut pending: Option<(u64, u64, Bounds, u32)> = None;
+        let mut rows = 0;
+        while let Some((offset, value)) = reader.next_value().map_err(|e| format!("column '{}': {}", column.name, e))? {
+            let (start, row, bounds, crc) = pending.take().unwrap_or((offset, rows as u64, None, 0));
+            let bounds = if null_flags.get(rows).is_some_and(|&flag| flag != 0) { bounds } else { Some(value.widen(bounds)) };
+            pending = Some((start, row, bounds, crc32c_append(crc, &value.to_bytes())));
+            rows += 1;
+            if rows % CHUNK_ROWS == 0 {
+                entries.extend(pending.take());
+            }
+        }
+        entries.extend(pending);
+    }
+
+    let mut text = if table.format_version > 0 { index_header(&column.data_type) } else { String::new() };
+    for (chunk_offset, row, bounds, crc) in &entries {
+        let (min_value, max_value) = bounds.as_ref().map(|(min, max)| (min.to_string(), max.to_string())).unzip();
+        let entry = MinMaxIndex { chunk_offset: *chunk_offset, min_value, max_value, checksum: Some(*crc), row: Some(*row) };
+        text.push_str(&(serde_json::to_string(&entry).unwrap() + "\n"));
+    }
+    let sync = DatabaseConfig::load(base_path).map_or(true, |config| config.durability != Durability::None);
+    write_atomic(&index_path, text.as_bytes(), sync).map_err(|e| format!("Failed to write '{}': {}", index_path, e))?;
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::storage::column::ColumnStore;
+
+    fn store(name: &str) -> (ColumnStore, String) {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let base_path = dir.to_str().unwrap().to_string();
+        (ColumnStore::new(&base_path), base_path)
+    }
+
+    fn messages(report: &Report) -> Vec<String> {
+        report.problems.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn nulls_and_nan_bounds_verify_clean() {
+        let (store, base_path) = store("verify-clean");
+        let table = TableSchema::new("t".to_string(), ["id:int", "x:float?"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1", "NaN"], vec!["2", "1.5"], vec!["3", ""]]).unwrap();
+        store.insert_rows(&table, &[vec!["4", ""]]).unwrap();
+
+        let report = verify_table(&base_path, &table);
+        assert_eq!(messages(&report), Vec::<String>::new());
+        assert_eq!(report.row_count, 4);
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn damaged_data_and_index_files_are_reported() {
+        let (store, base_path) = store("verify-damaged");
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1", "a"], vec!["5", "b"]]).unwrap();
+
+        let id_data = format!("{}/t_id.data", base_path);
+        fs::OpenOptions::new().append(true).open(&id_data).unwrap().write_all(&[0, 0]).unwrap();
+        let index = r#"{"chunk_offset":0,"min_value":"1","max_value":"9","row":0}"#;
+        fs::write(format!("{}/t_id.idx", base_path), format!("{}{}\nnot json\n", index_header("int"), index)).unwrap();
+
+        let report = verify_table(&base_path, &table);
+        assert_eq!(
+            messages(&report),
+            [
+                "column 'id': line 3 of the index is not a valid entry",
+                "column 'id': 2 trailing bytes at offset 8 do not make a whole value",
+                "column 'id': index entry at offset 0 says min '1' and max '9', the data holds '1' to '5'",
+            ]
+        );
+        assert!(report.problems.iter().all(|p| p.column.as_deref() == Some("id")));
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn columns_with_different_row_counts_disagree() {
+        let (store, base_path) = store("verify-rows");
+        let table = TableSchema::new("t".to_string(), ["a:int", "b:bool"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1", "true"], vec!["2", "false"]]).unwrap();
+        let b_data = format!("{}/t_b.data", base_path);
+        fs::OpenOptions::new().append(true).open(&b_data).unwrap().write_all(&[1]).unwrap();
+        // The extra row falls under the last index entry, whose checksum no longer matches.
+        assert_eq!(
+            messages(&verify_table(&base_path, &table)),
+            ["column 'b': the values of the index entry at offset 0 fail its checksum", "columns have different row counts: a 2, b 3"]
+        );
+
+        fs::OpenOptions::new().append(true).open(&b_data).unwrap().write_all(&[2]).unwrap();
+        let report = verify_table(&base_path, &table);
+        assert_eq!(
+            messages(&report),
+            [
+                "column 'b': bool at offset 3 is byte 2",
+                "column 'b': the values of the index entry at offset 0 fail its checksum",
+                "columns have different row counts: a 2, b 3",
+            ]
+        );
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn rebuilt_indexes_match_the_data_again() {
+        let (store, base_path) = store("verify-rebuild");
+        let table = TableSchema::new("t".to_string(), ["id:int", "x:int?"].map(String::from).to_vec());
+        let ids: Vec<String> = (0..1500).map(|i| i.to_string()).collect();
+        let rows: Vec<Vec<&str>> = ids.iter().map(|id| vec![id.as_str(), if id.ends_with('0') { id.as_str() } else { "" }]).collect();
+        store.insert_rows(&table, &rows[..700]).unwrap();
+        store.insert_rows(&table, &rows[700..]).unwrap();
+        // The writer started an entry at each batch as well; a rebuild writes one per chunk.
+        assert_eq!(fs::read_to_string(format!("{}/t_x.idx", base_path)).unwrap().matches("chunk_offset").count(), 3);
+
+        fs::write(format!("{}/t_x.idx", base_path), "").unwrap();
+        let report = verify_table(&base_path, &table);
+        assert_eq!(report.damaged_indexes(), ["x"]);
+
+        assert_eq!(store.reindex(&table, Some("x")).unwrap(), [("x".to_string(), 2)]);
+        assert!(verify_table(&base_path, &table).problems.is_empty());
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn reindex_waits_for_writers_and_unfinished_writes() {
+        let (store, base_path) = store("verify-reindex-lock");
+        let table = TableSchema::new("t".to_string(), vec!["id:int".to_string()]);
+        store.insert_rows(&table, &[vec!["1"]]).unwrap();
+
+        let writer = crate::storage::writer::TableWriter::open(&store, &table).unwrap();
+        assert_eq!(store.reindex(&table, None).unwrap_err(), "Table 't' is being written by another process");
+
+        drop(writer);
+
+        // A log left by a writer that died must be rolled back by recovery first.
+        drop(crate::storage::wal::WriteAheadLog::begin(&base_path, "t", Vec::new(), false).unwrap());
+        assert!(store.reindex(&table, None).unwrap_err().starts_with("Table 't' has an unfinished write"));
+        crate::storage::wal::recover(&base_path).unwrap();
+        assert_eq!(store.reindex(&table, None).unwrap(), [("id".to_string(), 1)]);
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+}
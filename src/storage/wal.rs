@@ -0,0 +1,257 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{ErrorKind, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::config::{sync_dir, write_atomic};
+
+/// The log of a write in progress, kept in `<table>.wal` from before the first byte is appended
+/// until every file has been flushed.
+///
+/// A table's files only ever grow, so the log records the length each file had when the write
+/// began. Cutting the files back to those lengths undoes a write that did not finish, whether it
+/// failed, panicked or the process died, and leaves every column with the same row count. A
+/// write committing row by row moves those lengths forward with [`WriteAheadLog::advance`].
+///
+/// The log holds an exclusive lock on `<table>.lock` for as long as it lives, so that
+/// [`recover`] only rolls back writes whose process is gone.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WriteAheadLog {
+    pub table_name: String,
+    pub files: Vec<LoggedFile>,
+    #[serde(skip)]
+    path: String,
+    /// The locked `<table>.lock`, released when the log is dropped.
+    #[serde(skip)]
+    lock: Option<File>,
+    /// Whether the log and the directory holding it are fsynced as they change.
+    #[serde(skip)]
+    sync: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoggedFile {
+    /// File name relative to the data directory, e.g. `users_age.data`.
+    pub name: String,
+    pub length: u64,
+    /// The last bytes of the file at `length`, written back by a rollback as the write may have
+    /// replaced them since: the provisional index entry of a write committing row by row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<String>,
+}
+
+impl WriteAheadLog {
+    /// Locks the table and writes the log of a write to it about to append to `files`. Fails if
+    /// another writer holds the lock or the table already has a log, as a second writer must
+    /// not cut back the first one's rows.
+    ///
+    /// With `sync`, the log and the directory are fsynced before any data can reach the disk,
+    /// which also makes the files the write just created durable.
+    pub fn begin(base_path: &str, table_name: &str, files: Vec<LoggedFile>, sync: bool) -> Result<Self, String> {
+        let lock = lock_table(base_path, table_name)?
+            .ok_or_else(|| format!("Table '{}' is being written by another process", table_name))?;
+        let path = log_path(base_path, table_name);
+        let log = Self { table_name: table_name.to_string(), files, path, lock: Some(lock), sync };
+
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&log.path).map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!("Table '{}' has an unfinished write ({})", table_name, log.path),
+            _ => format!("Failed to create '{}': {}", log.path, e),
+        })?;
+        file.write_all(serde_json::to_string(&log).unwrap().as_bytes())
+            .and_then(|_| if sync { file.sync_all().and_then(|_| sync_dir(base_path)) } else { Ok(()) })
+            .map_err(|e| format!("Failed to write '{}': {}", log.path, e))?;
+        Ok(log)
+    }
+
+    /// Commits what was written so far and goes on logging: a rollback now returns to the
+    /// current end of every file, which must be flushed and synced. `tails` pairs file names with
+    /// their provisional last bytes.
+    pub fn advance(&mut self, base_path: &str, tails: &[(&str, &str)]) -> Result<(), String> {
+        for logged in &mut self.files {
+            let path = format!("{}/{}", base_path, logged.name);
+            logged.length = fs::metadata(&path).map_err(|e| format!("Failed to read '{}': {}", path, e))?.len();
+            logged.tail = tails.iter().find(|(name, _)| *name == logged.name).map(|(_, tail)| tail.to_string());
+        }
+
+        // Renamed into place, so a crash leaves either the old point or the new one.
+        write_atomic(&self.path, serde_json::to_string(&self).unwrap().as_bytes(), self.sync)
+            .map_err(|e| format!("Failed to write '{}': {}", self.path, e))
+    }
+
+    /// Marks the write finished. Every file must already be flushed, and synced if the log is.
+    pub fn commit(self) -> Result<(), String> {
+        fs::remove_file(&self.path)
+            .and_then(|_| self.sync_dir())
+            .map_err(|e| format!("Failed to remove '{}': {}", self.path, e))
+    }
+
+    fn sync_dir(&self) -> std::io::Result<()> {
+        match Path::new(&self.path).parent().and_then(Path::to_str) {
+            Some(dir) if self.sync => sync_dir(dir),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cuts every logged file back to its logged length, writing back its tail if it has one,
+    /// then removes the log. The log stays in place if a file cannot be restored, so the next
+    /// start tries again.
+    pub fn rollback(self, base_path: &str) -> Result<(), String> {
+        for logged in &self.files {
+            let path = format!("{}/{}", base_path, logged.name);
+            let mut file = match OpenOptions::new().write(true).open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to open '{}': {}", path, e)),
+            };
+            match &logged.tail {
+                Some(tail) => file
+                    .set_len(logged.length - tail.len() as u64)
+                    .and_then(|_| file.seek(SeekFrom::End(0)))
+                    .and_then(|_| file.write_all(tail.as_bytes()))
+                    .and_then(|_| file.sync_all())
+                    .map_err(|e| format!("Failed to restore '{}': {}", path, e))?,
+                None if file.metadata().map_err(|e| e.to_string())?.len() > logged.length => {
+                    file.set_len(logged.length).and_then(|_| file.sync_all()).map_err(|e| format!("Failed to truncate '{}': {}", path, e))?
+                }
+                None => {}
+            }
+        }
+        self.commit()
+    }
+}
+
+fn log_path(base_path: &str, table_name: &str) -> String {
+    format!("{}/{}.wal", base_path, table_name)
+}
+
+/// Takes the exclusive lock on `<table>.lock`, creating the file if needed; `None` when another
+/// writer holds it. The file is never removed, as a writer may be waiting on the old one.
+fn lock_table(base_path: &str, table_name: &str) -> Result<Option<File>, String> {
+    let path = format!("{}/{}.lock", base_path, table_name);
+    let file = OpenOptions::new().write(true).create(true).truncate(false).open(&path).map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(format!("Failed to lock '{}': {}", path, e)),
+    }
+}
+
+/// Takes the table lock for a job that rewrites the table's files instead of appending to them,
+/// such as rebuilding an index; it is held until the returned file is dropped. Fails if a writer
+/// holds the lock or the table has an unfinished write, which must be rolled back first.
+pub fn lock_for_rewrite(base_path: &str, table_name: &str) -> Result<File, String> {
+    let lock = lock_table(base_path, table_name)?
+        .ok_or_else(|| format!("Table '{}' is being written by another process", table_name))?;
+    let path = log_path(base_path, table_name);
+    if Path::new(&path).exists() {
+        return Err(format!("Table '{}' has an unfinished write ({})", table_name, path));
+    }
+    Ok(lock)
+}
+
+/// Rolls back every write left unfinished in `base_path`, returning the tables they were to.
+/// Run at startup, before anything reads the tables. A log whose table lock is held belongs to
+/// a writer still running in another process and is left alone.
+pub fn recover(base_path: &str) -> Result<Vec<String>, String> {
+    let mut recovered = Vec::new();
+    for entry in fs::read_dir(base_path).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("wal") {
+            continue;
+        }
+        let Some(table_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let Some(lock) = lock_table(base_path, table_name)? else {
+            continue;
+        };
+        // The writer may have finished between listing the directory and taking the lock.
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+        let mut log: WriteAheadLog = match serde_json::from_str(&text) {
+            Ok(log) => log,
+            // The log is written before any data, so one cut short guards no appended bytes.
+            Err(_) => {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+                continue;
+            }
+        };
+        log.path = path.to_string_lossy().into_owned();
+        log.lock = Some(lock);
+        let table_name = log.table_name.clone();
+        log.rollback(base_path)?;
+        recovered.push(table_name);
+    }
+    Ok(recovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test, removed first in case an earlier run left it behind.
+    fn test_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn logged(name: &str, length: usize) -> LoggedFile {
+        LoggedFile { name: name.to_string(), length: length as u64, tail: None }
+    }
+
+    fn append(path: &str, bytes: &str) {
+        OpenOptions::new().append(true).open(path).unwrap().write_all(bytes.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn rollback_restores_the_logged_lengths() {
+        let base_path = test_dir("wal-rollback");
+        let (data, index) = (format!("{}/t_a.data", base_path), format!("{}/t_a.idx", base_path));
+        fs::write(&data, "abcd").unwrap();
+        fs::write(&index, "e0\n").unwrap();
+
+        let mut log = WriteAheadLog::begin(&base_path, "t", vec![logged("t_a.data", 4), logged("t_a.idx", 3)], false).unwrap();
+        append(&data, "efgh");
+        append(&index, "p1\n");
+        log.advance(&base_path, &[("t_a.idx", "p1\n")]).unwrap();
+
+        // The provisional entry is replaced by a final one, and more rows follow.
+        fs::write(&index, "e0\ne1\n").unwrap();
+        append(&data, "ijkl");
+        append(&index, "p2\n");
+        log.rollback(&base_path).unwrap();
+
+        assert_eq!(fs::read_to_string(&data).unwrap(), "abcdefgh");
+        assert_eq!(fs::read_to_string(&index).unwrap(), "e0\np1\n");
+        assert!(!Path::new(&log_path(&base_path, "t")).exists());
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[test]
+    fn recover_leaves_a_live_writer_alone() {
+        let base_path = test_dir("wal-recover");
+        let data = format!("{}/t_a.data", base_path);
+        fs::write(&data, "abcd").unwrap();
+
+        let log = WriteAheadLog::begin(&base_path, "t", vec![logged("t_a.data", 4)], false).unwrap();
+        append(&data, "efgh");
+        assert!(WriteAheadLog::begin(&base_path, "t", Vec::new(), false).unwrap_err().contains("another process"));
+        assert!(recover(&base_path).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(&data).unwrap(), "abcdefgh");
+
+        // Dropping the log without committing is a writer that died: its lock goes with it.
+        drop(log);
+        assert_eq!(recover(&base_path).unwrap(), ["t"]);
+        assert_eq!(fs::read_to_string(&data).unwrap(), "abcd");
+        assert!(!Path::new(&log_path(&base_path, "t")).exists());
+        fs::remove_dir_all(&base_path).unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+use std::{fmt, fs::{File, OpenOptions}, io::{self, BufWriter, Seek, SeekFrom, Write}};
+
+use super::{
+    chunk::CHUNK_ROWS,
+    column::{Column, ColumnStore, MinMaxIndex},
+    config::Durability,
+    format::{data_header, index_header, read_data_header},
+    stats::{HyperLogLog, TableStats},
+    table::TableSchema,
+    timestamp,
+    wal::{LoggedFile, WriteAheadLog},
+};
+use crate::utils::crc32c::crc32c_append;
+
+/// Appends rows to a table with every column's `.data` and `.idx` file kept open and buffered.
+///
+/// Index entries cover whole `CHUNK_ROWS` chunks: min and max are folded in as rows arrive and
+/// an entry is written when a chunk fills up (or the writer finishes), so each append costs
+/// O(1) no matter how large the table already is. When the table has statistics from
+/// `analyze`, they are kept current too.
+///
+/// The rows appended between `open` and `finish` are one atomic write, guarded by a
+/// [`WriteAheadLog`]: a writer dropped without finishing, by an error or a panic, cuts every file
+/// back to where it began, as does the next start after a crash.
+///
+/// The store's [`Durability`] decides when the files are fsynced: never, before the log is
+/// removed, or after every row, each committed on its own by moving the log past it.
+pub struct TableWriter {
+    base_path: String,
+    durability: Durability,
+    columns: Vec<ColumnWriter>,
+    row_count: usize,
+    rows_written: usize,
+    stats: Option<TableStats>,
+    /// `None` once the write has finished or been rolled back.
+    log: Option<WriteAheadLog>,
+}
+
+struct ColumnWriter {
+    column: Column,
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    /// Name of the `.idx` file, as logged.
+    index_name: String,
+    /// Bytes of the `.idx` file up to the end of its last final entry, including buffered writes.
+    index_len: u64,
+    /// The pending entry as last written to the `.idx` file, to be replaced by the next.
+    provisional: Option<String>,
+    /// The `.nulls` file of a nullable column.
+    nulls: Option<BufWriter<File>>,
+    /// Bytes of values in the `.data` file, past its header, including buffered writes.
+    offset: u64,
+    /// Offset, first row, min and max and CRC32C of the rows since the last index entry; min
+    /// and max leave out nulls and are `None` while every row is null.
+    pending: Option<(u64, u64, Bounds, u32)>,
+    /// Distinct count sketch from the table's statistics, if it has any.
+    sketch: Option<HyperLogLog>,
+}
+
+/// Min and max of the values an index entry covers, `None` while every one is null.
+pub type Bounds = Option<(Value, Value)>;
+
+/// A value converted to its column's type, ready to be written.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    /// Microseconds since the Unix epoch, UTC.
+    Timestamp(i64),
+}
+
+impl Value {
+    /// The schema type name of the value, as used in `Column::data_type`.
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "string",
+            Value::Timestamp(_) => "timestamp",
+        }
+    }
+
+    /// The bytes of the value in a `.data` file: little endian numbers, a byte per bool, and a
+    /// u32 length ahead of a string.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Int(v) => v.to_le_bytes().to_vec(),
+            Value::Float(v) => v.to_le_bytes().to_vec(),
+            Value::Bool(v) => vec![*v as u8],
+            Value::Timestamp(v) => v.to_le_bytes().to_vec(),
+            Value::Str(v) => [&(v.len() as u32).to_le_bytes(), v.as_bytes()].concat(),
+        }
+    }
+
+    /// What is written to the `.data` file for a null, keeping rows aligned across columns.
+    fn placeholder(data_type: &str) -> Value {
+        match data_type {
+            "int" => Value::Int(0),
+            "float" => Value::Float(0.0),
+            "bool" => Value::Bool(false),
+            "timestamp" => Value::Timestamp(0),
+            _ => Value::Str(String::new()),
+        }
+    }
+
+    /// `bounds` widened to take in `self`.
+    pub fn widen(&self, bounds: Bounds) -> (Value, Value) {
+        match bounds {
+            Some((min, max)) => (if *self < min { self.clone() } else { min }, if *self > max { self.clone() } else { max }),
+            None => (self.clone(), self.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{}", v),
+            Value::Timestamp(v) => write!(f, "{}", timestamp::format(*v)),
+        }
+    }
+}
+
+/// Converts the text form of a value to `data_type`.
+pub fn parse_value(data_type: &str, raw: &str) -> Result<Value, String> {
+    match data_type {
+        "int" => raw
+            .trim()
+            .parse::<i32>()
+            .map(Value::Int)
+            .map_err(|_| format!("'{}' is not a valid int", raw)),
+        "float" => raw
+            .trim()
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| format!("'{}' is not a valid float", raw)),
+        "bool" => match raw.trim().to_lowercase().as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' is not a valid bool", raw)),
+        },
+        "timestamp" => timestamp::parse(raw).map(Value::Timestamp),
+        "string" => Ok(Value::Str(raw.to_string())),
+        other => Err(format!("Unsupported data type '{}'", other)),
+    }
+}
+
+/// Converts the text form of a value of `column`; an empty field of a nullable column is null.
+pub fn parse_field(column: &Column, raw: &str) -> Result<Option<Value>, String> {
+    if column.nullable && raw.is_empty() {
+        return Ok(None);
+    }
+    parse_value(&column.data_type, raw).map(Some)
+}
+
+/// Why a row was rejected. Rows are checked whole before any value is written, so a rejected
+/// row leaves the table as it was.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowError {
+    /// The row has `got` values for a table of `expected` columns.
+    Arity { expected: usize, got: usize },
+    /// Every value that does not fit its column, in schema order.
+    Fields(Vec<FieldError>),
+    /// The row was valid but writing it failed.
+    Write(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub column: String,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowError::Arity { expected, got } => write!(f, "expected {} values, got {}", expected, got),
+            RowError::Fields(fields) => {
+                let fields: Vec<String> = fields.iter().map(|e| format!("column '{}': {}", e.column, e.message)).collect();
+                write!(f, "{}", fields.join("; "))
+            }
+            RowError::Write(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<RowError> for String {
+    fn from(error: RowError) -> String {
+        error.to_string()
+    }
+}
+
+/// Converts one row given as text, one value per column in schema order, reporting every value
+/// that does not fit rather than only the first.
+pub fn parse_row<'a>(columns: impl IntoIterator<Item = &'a Column>, values: &[&str]) -> Result<Vec<Option<Value>>, RowError> {
+    let columns: Vec<&Column> = columns.into_iter().collect();
+    if values.len() != columns.len() {
+        return Err(RowError::Arity { expected: columns.len(), got: values.len() });
+    }
+
+    let mut parsed = Vec::with_capacity(values.len());
+    let mut errors = Vec::new();
+    for (column, raw) in columns.into_iter().zip(values) {
+        match parse_field(column, raw) {
+            Ok(value) => parsed.push(value),
+            Err(message) => errors.push(FieldError { column: column.name.clone(), message }),
+        }
+    }
+    if errors.is_empty() { Ok(parsed) } else { Err(RowError::Fields(errors)) }
+}
+
+/// Checks converted values against their columns: nulls only in nullable columns and every
+/// other value of its column's type.
+fn check_row<'a>(columns: impl IntoIterator<Item = &'a Column>, values: &[Option<Value>]) -> Result<(), RowError> {
+    let columns: Vec<&Column> = columns.into_iter().collect();
+    if values.len() != columns.len() {
+        return Err(RowError::Arity { expected: columns.len(), got: values.len() });
+    }
+
+    let errors: Vec<FieldError> = columns
+        .into_iter()
+        .zip(values)
+        .filter_map(|(column, value)| {
+            let message = match value {
+                None if !column.nullable => "the column is not nullable".to_string(),
+                Some(value) if value.data_type() != column.data_type => {
+                    format!("the column holds {} values, got {}", column.data_type, value.data_type())
+                }
+                _ => return None,
+            };
+            Some(FieldError { column: column.name.clone(), message })
+        })
+        .collect();
+    if errors.is_empty() { Ok(()) } else { Err(RowError::Fields(errors)) }
+}
+
+impl TableWriter {
+    pub fn open(store: &ColumnStore, table: &TableSchema) -> Result<Self, String> {
+        let row_count = store.row_count(table);
+        let stats = TableStats::load(&store.base_path, &table.table_name);
+        let mut columns = Vec::new();
+        let mut logged = Vec::new();
+
+        for column in &table.columns {
+            // Opens a file for appending and logs its length before the write.
+            let mut open = |extension: &str| -> Result<(File, String, String, u64), String> {
+                let name = format!("{}_{}.{}", table.table_name, column.name, extension);
+                let path = format!("{}/{}", store.base_path, name);
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .read(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+                let length = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+                logged.push(LoggedFile { name: name.clone(), length, tail: None });
+                Ok((file, name, path, length))
+            };
+
+            // New files get a header, buffered so it only reaches disk once the log is written,
+            // unless the table is from before headers existed and its files have none.
+            let headers = table.format_version > 0;
+            let (mut data_file, _, data_path, data_len) = open("data")?;
+            let offset = match data_len {
+                0 => 0,
+                _ => data_len - read_data_header(&mut data_file, &data_path, &column.data_type, table.format_version)?,
+            };
+            let mut data = BufWriter::new(data_file);
+            if data_len == 0 && headers {
+                data.write_all(&data_header(&column.data_type)).map_err(|e| e.to_string())?;
+            }
+            let (index_file, index_name, _, mut index_len) = open("idx")?;
+            let mut index = BufWriter::new(index_file);
+            if index_len == 0 && headers {
+                let header = index_header(&column.data_type);
+                index.write_all(header.as_bytes()).map_err(|e| e.to_string())?;
+                index_len = header.len() as u64;
+            }
+            let nulls = if column.nullable { Some(BufWriter::new(open("nulls")?.0)) } else { None };
+            columns.push(ColumnWriter {
+                column: column.clone(),
+                data,
+                index,
+                index_name,
+                index_len,
+                provisional: None,
+                nulls,
+                offset,
+                pending: None,
+                sketch: stats.as_ref().and_then(|s| s.column(&column.name)).and_then(|s| HyperLogLog::from_hex(&s.sketch)),
+            });
+        }
+
+        let log = WriteAheadLog::begin(&store.base_path, &table.table_name, logged, store.durability != Durability::None)?;
+        Ok(Self {
+            base_path: store.base_path.clone(),
+            durability: store.durability,
+            columns,
+            row_count,
+            rows_written: 0,
+            stats,
+            log: Some(log),
+        })
+    }
+
+    /// Appends one row given as text, one value per column in schema order.
+    pub fn append(&mut self, values: &[&str]) -> Result<(), RowError> {
+        let parsed = parse_row(self.columns.iter().map(|w| &w.column), values)?;
+        self.append_values(parsed)
+    }
+
+    /// Appends one row of converted values in schema order, `None` for a null.
+    pub fn append_values(&mut self, values: Vec<Option<Value>>) -> Result<(), RowError> {
+        check_row(self.columns.iter().map(|w| &w.column), &values)?;
+
+        let chunk_full = (self.row_count + 1).is_multiple_of(CHUNK_ROWS);
+        for (column, value) in self.columns.iter_mut().zip(values) {
+            column.write(self.row_count, value.as_ref()).map_err(|e| RowError::Write(e.to_string()))?;
+            if chunk_full {
+                column.flush_entry().map_err(|e| RowError::Write(e.to_string()))?;
+            }
+
+            let stats = self.stats.as_mut().and_then(|s| s.columns.iter_mut().find(|c| c.name == column.column.name));
+            match (value, stats) {
+                (Some(value), Some(stats)) => stats.observe(&value.to_string()),
+                (None, Some(stats)) => stats.null_count += 1,
+                _ => {}
+            }
+        }
+
+        self.row_count += 1;
+        self.rows_written += 1;
+        if self.durability == Durability::PerRow {
+            self.checkpoint().map_err(RowError::Write)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the rows appended so far: every file is synced with the pending index entries
+    /// written provisionally, then the log moves past them.
+    fn checkpoint(&mut self) -> Result<(), String> {
+        for column in &mut self.columns {
+            column.write_provisional_entry().and_then(|_| column.flush(true)).map_err(|e| e.to_string())?;
+        }
+        let tails: Vec<(&str, &str)> =
+            self.columns.iter().filter_map(|c| Some((c.index_name.as_str(), c.provisional.as_deref()?))).collect();
+        self.log.as_mut().unwrap().advance(&self.base_path, &tails)
+    }
+
+    /// Writes the index entries of a partly filled chunk, flushes every file, synced unless the
+    /// durability is `none`, and saves the updated statistics. Returns the number of rows appended.
+    pub fn finish(mut self) -> Result<usize, String> {
+        let sync = self.durability != Durability::None;
+        for column in &mut self.columns {
+            column.flush_entry().and_then(|_| column.flush(sync)).map_err(|e| e.to_string())?;
+        }
+        self.log.take().unwrap().commit()?;
+
+        if let Some(mut stats) = self.stats.take() {
+            stats.row_count += self.rows_written as u64;
+            for column in &self.columns {
+                let (Some(sketch), Some(column_stats)) = (&column.sketch, stats.columns.iter_mut().find(|c| c.name == column.column.name)) else {
+                    continue;
+                };
+                column_stats.distinct_count = sketch.estimate();
+                column_stats.sketch = sketch.to_hex();
+            }
+            stats.save(&self.base_path)?;
+        }
+
+        Ok(self.rows_written)
+    }
+}
+
+impl Drop for TableWriter {
+    fn drop(&mut self) {
+        let Some(log) = self.log.take() else {
+            return;
+        };
+        // Buffered bytes are thrown away rather than flushed past the point being restored.
+        for column in self.columns.drain(..) {
+            drop(column.data.into_parts());
+            drop(column.index.into_parts());
+            drop(column.nulls.map(BufWriter::into_parts));
+        }
+        if let Err(e) = log.rollback(&self.base_path) {
+            eprintln!("Failed to roll back an unfinished write: {}", e);
+        }
+    }
+}
+
+impl ColumnWriter {
+    /// Buffers the value of `row`, or a placeholder and a null flag for `None`, and folds what
+    /// was written into the pending index entry so the index always describes the data file.
+    /// The placeholder only goes into the checksum, not the bounds.
+    fn write(&mut self, row: usize, value: Option<&Value>) -> io::Result<()> {
+        if let Some(nulls) = &mut self.nulls {
+            nulls.write_all(&[value.is_none() as u8])?;
+        }
+        match (value, &mut self.sketch) {
+            (Some(Value::Int(v)), Some(sketch)) => sketch.insert_int(*v),
+            (Some(Value::Str(v)), Some(sketch)) => sketch.insert_str(v),
+            (Some(other), Some(sketch)) => sketch.insert_str(&other.to_string()),
+            _ => {}
+        }
+
+        let offset = self.offset;
+        let bytes = value.map_or_else(|| Value::placeholder(&self.column.data_type).to_bytes(), Value::to_bytes);
+        self.data.write_all(&bytes)?;
+        self.offset += bytes.len() as u64;
+
+        let (start, first_row, bounds, crc) = self.pending.take().unwrap_or((offset, row as u64, None, 0));
+        let bounds = match value {
+            Some(value) => Some(value.widen(bounds)),
+            None => bounds,
+        };
+        self.pending = Some((start, first_row, bounds, crc32c_append(crc, &bytes)));
+        Ok(())
+    }
+
+    /// Writes the pending index entry as final, replacing its provisional version.
+    fn flush_entry(&mut self) -> io::Result<()> {
+        self.drop_provisional_entry()?;
+        if let Some(pending) = self.pending.take() {
+            let line = entry_line(pending);
+            self.index.write_all(line.as_bytes())?;
+            self.index_len += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending index entry so the index covers every row so far, to be replaced as
+    /// the entry grows.
+    fn write_provisional_entry(&mut self) -> io::Result<()> {
+        self.drop_provisional_entry()?;
+        if let Some(pending) = self.pending.clone() {
+            let line = entry_line(pending);
+            self.index.write_all(line.as_bytes())?;
+            self.provisional = Some(line);
+        }
+        Ok(())
+    }
+
+    fn drop_provisional_entry(&mut self) -> io::Result<()> {
+        if self.provisional.take().is_some() {
+            self.index.flush()?;
+            self.index.get_ref().set_len(self.index_len)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes every file, and with `sync` fsyncs it too.
+    fn flush(&mut self, sync: bool) -> io::Result<()> {
+        for file in [Some(&mut self.data), Some(&mut self.index), self.nulls.as_mut()].into_iter().flatten() {
+            file.flush()?;
+            if sync {
+                file.get_ref().sync_data()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `.idx` line of a pending entry: its offset, min, max, checksum and first row.
+fn entry_line((chunk_offset, row, bounds, crc): (u64, u64, Bounds, u32)) -> String {
+    let (min_value, max_value) = bounds.map(|(min, max)| (min.to_string(), max.to_string())).unzip();
+    let entry = MinMaxIndex { chunk_offset, min_value, max_value, checksum: Some(crc), row: Some(row) };
+    serde_json::to_string(&entry).unwrap() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> (ColumnStore, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("cdbe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (ColumnStore::new(dir.to_str().unwrap()), dir)
+    }
+
+    #[test]
+    fn batches_append_rows_and_one_index_entry_per_chunk() {
+        let (store, dir) = store("writer-batches");
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string"].map(String::from).to_vec());
+        let names: Vec<String> = (0..1500).map(|i| format!("n{}", i)).collect();
+        let ids: Vec<String> = (0..1500).map(|i| i.to_string()).collect();
+        let rows: Vec<Vec<&str>> = (0..1500).map(|i| vec![ids[i].as_str(), names[i].as_str()]).collect();
+
+        assert_eq!(store.insert_rows(&table, &rows[..1000]).unwrap(), 1000);
+        assert_eq!(store.insert_rows(&table, &rows[1000..]).unwrap(), 500);
+        assert_eq!(store.row_count(&table), 1500);
+
+        // The first batch ends mid-chunk, so the second continues that chunk with a new entry.
+        let index = store.read_index(&table, "id").unwrap();
+        let rows_and_bounds: Vec<_> = index.iter().map(|e| (e.row, e.min_value.as_deref(), e.max_value.as_deref())).collect();
+        assert_eq!(rows_and_bounds, [(Some(0), Some("0"), Some("999")), (Some(1000), Some("1000"), Some("1023")), (Some(1024), Some("1024"), Some("1499"))]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn string_tables_count_rows_from_the_last_index_entry() {
+        let (store, dir) = store("writer-strings");
+        let table = TableSchema::new("t".to_string(), ["name:string"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["a"], vec!["bb"], vec!["ccc"]]).unwrap();
+        store.insert_rows(&table, &[vec!["d"]]).unwrap();
+        assert_eq!(store.row_count(&table), 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejected_rows_leave_the_table_unchanged() {
+        let (store, dir) = store("writer-rejected");
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string"].map(String::from).to_vec());
+        let mut writer = TableWriter::open(&store, &table).unwrap();
+        assert!(writer.append(&["1"]).is_err());
+        assert!(writer.append(&["x", "a"]).is_err());
+        writer.append(&["2", "b"]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 1);
+        assert_eq!(store.row_count(&table), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_row_reports_every_bad_field() {
+        let table = TableSchema::new("t".to_string(), ["id:int", "score:float", "note:string?"].map(String::from).to_vec());
+        assert_eq!(parse_row(&table.columns, &["1"]).unwrap_err().to_string(), "expected 3 values, got 1");
+
+        let error = parse_row(&table.columns, &["x", "y", ""]).unwrap_err();
+        match &error {
+            RowError::Fields(fields) => assert_eq!(fields.iter().map(|e| e.column.as_str()).collect::<Vec<_>>(), ["id", "score"]),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(error.to_string().starts_with("column 'id': "));
+        assert_eq!(parse_row(&table.columns, &["1", "2.5", ""]).unwrap(), [Some(Value::Int(1)), Some(Value::Float(2.5)), None]);
+    }
+
+    #[test]
+    fn a_bad_row_in_a_batch_writes_nothing() {
+        let (store, dir) = store("writer-validate");
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1", "a"]]).unwrap();
+
+        let error = store.insert_rows(&table, &[vec!["2", "b"], vec!["x", "c"], vec!["4"]]).unwrap_err();
+        assert_eq!(error.lines().map(|l| &l[..5]).collect::<Vec<_>>(), ["row 2", "row 3"]);
+        assert_eq!(store.row_count(&table), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appends_keep_analyzed_statistics_current() {
+        let (store, dir) = store("writer-stats");
+        let table = TableSchema::new("t".to_string(), ["id:int"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1"], vec!["2"]]).unwrap();
+        store.analyze(&table).unwrap();
+        store.insert_rows(&table, &[vec!["7"]]).unwrap();
+
+        let stats = TableStats::load(&store.base_path, "t").unwrap();
+        assert_eq!(stats.row_count, 3);
+        assert_eq!(stats.column("id").unwrap().max_value.as_deref(), Some("7"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn index_bounds_leave_out_nulls() {
+        let (store, dir) = store("writer-nulls");
+        let table = TableSchema::new("t".to_string(), ["a:int?"].map(String::from).to_vec());
+        let mut writer = TableWriter::open(&store, &table).unwrap();
+        writer.append_values(vec![None]).unwrap();
+        writer.append_values(vec![Some(Value::Int(-4))]).unwrap();
+        writer.append_values(vec![None]).unwrap();
+        assert!(writer.append_values(vec![Some(Value::Str("x".to_string()))]).is_err());
+        writer.finish().unwrap();
+        store.insert_rows(&table, &[vec![""]]).unwrap();
+
+        let bounds: Vec<_> = store.read_index(&table, "a").unwrap().into_iter().map(|e| (e.min_value, e.max_value)).collect();
+        assert_eq!(bounds, [(Some("-4".to_string()), Some("-4".to_string())), (None, None)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn per_row_durability_keeps_the_rows_of_a_write_cut_short() {
+        let (mut store, dir) = store("writer-per-row");
+        let table = TableSchema::new("t".to_string(), ["id:int", "name:string?"].map(String::from).to_vec());
+        store.insert_rows(&table, &[vec!["1", "a"]]).unwrap();
+
+        // Dropping an unfinished writer rolls back to the last committed row.
+        for (durability, rows) in [(Durability::PerBatch, 1), (Durability::PerRow, 3)] {
+            store.durability = durability;
+            let mut writer = TableWriter::open(&store, &table).unwrap();
+            writer.append(&["2", ""]).unwrap();
+            writer.append(&["3", "c"]).unwrap();
+            drop(writer);
+            assert_eq!(store.row_count(&table), rows);
+        }
+
+        // The provisional index entry of the last committed row is kept as it was.
+        let report = store.verify(&table);
+        assert!(report.problems.is_empty(), "{}", report);
+        let index = store.read_index(&table, "id").unwrap();
+        let bounds: Vec<_> = index.iter().map(|e| (e.min_value.as_deref(), e.max_value.as_deref())).collect();
+        assert_eq!(bounds, [(Some("1"), Some("1")), (Some("2"), Some("3"))]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use super::{chunk::CHUNK_ROWS, column::MinMaxIndex, writer::{parse_value, Value}};
+use crate::utils::simd::SimdOp;
+
+/// Min/max of an int column per read chunk, folded from the `.idx` entries covering its rows.
+///
+/// A chunk only gets bounds when every one of its rows is covered by a parseable index entry,
+/// so a missing or partial index can make the map less useful but never wrong.
+#[derive(Debug, Clone)]
+pub struct ZoneMap {
+    chunks: Vec<Option<(i32, i32)>>,
+}
+
+impl ZoneMap {
+    pub fn from_index(entries: &[MinMaxIndex], row_count: usize) -> Self {
+        let chunk_count = row_count.div_ceil(CHUNK_ROWS);
+        let mut bounds: Vec<Option<(i32, i32)>> = vec![None; chunk_count];
+        let mut covered = vec![0usize; chunk_count];
+        let mut invalid = vec![false; chunk_count];
+
+        let starts: Vec<usize> = entries.iter().map(|e| e.chunk_offset as usize / 4).collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let start = starts[i].min(row_count);
+            let end = starts.get(i + 1).copied().unwrap_or(row_count).clamp(start, row_count);
+            if start == end {
+                continue;
+            }
+
+            // An entry over nothing but nulls covers its rows without widening the bounds, as
+            // no filter matches a null.
+            let parsed = match (&entry.min_value, &entry.max_value) {
+                (Some(min), Some(max)) => min.parse::<i32>().ok().zip(max.parse::<i32>().ok()).map(Some),
+                (None, None) => Some(None),
+                _ => None,
+            };
+            for chunk in start / CHUNK_ROWS..=(end - 1) / CHUNK_ROWS {
+                let overlap = end.min((chunk + 1) * CHUNK_ROWS) - start.max(chunk * CHUNK_ROWS);
+                covered[chunk] += overlap;
+                match parsed {
+                    Some(None) => {}
+                    Some(Some((min, max))) => {
+                        bounds[chunk] = Some(match bounds[chunk] {
+                            Some((lo, hi)) => (lo.min(min), hi.max(max)),
+                            None => (min, max),
+                        });
+                    }
+                    None => invalid[chunk] = true,
+                }
+            }
+        }
+
+        for chunk in 0..chunk_count {
+            let chunk_len = CHUNK_ROWS.min(row_count - chunk * CHUNK_ROWS);
+            if invalid[chunk] || covered[chunk] != chunk_len {
+                bounds[chunk] = None;
+            }
+        }
+
+        Self { chunks: bounds }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn bounds(&self, chunk: usize) -> Option<(i32, i32)> {
+        self.chunks.get(chunk).copied().flatten()
+    }
+
+    /// Whether `value op threshold` can hold for some row of `chunk`.
+    pub fn may_match(&self, chunk: usize, op: SimdOp, threshold: i32) -> bool {
+        let Some((min, max)) = self.bounds(chunk) else {
+            return true;
+        };
+        match op {
+            SimdOp::Eq => min <= threshold && threshold <= max,
+            SimdOp::Ne => !(min == threshold && max == threshold),
+            SimdOp::Lt => min < threshold,
+            SimdOp::Le => min <= threshold,
+            SimdOp::Gt => max > threshold,
+            SimdOp::Ge => max >= threshold,
+        }
+    }
+
+    /// Whether some row of `chunk` can fall in `low..=high`.
+    pub fn may_overlap(&self, chunk: usize, low: i32, high: i32) -> bool {
+        match self.bounds(chunk) {
+            Some((min, max)) => max >= low && min <= high,
+            None => true,
+        }
+    }
+}
+
+/// Min and max of each chunk of a column of any type, folded from the `.idx` entries over its
+/// bytes. `chunk_offsets` holds where each chunk starts in the `.data` file, plus where the
+/// last one ends.
+///
+/// As with [`ZoneMap`], a chunk only gets bounds when all of its bytes are covered by parseable
+/// entries. An entry straddling a chunk edge can widen the chunk's bounds but never narrow them.
+pub fn chunk_bounds(entries: &[MinMaxIndex], chunk_offsets: &[u64], data_type: &str) -> Vec<Option<(Value, Value)>> {
+    let file_end = chunk_offsets.last().copied().unwrap_or(0);
+    let entry_end = |i: usize| entries.get(i + 1).map_or(file_end, |next| next.chunk_offset);
+    let mut first = 0;
+
+    chunk_offsets
+        .windows(2)
+        .map(|chunk| {
+            let (start, end) = (chunk[0], chunk[1]);
+            while first < entries.len() && entry_end(first) <= start {
+                first += 1;
+            }
+
+            let mut bounds: Option<(Value, Value)> = None;
+            let mut covered = start;
+            for (i, entry) in entries.iter().enumerate().skip(first) {
+                if entry.chunk_offset >= end {
+                    break;
+                }
+                if entry.chunk_offset > covered {
+                    return None;
+                }
+                let min = parse_value(data_type, entry.min_value.as_deref()?).ok()?;
+                let max = parse_value(data_type, entry.max_value.as_deref()?).ok()?;
+                bounds = Some(match bounds {
+                    Some((lo, hi)) => (if min < lo { min } else { lo }, if max > hi { max } else { hi }),
+                    None => (min, max),
+                });
+                covered = covered.max(entry_end(i));
+            }
+            bounds.filter(|_| covered >= end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(row: usize, min: &str, max: &str) -> MinMaxIndex {
+        MinMaxIndex { chunk_offset: row as u64 * 4, min_value: Some(min.to_string()), max_value: Some(max.to_string()), checksum: None, row: Some(row as u64) }
+    }
+
+    #[test]
+    fn bounds_fold_the_entries_of_each_chunk() {
+        let entries = [entry(0, "5", "9"), entry(1000, "-3", "4"), entry(1100, "20", "30"), entry(2048, "7", "7")];
+        let zone_map = ZoneMap::from_index(&entries, 2100);
+        assert_eq!(zone_map.chunk_count(), 3);
+        assert_eq!(zone_map.bounds(0), Some((-3, 9)));
+        assert_eq!(zone_map.bounds(1), Some((-3, 30)));
+        assert_eq!(zone_map.bounds(2), Some((7, 7)));
+
+        assert!(!zone_map.may_match(2, SimdOp::Ne, 7));
+        assert!(!zone_map.may_match(0, SimdOp::Gt, 9));
+        assert!(zone_map.may_match(0, SimdOp::Ge, 9));
+        assert!(!zone_map.may_overlap(1, 31, 40));
+    }
+
+    #[test]
+    fn partly_covered_or_unparsable_chunks_have_no_bounds() {
+        // The first entry starts after row 0, and the second chunk has an entry that is not an int.
+        let entries = [entry(10, "1", "2"), entry(1024, "x", "2"), entry(1500, "1", "2")];
+        let zone_map = ZoneMap::from_index(&entries, 2048);
+        assert_eq!(zone_map.bounds(0), None);
+        assert_eq!(zone_map.bounds(1), None);
+        assert!(zone_map.may_match(1, SimdOp::Eq, 1000));
+    }
+
+    #[test]
+    fn entries_of_only_nulls_cover_rows_without_bounds() {
+        let nulls = MinMaxIndex { chunk_offset: 500 * 4, min_value: None, max_value: None, checksum: None, row: Some(500) };
+        let zone_map = ZoneMap::from_index(&[entry(0, "1", "9"), nulls], 1024);
+        assert_eq!(zone_map.bounds(0), Some((1, 9)));
+        assert!(!zone_map.may_match(0, SimdOp::Gt, 9));
+    }
+}
@@ -0,0 +1,185 @@
+use std::simd::u64x4;
+
+/// A dense selection vector with one bit per row, lowest row in the lowest bit.
+///
+/// Bits past `len` in the last word are always kept zero so that counts and
+/// complements never report rows that do not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    pub fn full(len: usize) -> Self {
+        let mut bitmap = Self {
+            words: vec![u64::MAX; len.div_ceil(64)],
+            len,
+        };
+        bitmap.clear_tail();
+        bitmap
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        (self.words[idx / 64] >> (idx % 64)) & 1 != 0
+    }
+
+    pub fn set(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    /// ORs `bits` into the bitmap starting at row `idx`; `idx` must be aligned so the
+    /// bits do not cross a word boundary, as is the case for 4 and 8 lane movemasks.
+    pub fn set_bits(&mut self, idx: usize, bits: u64) {
+        self.words[idx / 64] |= bits << (idx % 64);
+    }
+
+    /// ORs whole words into the bitmap starting at `row`, which must be a multiple of 64.
+    pub fn or_words_at(&mut self, row: usize, words: &[u64]) {
+        let start = row / 64;
+        for (dst, src) in self.words[start..start + words.len()].iter_mut().zip(words) {
+            *dst |= src;
+        }
+    }
+
+    /// Whether any row in `start..end` is set; both bounds must be multiples of 64 or `end` the bitmap length.
+    pub fn any_in(&self, start: usize, end: usize) -> bool {
+        let end = end.min(self.len);
+        start < end && self.words[start / 64..end.div_ceil(64)].iter().any(|&w| w != 0)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn any(&self) -> bool {
+        self.words.iter().any(|&w| w != 0)
+    }
+
+    /// Iterates the set rows in ascending order.
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_idx, &word)| {
+            let mut remaining = word;
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+                let bit = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+                Some(word_idx * 64 + bit)
+            })
+        })
+    }
+
+    pub fn and(&self, other: &Bitmap) -> Bitmap {
+        self.zip_with(other, |a, b| a & b, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &Bitmap) -> Bitmap {
+        self.zip_with(other, |a, b| a | b, |a, b| a | b)
+    }
+
+    /// Rows set in `self` but not in `other`.
+    pub fn and_not(&self, other: &Bitmap) -> Bitmap {
+        self.zip_with(other, |a, b| a & !b, |a, b| a & !b)
+    }
+
+    /// Gathers the given rows into a new bitmap, in the given order.
+    pub fn take(&self, rows: &[usize]) -> Bitmap {
+        let mut taken = Bitmap::new(rows.len());
+        for (i, _) in rows.iter().enumerate().filter(|(_, &row)| self.get(row)) {
+            taken.set(i);
+        }
+        taken
+    }
+
+    pub fn not(&self) -> Bitmap {
+        Bitmap::full(self.len).and_not(self)
+    }
+
+    fn zip_with(
+        &self,
+        other: &Bitmap,
+        lanes: impl Fn(u64x4, u64x4) -> u64x4,
+        scalar: impl Fn(u64, u64) -> u64,
+    ) -> Bitmap {
+        assert_eq!(self.len, other.len, "bitmaps must cover the same rows");
+
+        let mut words = vec![0u64; self.words.len()];
+        let mut i = 0;
+
+        while i + 4 <= words.len() {
+            let a = u64x4::from_slice(&self.words[i..]);
+            let b = u64x4::from_slice(&other.words[i..]);
+            lanes(a, b).copy_to_slice(&mut words[i..]);
+            i += 4;
+        }
+
+        for ((out, &a), &b) in words[i..].iter_mut().zip(&self.words[i..]).zip(&other.words[i..]) {
+            *out = scalar(a, b);
+        }
+
+        Bitmap { words, len: self.len }
+    }
+
+    fn clear_tail(&mut self) {
+        if !self.len.is_multiple_of(64) {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1u64 << (self.len % 64)) - 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bitmap of `len` rows with the rows `keep` selects set.
+    fn bitmap(len: usize, keep: impl Fn(usize) -> bool) -> Bitmap {
+        let mut bitmap = Bitmap::new(len);
+        (0..len).filter(|&row| keep(row)).for_each(|row| bitmap.set(row));
+        bitmap
+    }
+
+    #[test]
+    fn and_or_not_match_per_row_logic() {
+        // Lengths around word boundaries, and past the four word SIMD lanes into the scalar tail.
+        for len in [0, 1, 63, 64, 65, 255, 256, 300] {
+            let a = bitmap(len, |row| row % 2 == 0);
+            let b = bitmap(len, |row| row % 3 == 0);
+            assert_eq!(a.and(&b), bitmap(len, |row| row % 6 == 0), "len {}", len);
+            assert_eq!(a.or(&b), bitmap(len, |row| row % 2 == 0 || row % 3 == 0), "len {}", len);
+            assert_eq!(a.and_not(&b), bitmap(len, |row| row % 2 == 0 && row % 3 != 0), "len {}", len);
+            assert_eq!(a.not(), bitmap(len, |row| row % 2 != 0), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn not_keeps_the_tail_clear() {
+        let not = Bitmap::new(70).not();
+        assert_eq!(not.count_ones(), 70);
+        assert_eq!(not.words()[1], (1 << 6) - 1);
+        assert_eq!(not.not(), Bitmap::new(70));
+        assert_eq!(Bitmap::full(70), not);
+    }
+}
@@ -0,0 +1,87 @@
+use std::arch::x86_64::*;
+
+/// Reflected CRC-32C (Castagnoli) polynomial.
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32C of `bytes`, using the SSE4.2 `crc32` instruction when the CPU has it.
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    crc32c_append(0, bytes)
+}
+
+/// Extends `crc`, the CRC-32C of some bytes, with the bytes that follow them.
+pub fn crc32c_append(crc: u32, bytes: &[u8]) -> u32 {
+    if std::is_x86_feature_detected!("sse4.2") {
+        // SAFETY: the CPU supports SSE4.2, checked just above.
+        unsafe { crc32c_sse42(crc, bytes) }
+    } else {
+        crc32c_table(crc, bytes)
+    }
+}
+
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc as u64;
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(word.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for &byte in words.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    !crc
+}
+
+fn crc32c_table(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &byte| TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The check value of "123456789" and the test patterns of RFC 3720, appendix B.4.
+    #[test]
+    fn known_vectors() {
+        let ascending: Vec<u8> = (0..32).collect();
+        let descending: Vec<u8> = (0..32).rev().collect();
+        let cases: [(&[u8], u32); 6] = [
+            (b"", 0),
+            (b"123456789", 0xE306_9283),
+            (&[0; 32], 0x8A91_36AA),
+            (&[0xFF; 32], 0x62A8_AB43),
+            (&ascending, 0x46DD_794E),
+            (&descending, 0x113F_DB5C),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(crc32c(bytes), expected, "{:?}", bytes);
+            assert_eq!(crc32c_table(0, bytes), expected, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn append_matches_a_single_pass() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        for split in [0, 1, 7, 8, 9, 500, 1000] {
+            let (head, tail) = bytes.split_at(split);
+            assert_eq!(crc32c_append(crc32c(head), tail), crc32c(&bytes));
+            assert_eq!(crc32c_table(crc32c_table(0, head), tail), crc32c(&bytes));
+        }
+    }
+}
@@ -1 +1,3 @@
-pub mod simd;
\ No newline at end of file
+pub mod bitmap;
+pub mod crc32c;
+pub mod simd;
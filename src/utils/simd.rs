@@ -1,7 +1,9 @@
-use std::{arch::x86_64::*, collections::HashSet};
+use std::arch::x86_64::*;
 
 use clap::ValueEnum;
 
+use super::bitmap::Bitmap;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
 pub enum SimdOp {
     Ne,
@@ -18,8 +20,86 @@ pub enum LogicalOp {
     Or,
 }
 
-pub fn filter_simd_32(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Vec<usize> {
-    let mut results: Vec<usize> = Vec::new();
+/// Which instruction set the filter kernels run on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SimdBackend {
+    Sse,
+    Avx2,
+}
+
+impl SimdBackend {
+    pub fn detect() -> Self {
+        if std::is_x86_feature_detected!("avx2") {
+            SimdBackend::Avx2
+        } else {
+            SimdBackend::Sse
+        }
+    }
+}
+
+impl std::fmt::Display for SimdBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimdBackend::Sse => write!(f, "SSE (4 x i32)"),
+            SimdBackend::Avx2 => write!(f, "AVX2 (8 x i32)"),
+        }
+    }
+}
+
+pub fn compare_scalar(value: i32, threshold_value: i32, op: SimdOp) -> bool {
+    match op {
+        SimdOp::Eq => value == threshold_value,
+        SimdOp::Ne => value != threshold_value,
+        SimdOp::Lt => value < threshold_value,
+        SimdOp::Gt => value > threshold_value,
+        SimdOp::Le => value <= threshold_value,
+        SimdOp::Ge => value >= threshold_value,
+    }
+}
+
+unsafe fn compare_sse(chunk: __m128i, cmp: __m128i, op: SimdOp) -> __m128i {
+    match op {
+        SimdOp::Eq => _mm_cmpeq_epi32(chunk, cmp),
+        SimdOp::Ne => {
+            let eq = _mm_cmpeq_epi32(chunk, cmp);
+            _mm_cmpeq_epi32(_mm_setzero_si128(), eq)
+        }
+        SimdOp::Lt => _mm_cmplt_epi32(chunk, cmp),
+        SimdOp::Gt => _mm_cmpgt_epi32(chunk, cmp),
+        SimdOp::Le => {
+            let gt = _mm_cmpgt_epi32(chunk, cmp);
+            _mm_cmpeq_epi32(_mm_setzero_si128(), gt)
+        }
+        SimdOp::Ge => {
+            let lt = _mm_cmpgt_epi32(cmp, chunk);
+            _mm_cmpeq_epi32(_mm_setzero_si128(), lt)
+        }
+    }
+}
+
+unsafe fn compare_avx(chunk: __m256i, cmp: __m256i, op: SimdOp) -> __m256i {
+    match op {
+        SimdOp::Eq => _mm256_cmpeq_epi32(chunk, cmp),
+        SimdOp::Ne => {
+            let eq = _mm256_cmpeq_epi32(chunk, cmp);
+            _mm256_cmpeq_epi32(_mm256_setzero_si256(), eq)
+        }
+        SimdOp::Lt => _mm256_cmpgt_epi32(cmp, chunk),
+        SimdOp::Gt => _mm256_cmpgt_epi32(chunk, cmp),
+        SimdOp::Le => {
+            let gt = _mm256_cmpgt_epi32(chunk, cmp);
+            _mm256_cmpeq_epi32(_mm256_setzero_si256(), gt)
+        }
+        SimdOp::Ge => {
+            let lt = _mm256_cmpgt_epi32(cmp, chunk);
+            _mm256_cmpeq_epi32(_mm256_setzero_si256(), lt)
+        }
+    }
+}
+
+/// Compares 4 lanes at a time and writes each movemask straight into the result bitmap.
+pub fn filter_simd_32_bitmap(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Bitmap {
+    let mut bitmap = Bitmap::new(buffer.len());
     let mut i = 0;
     let len = buffer.len();
 
@@ -29,133 +109,353 @@ pub fn filter_simd_32(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Vec<u
         while i + 4 <= len {
             let ptr = buffer[i..].as_ptr() as *const __m128i;
             let chunk = _mm_loadu_si128(ptr);
-            let mask = match op {
-                SimdOp::Eq => _mm_cmpeq_epi32(chunk, cmp),
-                SimdOp::Ne => {
-                    let eq = _mm_cmpeq_epi32(chunk, cmp);
-                    _mm_cmpeq_epi32(_mm_setzero_si128(), eq)
-                }
-                SimdOp::Lt => _mm_cmplt_epi32(chunk, cmp),
-                SimdOp::Gt => _mm_cmpgt_epi32(chunk, cmp),
-                SimdOp::Le => {
-                    let gt = _mm_cmpgt_epi32(chunk, cmp);
-                    _mm_cmpeq_epi32(_mm_setzero_si128(), gt)
-                }
-                SimdOp::Ge => {
-                    let lt = _mm_cmpgt_epi32(cmp, chunk);
-                    _mm_cmpeq_epi32(_mm_setzero_si128(), lt)
-                }
-            };
-
-            let mask_bits = _mm_movemask_ps(std::mem::transmute(mask));
-
-            for j in 0..4 {
-                if (mask_bits & (1 << j)) != 0 {
-                    results.push(i + j);
-                }
-            }
+            let mask = compare_sse(chunk, cmp, op);
+            let mask_bits = _mm_movemask_ps(_mm_castsi128_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 4;
+        }
+    }
+
+    for (j, &value) in buffer.iter().enumerate().skip(i) {
+        if compare_scalar(value, threshold_value, op) {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+/// AVX2 variant of [`filter_simd_32_bitmap`], 8 lanes per compare.
+pub fn filter_simd_32_avx_bitmap(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Bitmap {
+    let mut bitmap = Bitmap::new(buffer.len());
+    let mut i = 0;
+    let len = buffer.len();
+
+    unsafe {
+        let cmp = _mm256_set1_epi32(threshold_value);
+
+        while i + 8 <= len {
+            let ptr = buffer[i..].as_ptr() as *const __m256i;
+            let chunk = _mm256_loadu_si256(ptr);
+            let mask = compare_avx(chunk, cmp, op);
+            let mask_bits = _mm256_movemask_ps(_mm256_castsi256_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 8;
+        }
+    }
+
+    for (j, &value) in buffer.iter().enumerate().skip(i) {
+        if compare_scalar(value, threshold_value, op) {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+/// Compares two columns of the same table lane by lane, `left[i] op right[i]`. Both must have
+/// the same number of rows.
+pub fn filter_columns_simd_32_bitmap(left: &[i32], right: &[i32], op: SimdOp) -> Bitmap {
+    assert_eq!(left.len(), right.len(), "columns must have the same rows");
+    let len = left.len();
+    let mut bitmap = Bitmap::new(len);
+    let mut i = 0;
+
+    unsafe {
+        while i + 4 <= len {
+            let lhs = _mm_loadu_si128(left[i..].as_ptr() as *const __m128i);
+            let rhs = _mm_loadu_si128(right[i..].as_ptr() as *const __m128i);
+            let mask = compare_sse(lhs, rhs, op);
+            let mask_bits = _mm_movemask_ps(_mm_castsi128_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
 
             i += 4;
         }
     }
 
     for j in i..len {
-        let matched = match op {
-            SimdOp::Eq => buffer[j] == threshold_value,
-            SimdOp::Ne => buffer[j] != threshold_value,
-            SimdOp::Lt => buffer[j] < threshold_value,
-            SimdOp::Gt => buffer[j] > threshold_value,
-            SimdOp::Le => buffer[j] <= threshold_value,
-            SimdOp::Ge => buffer[j] >= threshold_value,
-        };
-        if matched {
-            results.push(j);
+        if compare_scalar(left[j], right[j], op) {
+            bitmap.set(j);
         }
     }
 
-    results
+    bitmap
 }
 
-pub fn filter_with_logical_op(
-    buf1: &[i32],
-    op1: SimdOp,
-    val1: i32,
-    buf2: &[i32],
-    op2: SimdOp,
-    val2: i32,
-    logic_op: LogicalOp,
-) -> Vec<usize> {
-    let mask1 = filter_simd_32(buf1, val1, op1);
-    let mask2 = filter_simd_32(buf2, val2, op2);
-    combine_masks(&mask1, &mask2, logic_op)
+/// AVX2 variant of [`filter_columns_simd_32_bitmap`].
+pub fn filter_columns_simd_32_avx_bitmap(left: &[i32], right: &[i32], op: SimdOp) -> Bitmap {
+    assert_eq!(left.len(), right.len(), "columns must have the same rows");
+    let len = left.len();
+    let mut bitmap = Bitmap::new(len);
+    let mut i = 0;
+
+    unsafe {
+        while i + 8 <= len {
+            let lhs = _mm256_loadu_si256(left[i..].as_ptr() as *const __m256i);
+            let rhs = _mm256_loadu_si256(right[i..].as_ptr() as *const __m256i);
+            let mask = compare_avx(lhs, rhs, op);
+            let mask_bits = _mm256_movemask_ps(_mm256_castsi256_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 8;
+        }
+    }
+
+    for j in i..len {
+        if compare_scalar(left[j], right[j], op) {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
 }
 
-pub fn filter_simd_32_avx(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Vec<usize> {
-    let mut results: Vec<usize> = Vec::new();
+/// `low <= value <= high` in a single pass: both bounds are checked against the same loaded lanes.
+pub fn filter_between_simd_32_bitmap(buffer: &[i32], low: i32, high: i32) -> Bitmap {
+    let mut bitmap = Bitmap::new(buffer.len());
     let mut i = 0;
     let len = buffer.len();
 
     unsafe {
-        let cmp = _mm256_set1_epi32(threshold_value);
+        let lo = _mm_set1_epi32(low);
+        let hi = _mm_set1_epi32(high);
+
+        while i + 4 <= len {
+            let ptr = buffer[i..].as_ptr() as *const __m128i;
+            let chunk = _mm_loadu_si128(ptr);
+            let outside = _mm_or_si128(_mm_cmplt_epi32(chunk, lo), _mm_cmpgt_epi32(chunk, hi));
+            let mask_bits = !_mm_movemask_ps(_mm_castsi128_ps(outside)) & 0xF;
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 4;
+        }
+    }
+
+    for (j, &value) in buffer.iter().enumerate().skip(i) {
+        if low <= value && value <= high {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+/// AVX2 variant of [`filter_between_simd_32_bitmap`].
+pub fn filter_between_simd_32_avx_bitmap(buffer: &[i32], low: i32, high: i32) -> Bitmap {
+    let mut bitmap = Bitmap::new(buffer.len());
+    let mut i = 0;
+    let len = buffer.len();
+
+    unsafe {
+        let lo = _mm256_set1_epi32(low);
+        let hi = _mm256_set1_epi32(high);
 
         while i + 8 <= len {
             let ptr = buffer[i..].as_ptr() as *const __m256i;
             let chunk = _mm256_loadu_si256(ptr);
-            let mask = match op {
-                SimdOp::Eq => _mm256_cmpeq_epi32(chunk, cmp),
-                SimdOp::Ne => {
-                    let eq = _mm256_cmpeq_epi32(chunk, cmp);
-                    _mm256_cmpeq_epi32(_mm256_setzero_si256(), eq)
-                }
-                SimdOp::Lt => _mm256_cmpgt_epi32(cmp, chunk),
-                SimdOp::Gt => _mm256_cmpgt_epi32(chunk, cmp),
-                SimdOp::Le => {
-                    let gt = _mm256_cmpgt_epi32(chunk, cmp);
-                    _mm256_cmpeq_epi32(_mm256_setzero_si256(), gt)
-                }
-                SimdOp::Ge => {
-                    let lt = _mm256_cmpgt_epi32(cmp, chunk);
-                    _mm256_cmpeq_epi32(_mm256_setzero_si256(), lt)
-                }
-            };
-
-            let mask_array: [i32; 8] = std::mem::transmute(mask);
-
-            for j in 0..8 {
-                if mask_array[j] != 0 {
-                    results.push(i + j);
-                }
+            let outside = _mm256_or_si256(_mm256_cmpgt_epi32(lo, chunk), _mm256_cmpgt_epi32(chunk, hi));
+            let mask_bits = !_mm256_movemask_ps(_mm256_castsi256_ps(outside)) & 0xFF;
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 8;
+        }
+    }
+
+    for (j, &value) in buffer.iter().enumerate().skip(i) {
+        if low <= value && value <= high {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+/// Up to this many literals an IN-list is checked by broadcasting and comparing every literal;
+/// longer lists are range-filtered first and the surviving lanes probed in a sorted set.
+pub const IN_LIST_BROADCAST_LIMIT: usize = 16;
+
+/// `value IN (values)` in a single pass over the column.
+pub fn filter_in_simd_32_bitmap(buffer: &[i32], values: &[i32]) -> Bitmap {
+    if values.len() > IN_LIST_BROADCAST_LIMIT {
+        return probe_in_list(filter_between_simd_32_bitmap, buffer, values);
+    }
+
+    let mut bitmap = Bitmap::new(buffer.len());
+    let mut i = 0;
+    let len = buffer.len();
+
+    unsafe {
+        let literals: Vec<__m128i> = values.iter().map(|&v| _mm_set1_epi32(v)).collect();
+
+        while i + 4 <= len {
+            let ptr = buffer[i..].as_ptr() as *const __m128i;
+            let chunk = _mm_loadu_si128(ptr);
+            let mut mask = _mm_setzero_si128();
+            for &literal in &literals {
+                mask = _mm_or_si128(mask, _mm_cmpeq_epi32(chunk, literal));
             }
+            let mask_bits = _mm_movemask_ps(_mm_castsi128_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
+
+            i += 4;
+        }
+    }
+
+    for (j, value) in buffer.iter().enumerate().skip(i) {
+        if values.contains(value) {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+/// AVX2 variant of [`filter_in_simd_32_bitmap`].
+pub fn filter_in_simd_32_avx_bitmap(buffer: &[i32], values: &[i32]) -> Bitmap {
+    if values.len() > IN_LIST_BROADCAST_LIMIT {
+        return probe_in_list(filter_between_simd_32_avx_bitmap, buffer, values);
+    }
+
+    let mut bitmap = Bitmap::new(buffer.len());
+    let mut i = 0;
+    let len = buffer.len();
+
+    unsafe {
+        let literals: Vec<__m256i> = values.iter().map(|&v| _mm256_set1_epi32(v)).collect();
+
+        while i + 8 <= len {
+            let ptr = buffer[i..].as_ptr() as *const __m256i;
+            let chunk = _mm256_loadu_si256(ptr);
+            let mut mask = _mm256_setzero_si256();
+            for &literal in &literals {
+                mask = _mm256_or_si256(mask, _mm256_cmpeq_epi32(chunk, literal));
+            }
+            let mask_bits = _mm256_movemask_ps(_mm256_castsi256_ps(mask));
+
+            bitmap.set_bits(i, mask_bits as u64);
 
             i += 8;
         }
     }
 
-    for j in i..len {
-        let matched = match op {
-            SimdOp::Eq => buffer[j] == threshold_value,
-            SimdOp::Ne => buffer[j] != threshold_value,
-            SimdOp::Lt => buffer[j] < threshold_value,
-            SimdOp::Gt => buffer[j] > threshold_value,
-            SimdOp::Le => buffer[j] <= threshold_value,
-            SimdOp::Ge => buffer[j] >= threshold_value,
-        };
-        if matched {
-            results.push(j);
+    for (j, value) in buffer.iter().enumerate().skip(i) {
+        if values.contains(value) {
+            bitmap.set(j);
         }
     }
 
-    results
+    bitmap
 }
 
-pub fn combine_masks(mask1: &[usize], mask2: &[usize], op: LogicalOp) -> Vec<usize> {
-    use LogicalOp::*;
+fn probe_in_list(between: fn(&[i32], i32, i32) -> Bitmap, buffer: &[i32], values: &[i32]) -> Bitmap {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
 
-    let set1: HashSet<_> = mask1.iter().cloned().collect();
-    let set2: HashSet<_> = mask2.iter().cloned().collect();
+    let in_range = between(buffer, sorted[0], sorted[sorted.len() - 1]);
+    let mut bitmap = Bitmap::new(buffer.len());
+    for j in in_range.iter_ones() {
+        if sorted.binary_search(&buffer[j]).is_ok() {
+            bitmap.set(j);
+        }
+    }
+
+    bitmap
+}
+
+pub fn filter_simd_32(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Vec<usize> {
+    filter_simd_32_bitmap(buffer, threshold_value, op).iter_ones().collect()
+}
 
+pub fn filter_simd_32_avx(buffer: &[i32], threshold_value: i32, op: SimdOp) -> Vec<usize> {
+    filter_simd_32_avx_bitmap(buffer, threshold_value, op).iter_ones().collect()
+}
+
+pub fn combine_bitmaps(mask1: &Bitmap, mask2: &Bitmap, op: LogicalOp) -> Bitmap {
     match op {
-        And => set1.intersection(&set2).cloned().collect(),
-        Or => set1.union(&set2).cloned().collect(),
+        LogicalOp::And => mask1.and(mask2),
+        LogicalOp::Or => mask1.or(mask2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values around the bounds used below, with a length that leaves a scalar tail for both widths.
+    fn values() -> Vec<i32> {
+        (0..203).map(|i| (i * 37 % 101) - 50).chain([i32::MIN, i32::MAX]).collect()
+    }
+
+    fn expected(buffer: &[i32], keep: impl Fn(i32) -> bool) -> Bitmap {
+        let mut bitmap = Bitmap::new(buffer.len());
+        (0..buffer.len()).filter(|&row| keep(buffer[row])).for_each(|row| bitmap.set(row));
+        bitmap
+    }
+
+    fn avx() -> bool {
+        std::is_x86_feature_detected!("avx2")
+    }
+
+    #[test]
+    fn between_includes_both_bounds() {
+        let buffer = values();
+        for (low, high) in [(-10, 10), (5, 5), (10, -10), (i32::MIN, 0), (0, i32::MAX)] {
+            let want = expected(&buffer, |v| low <= v && v <= high);
+            assert_eq!(filter_between_simd_32_bitmap(&buffer, low, high), want, "{}..={}", low, high);
+            if avx() {
+                assert_eq!(filter_between_simd_32_avx_bitmap(&buffer, low, high), want, "{}..={}", low, high);
+            }
+        }
+    }
+
+    #[test]
+    fn in_matches_short_and_long_lists() {
+        let buffer = values();
+        // Short lists are broadcast, long ones probed; duplicates and unsorted literals are fine in both.
+        let short = vec![7, -3, 7, i32::MIN];
+        let long: Vec<i32> = (0..=IN_LIST_BROADCAST_LIMIT as i32).map(|i| 40 - i * 5).collect();
+        for list in [Vec::new(), short, long] {
+            let want = expected(&buffer, |v| list.contains(&v));
+            assert_eq!(filter_in_simd_32_bitmap(&buffer, &list), want, "{:?}", list);
+            if avx() {
+                assert_eq!(filter_in_simd_32_avx_bitmap(&buffer, &list), want, "{:?}", list);
+            }
+        }
+    }
+
+    #[test]
+    fn column_compare_matches_per_row() {
+        let left = values();
+        let right: Vec<i32> = left.iter().rev().copied().collect();
+        for op in [SimdOp::Eq, SimdOp::Ne, SimdOp::Lt, SimdOp::Gt, SimdOp::Le, SimdOp::Ge] {
+            let mut want = Bitmap::new(left.len());
+            (0..left.len()).filter(|&row| compare_scalar(left[row], right[row], op)).for_each(|row| want.set(row));
+            assert_eq!(filter_columns_simd_32_bitmap(&left, &right, op), want, "{:?}", op);
+            if avx() {
+                assert_eq!(filter_columns_simd_32_avx_bitmap(&left, &right, op), want, "{:?}", op);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "columns must have the same rows")]
+    fn column_compare_refuses_mismatched_lengths() {
+        filter_columns_simd_32_bitmap(&[1, 2, 3, 4, 5], &[1, 2, 3, 4], SimdOp::Eq);
+    }
+
+    #[test]
+    #[should_panic(expected = "columns must have the same rows")]
+    fn avx_column_compare_refuses_mismatched_lengths() {
+        filter_columns_simd_32_avx_bitmap(&[1; 9], &[1; 8], SimdOp::Eq);
     }
 }
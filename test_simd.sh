@@ -29,6 +29,20 @@ cargo run -- insert users 10 "Judy" 21
 cargo run -- insert users 11 "Karl" 20
 cargo run -- insert users 12 "Laura" 22
 
+echo
+echo "📥 Bulk inserting rows into 'events' table..."
+cargo run -- create-table events id:int kind:string
+cargo run -- insert-rows events "1,click" "2,view" "3,click" "4,purchase"
+printf 'kind,id\n"add, to cart",5\nview,6\n' > /tmp/events.csv
+cargo run -- import-csv events /tmp/events.csv
+cargo run -- export events /tmp/events_out.csv --where "id >= 2"
+cargo run -- export events /tmp/events_out.ndjson --format ndjson
+printf '{"id": 1, "kind": "click", "at": "2024-05-01T12:00:00Z", "ms": 12.5}\n{"id": 2, "kind": "view", "at": "2024-05-01 12:00:03", "ms": null}\n' > /tmp/page_events.ndjson
+cargo run -- import-json page_events /tmp/page_events.ndjson
+cargo run -- export page_events /tmp/page_events.arrow --format arrow
+cargo run -- import-arrow page_events_copy /tmp/page_events.arrow
+cargo run -- export page_events /tmp/page_events.parquet --format parquet
+
 echo
 echo "🔍 Scanning 'age' column..."
 cargo run -- scan users age
@@ -42,6 +56,10 @@ cargo run -- filter-simd-lt users age 30
 cargo run -- filter-simd-lt-eq users age 30
 cargo run -- filter-simd-gt-eq users age 30
 cargo run -- filter-simd-logical users age gt 25 age lt 54 or
+cargo run -- filter-simd-expr users "(age gt 25 and id < 5) or not age >= 21"
+cargo run -- filter-simd-between users age 20 30
+cargo run -- filter-simd-in users age 20 54 65
+cargo run -- filter-simd-columns users id lt age
 
 echo
 echo "⚡ Testing AVX2 SIMD filters..."
@@ -52,6 +70,28 @@ cargo run -- filter-simd-lt-avx users age 20
 cargo run -- filter-simd-gt-eq-avx users age 20
 cargo run -- filter-simd-lt-eq-avx users age 20
 
+echo
+echo "🧮 Selecting computed expressions..."
+cargo run -- select users "name, age + 1 as next_age, upper(name) as shout" --where "id <= 3"
+
+echo
+echo "🔎 Running SQL queries..."
+cargo run -- analyze users
+cargo run -- query "select age / 10 * 10 as decade, count(*) as people, avg(age) from users where age >= 20 group by age / 10 * 10 order by decade"
+cargo run -- explain "select name, age from users where age > 30 and name <> 'Eve' order by age desc limit 3"
+cargo run -- explain --analyze "select count(*) from users where age between 20 and 30"
+
+echo
+echo "🩺 Verifying table files..."
+cargo run -- upgrade
+cargo run -- reindex users
+cargo run -- verify --repair
+
+echo
+echo "💾 Configuring durability..."
+cargo run -- config --durability per-batch
+cargo run -- config
+
 echo
 echo "📋 Listing all tables..."
 cargo run -- list-tables